@@ -44,6 +44,13 @@ fn main() {
                                  .help("Path to RPM package file")))
         .subcommand(SubCommand::with_name("validate")
                         .about("Validates the package signature")
+                        .arg(Arg::with_name("key")
+                                 .short("k")
+                                 .long("key")
+                                 .takes_value(true)
+                                 .multiple(true)
+                                 .number_of_values(1)
+                                 .help("Path to an OpenPGP public key file"))
                         .arg(Arg::with_name("rpm")
                                  .required(true)
                                  .help("Path to RPM package file")))
@@ -93,9 +100,15 @@ fn main() {
             println!("Vendor: {}", vendor);
         }
         println!("License: {}", package.header().license_name());
+        // Fall back to the raw PAYLOADFLAGS string if it can't be parsed.
+        #[allow(deprecated)]
+        let payload_flags = match package.header().payload_flags() {
+            Ok(flags) => flags.to_string(),
+            Err(_) => package.header().payload_compression_level().to_string(),
+        };
         println!("Compression: {}/{}",
                  package.header().payload_compressor(),
-                 package.header().payload_compression_level());
+                 payload_flags);
         if let Some(time) = package.header().build_time() {
             println!("Built at: {}",
                      timestamp_datetime(time).format("%Y-%m-%d %H:%M:%S"));
@@ -158,7 +171,33 @@ fn main() {
         let path = submatches.value_of("rpm").unwrap();
         let file = fs::File::open(path).unwrap();
         let mut package = rpmpkg::Package::read(file).unwrap();
-        package.validate().unwrap();
+        let mut keyring = rpmpkg::Keyring::new();
+        if let Some(key_paths) = submatches.values_of("key") {
+            for key_path in key_paths {
+                keyring.add_keys(fs::File::open(key_path).unwrap()).unwrap();
+            }
+        }
+        let verifications = package.validate_with_keyring(&keyring).unwrap();
+        for verification in verifications.iter() {
+            if !verification.is_supported() {
+                println!("{:?} signature: unsupported", verification.region());
+                continue;
+            }
+            let key_id = match verification.key_id() {
+                Some(key_id) => format!("{:016X}", key_id),
+                None => "unknown key".to_string(),
+            };
+            println!("{:?} signature ({:?}/{:?}) from key {}: {}",
+                     verification.region(),
+                     verification.algorithm().unwrap(),
+                     verification.digest_algorithm().unwrap(),
+                     key_id,
+                     if verification.is_verified() {
+                         "ok"
+                     } else {
+                         "key not found"
+                     });
+        }
         println!("Package signature ok.");
     }
 }
@@ -1,17 +1,25 @@
-use bzip2::Compression as BzCompression;
-use bzip2::write::BzEncoder;
 use cpio;
-use flate2::Compression as GzCompression;
-use flate2::write::GzEncoder;
+use internal::changelog::ChangeLogEntry;
+use internal::codec::{PayloadCodecRegistry, PayloadEncoder, PayloadFlags};
 use internal::convert::{self, Sha1Writer};
+use internal::dependency::Dependency;
+use internal::digest::{DigestAlgorithm, DigestWriter};
 use internal::header::{FileInfo, HeaderSection};
 use internal::lead::{LeadSection, PackageType};
+use internal::pgp::SigningKey;
+use internal::scriptlet::{Scriptlet, ScriptletType};
 use internal::signature::SignatureSection;
+use internal::stripped::StrippedWriter;
+use internal::trigger::{FileTrigger, Trigger};
 use md5;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::time::SystemTime;
 use std::u32;
-use xz2::write::XzEncoder;
+
+// ========================================================================= //
+
+/// The hash algorithm used for the payload digest in new packages.
+const PAYLOAD_DIGEST_ALGORITHM: DigestAlgorithm = DigestAlgorithm::Sha256;
 
 // ========================================================================= //
 
@@ -19,6 +27,10 @@ use xz2::write::XzEncoder;
 pub struct PackageBuilder {
     package_type: PackageType,
     header: HeaderSection,
+    signing_key: Option<SigningKey>,
+    zstd_window_log: Option<u32>,
+    payload_threads: Option<u32>,
+    payload_codecs: PayloadCodecRegistry,
 }
 
 impl PackageBuilder {
@@ -27,6 +39,10 @@ impl PackageBuilder {
         PackageBuilder {
             package_type,
             header: HeaderSection::new(),
+            signing_key: None,
+            zstd_window_log: None,
+            payload_threads: None,
+            payload_codecs: PayloadCodecRegistry::new(),
         }
     }
 
@@ -45,6 +61,12 @@ impl PackageBuilder {
         self.header.set_release_string(release.into());
     }
 
+    /// Sets the epoch of this package.  Packages with a higher epoch are
+    /// always considered newer, regardless of their version strings.
+    pub fn set_epoch(&mut self, epoch: u32) {
+        self.header.set_epoch(epoch);
+    }
+
     /// Sets the one-line description of this package.
     pub fn set_summary<S: Into<String>>(&mut self, summary: S) {
         self.header.set_summary(summary.into());
@@ -76,12 +98,145 @@ impl PackageBuilder {
     }
 
     /// Sets the compressor and compression level used to compress the Archive
-    /// section of the package.  Currently supported values for `compressor`
-    /// are `"gzip"`, `"bzip2"`, and `"xz"`.  The `level` value should be
-    /// between 1 (fastest) and 9 (best) inclusive.
+    /// section of the package.  The built-in values for `compressor` are
+    /// `"gzip"`, `"bzip2"`, `"xz"`, `"lzma"`, `"zstd"`, `"lz4"`, and
+    /// `"identity"` (no compression); others can be added with
+    /// `set_payload_codecs`.  The `level` value should be between 1
+    /// (fastest) and 9 (best) inclusive, or up to 22 for zstd.
     pub fn set_payload_compression(&mut self, compression: &str, level: u32) {
         self.header.set_payload_compressor(compression.to_string());
-        self.header.set_payload_compression_level(format!("{}", level));
+        self.header.set_payload_flags(&PayloadFlags::new(level));
+    }
+
+    /// Sets the compression settings (such as the level and thread count)
+    /// for the compressor chosen with `set_payload_compression`.  These are
+    /// stored in the package header as given, so for example
+    /// `PayloadFlags::new(2).with_threads(16).with_io("xzdio")` is recorded
+    /// as `"w2T16.xzdio"`.
+    pub fn set_payload_flags(&mut self, flags: PayloadFlags) {
+        self.header.set_payload_flags(&flags);
+    }
+
+    /// Enables long-window mode for zstd payload compression (like `zstd
+    /// --long`), with a window of 2^`window_log` bytes (between 2^10 and
+    /// 2^31).  This can improve compression of large packages, but needs as
+    /// much memory to decompress.  It has no effect on other compressors.
+    pub fn set_payload_zstd_long_window(&mut self, window_log: u32) {
+        self.zstd_window_log = Some(window_log);
+    }
+
+    /// Compresses the Archive section on the given number of threads (zero
+    /// means one thread per CPU).  This is supported for xz, zstd, and gzip
+    /// (which is compressed in independent blocks, like pigz); other
+    /// compressors ignore it.  The thread count is recorded in the package
+    /// header (e.g. `"9T4"`), but the compressed data is the same no matter
+    /// how many threads are used.
+    pub fn set_payload_threads(&mut self, threads: u32) {
+        self.payload_threads = Some(threads);
+    }
+
+    /// Sets the codecs available for compressing the Archive section.  By
+    /// default, all of the built-in codecs are available.
+    pub fn set_payload_codecs(&mut self, codecs: PayloadCodecRegistry) {
+        self.payload_codecs = codecs;
+    }
+
+    /// Sets the hash algorithm used for the digests of the files in the
+    /// package.  The default is SHA-256.  Using MD5 avoids a dependency on
+    /// `rpmlib(FileDigests)` (for very old versions of rpm), but MD5 digests
+    /// are rejected by hosts in FIPS mode.
+    pub fn set_file_digest_algorithm(&mut self, algorithm: DigestAlgorithm) {
+        self.header.set_file_digest_algorithm(algorithm);
+    }
+
+    /// Sets the scriptlet of the given type (e.g. a `%post` script),
+    /// replacing any previous scriptlet of that type.
+    pub fn set_scriptlet(&mut self, scriptlet_type: ScriptletType,
+                         scriptlet: Scriptlet) {
+        self.header.set_scriptlet(scriptlet_type, scriptlet);
+    }
+
+    /// Adds an entry to the package changelog.  The entries are kept sorted
+    /// from newest to oldest, regardless of the order they are added in.
+    pub fn add_changelog_entry<S1, S2>(&mut self, timestamp: SystemTime,
+                                       author: S1, description: S2)
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        let entry = ChangeLogEntry::new(timestamp, author, description);
+        self.header.add_changelog_entry(entry);
+    }
+
+    /// Adds a trigger, which runs a scriptlet when other packages are
+    /// installed or uninstalled.  The trigger must have at least one
+    /// condition.
+    pub fn add_trigger(&mut self, trigger: Trigger) {
+        self.header.add_trigger(trigger);
+    }
+
+    /// Adds a file trigger, which runs a scriptlet once for each package
+    /// that installs or uninstalls files under the trigger's path prefixes.
+    /// The trigger must have at least one prefix, and must not be of type
+    /// `PreInstall`.
+    pub fn add_file_trigger(&mut self, trigger: FileTrigger) {
+        self.header.add_file_trigger(trigger);
+    }
+
+    /// Adds a transaction file trigger, which runs a scriptlet once per
+    /// transaction that installs or uninstalls files under the trigger's
+    /// path prefixes.  The trigger must have at least one prefix, and must
+    /// not be of type `PreInstall`.
+    pub fn add_transaction_file_trigger(&mut self, trigger: FileTrigger) {
+        self.header.add_transaction_file_trigger(trigger);
+    }
+
+    /// Adds a capability that this package provides.  (The package always
+    /// provides its own name, so there's no need to add that.)
+    pub fn add_provides(&mut self, dependency: Dependency) {
+        self.header.add_provides(dependency);
+    }
+
+    /// Adds a requirement of this package.  The dependency name may be a
+    /// rich dependency expression, such as `"(foo >= 1.2 if bar)"`.  Any
+    /// `rpmlib()` requirements for features used by the package are added
+    /// automatically.
+    pub fn add_requires(&mut self, dependency: Dependency) {
+        self.header.add_requires(dependency);
+    }
+
+    /// Adds a capability that conflicts with this package.
+    pub fn add_conflicts(&mut self, dependency: Dependency) {
+        self.header.add_conflicts(dependency);
+    }
+
+    /// Adds a capability that this package obsoletes.
+    pub fn add_obsoletes(&mut self, dependency: Dependency) {
+        self.header.add_obsoletes(dependency);
+    }
+
+    /// Adds a capability that this package recommends.  Recommended packages
+    /// are installed along with this one by default, if available.
+    pub fn add_recommends(&mut self, dependency: Dependency) {
+        self.header.add_recommends(dependency);
+    }
+
+    /// Adds a capability that this package suggests.  Suggested packages are
+    /// not installed by default.
+    pub fn add_suggests(&mut self, dependency: Dependency) {
+        self.header.add_suggests(dependency);
+    }
+
+    /// Adds a capability that this package supplements.  This package will be
+    /// installed by default along with the supplemented one.
+    pub fn add_supplements(&mut self, dependency: Dependency) {
+        self.header.add_supplements(dependency);
+    }
+
+    /// Adds a capability that this package enhances.  This is a weaker form
+    /// of `add_supplements`.
+    pub fn add_enhances(&mut self, dependency: Dependency) {
+        self.header.add_enhances(dependency);
     }
 
     /// Adds metadata about a file that will be installed by the package.  The
@@ -101,6 +256,12 @@ impl PackageBuilder {
         self.set_build_time(SystemTime::now());
     }
 
+    /// Sets the OpenPGP key that will be used to sign the package.  If no key
+    /// is set, the package will be left unsigned.
+    pub fn set_signing_key(&mut self, key: SigningKey) {
+        self.signing_key = Some(key);
+    }
+
     /// Locks in the package metadata and returns an `ArchiveBuilder` object
     /// for writing archive files into the package.
     pub fn build<W: Read + Write + Seek>(mut self, mut writer: W)
@@ -113,66 +274,111 @@ impl PackageBuilder {
                                     full_name.as_bytes().to_vec());
         lead.write(&mut writer)?;
         let signature_start = writer.seek(SeekFrom::Current(0))?;
-        let signature = SignatureSection::placeholder();
+        let mut payload_flags = self.header.payload_flags()?;
+        if let Some(window_log) = self.zstd_window_log {
+            if self.header.payload_compressor() == "zstd" {
+                payload_flags = payload_flags.with_long_window(window_log);
+                self.header.set_payload_flags(&payload_flags);
+            }
+        }
+        if let Some(threads) = self.payload_threads {
+            payload_flags = payload_flags.with_threads(threads);
+            self.header.set_payload_flags(&payload_flags);
+        }
+        let payload_encoder = {
+            let compressor = self.header.payload_compressor();
+            let codec = match self.payload_codecs.get(compressor) {
+                Some(codec) => codec,
+                None => {
+                    invalid_input!("Unsupported payload compressor ({:?})",
+                                   compressor);
+                }
+            };
+            if let Some((name, version)) = codec.rpmlib_feature() {
+                self.header.set_payload_rpmlib_feature(name, version);
+            }
+            codec.encoder(&payload_flags)?
+        };
+        // Reserve space for the payload digest, which will be filled in once
+        // the archive is done.
+        let empty_digest = PAYLOAD_DIGEST_ALGORITHM.writer().finish_hex();
+        self.header.set_payload_digest(PAYLOAD_DIGEST_ALGORITHM, empty_digest);
+        self.header.normalize_file_inodes();
+        // Likewise, reserve space for the file digests, which will be filled
+        // in as the file data is written.
+        let file_digest_algorithm = match self.header.file_digest_algorithm() {
+            Some(algorithm) => algorithm,
+            None => invalid_input!("Unsupported file digest algorithm"),
+        };
+        let file_digests: Vec<String> = {
+            let empty_digest = file_digest_algorithm.writer().finish_hex();
+            self.header
+                .files()
+                .map(|file_info| if file_info.is_regular_file() &&
+                         !file_info.is_ghost()
+                     {
+                         empty_digest.clone()
+                     } else {
+                         String::new()
+                     })
+                .collect()
+        };
+        self.header.set_file_digests(file_digests.clone());
+        self.header.done_adding_fields()?;
+        let file_infos: Vec<FileInfo> = self.header.files().collect();
+        // Like rpmbuild, use the stripped cpio format for packages with
+        // 64-bit file sizes, since newc entries can't hold such files.
+        let stripped = self.header.has_stripped_payload();
+        // The Signature section can't grow once the archive has been
+        // written, so decide now whether it needs 64-bit size entries.
+        let header_size = {
+            let mut cursor = Cursor::new(Vec::new());
+            self.header.write(&mut cursor)?;
+            cursor.into_inner().len() as u64
+        };
+        let long_sizes = header_size + max_archive_size(&file_infos) >
+            u32::MAX as u64;
+        let signature_len =
+            self.signing_key.as_ref().map(SigningKey::max_signature_len);
+        let signature = SignatureSection::placeholder(signature_len,
+                                                      long_sizes);
         signature.write(&mut writer)?;
         let header_start = writer.seek(SeekFrom::Current(0))?;
-        self.header.done_adding_fields();
         self.header.write(&mut writer)?;
         let archive_start = writer.seek(SeekFrom::Current(0))?;
-        let file_infos = self.header.files().collect();
-        let compressor = self.header.payload_compressor();
-        let encoder = match compressor {
-            "bzip2" => {
-                let level = self.header.payload_compression_level();
-                let level = match level.parse::<u32>() {
-                    Ok(level) if level >= 1 && level <= 9 => {
-                        // TODO: use specified bzip2 compression level
-                        BzCompression::Default
-                    }
-                    _ => {
-                        invalid_input!("Invalid bzip2 compression level \
-                                        ({:?})",
-                                       level);
-                    }
-                };
-                ArchiveEncoder::Bzip2(BzEncoder::new(writer, level))
-            }
-            "gzip" => {
-                let level = self.header.payload_compression_level();
-                let level = match level.parse::<u32>() {
-                    Ok(level) if level >= 1 && level <= 9 => {
-                        GzCompression::new(level)
-                    }
-                    _ => {
-                        invalid_input!("Invalid gzip compression level ({:?})",
-                                       level);
-                    }
-                };
-                ArchiveEncoder::Gzip(GzEncoder::new(writer, level), 0)
-            }
-            "xz" => {
-                let level = self.header.payload_compression_level();
-                let level = match level.parse::<u32>() {
-                    Ok(level) if level >= 1 && level <= 9 => level,
-                    _ => {
-                        invalid_input!("Invalid xz compression level ({:?})",
-                                       level);
-                    }
-                };
-                ArchiveEncoder::Xz(XzEncoder::new(writer, level))
-            }
-            _ => {
-                invalid_input!("Unsupported payload compressor ({:?})",
-                               compressor);
+        // Within each set of hard links, only the last file in the archive
+        // carries the data.
+        let hard_link_sets = self.header.hard_link_sets();
+        let mut file_links = vec![(1, true); file_infos.len()];
+        for set in hard_link_sets.iter() {
+            for &index in set.iter() {
+                file_links[index] = (set.len() as u32, false);
             }
+            file_links[*set.last().unwrap()].1 = true;
+        }
+        let signing_time = self.header
+            .build_time()
+            .unwrap_or_else(SystemTime::now);
+        let encoder = ArchiveEncoder {
+            writer,
+            encoder: payload_encoder,
+            total_in: 0,
         };
         let archive = ArchiveBuilder {
             encoder: Some(encoder),
             signature_start,
             signature,
+            signing_key: self.signing_key,
+            signing_time: convert::system_time_to_u32(signing_time),
             header_start,
+            header: self.header,
             archive_start,
+            stripped,
             file_infos,
+            file_links,
+            hard_link_sets,
+            file_digest_algorithm,
+            file_digests,
             next_file_index: 0,
         };
         Ok(archive)
@@ -186,45 +392,134 @@ pub struct ArchiveBuilder<W: Read + Write + Seek> {
     encoder: Option<ArchiveEncoder<W>>,
     signature_start: u64,
     signature: SignatureSection,
+    signing_key: Option<SigningKey>,
+    signing_time: u32,
     header_start: u64,
+    header: HeaderSection,
     archive_start: u64,
+    /// True if the archive uses the stripped cpio format.
+    stripped: bool,
     file_infos: Vec<FileInfo>,
+    /// The link count for each file, and whether its archive entry carries
+    /// the file data.
+    file_links: Vec<(u32, bool)>,
+    hard_link_sets: Vec<Vec<usize>>,
+    file_digest_algorithm: DigestAlgorithm,
+    file_digests: Vec<String>,
     next_file_index: usize,
 }
 
 impl<W: Read + Write + Seek> ArchiveBuilder<W> {
     /// Returns a `FileWriter` for the next file within the package archive
     /// that needs data to be written, or `None` if all files are now complete.
+    ///
+    /// Directories, symlinks, and devices have no data to write, so their
+    /// archive entries are written automatically.  Ghost files are not
+    /// included in the archive at all, and so are skipped.  For a set of
+    /// hard links, data is only written for the last file in the set.
     pub fn next_file(&mut self) -> io::Result<Option<FileWriter<W>>> {
+        self.write_special_files()?;
         if self.next_file_index >= self.file_infos.len() {
             return Ok(None);
         }
         let file_info = &self.file_infos[self.next_file_index];
-        let cpio_writer =
-            cpio::newc::Builder::new(file_info.name())
-                .ino(file_info.inode())
-                .mode(file_info.mode().into())
-                .mtime(convert::system_time_to_u32(file_info.modified_time()))
-                .write(self.encoder.as_mut().unwrap(), file_info.size());
+        let digest = &mut self.file_digests[self.next_file_index];
+        let (link_count, _) = self.file_links[self.next_file_index];
+        let entry_writer = EntryWriter::new(self.encoder.as_mut().unwrap(),
+                                            self.stripped,
+                                            self.next_file_index,
+                                            file_info,
+                                            link_count,
+                                            file_info.size())?;
+        let context = if file_info.is_regular_file() {
+            Some(self.file_digest_algorithm.writer())
+        } else {
+            None
+        };
         let file_writer = FileWriter {
-            writer: Some(cpio_writer),
+            writer: Some(entry_writer),
             file_info,
+            context,
+            digest,
         };
         self.next_file_index += 1;
         Ok(Some(file_writer))
     }
 
+    /// Writes archive entries for any files with no data of their own (that
+    /// is, non-regular files, and hard links other than the last in each
+    /// set) up to the next regular file with data, skipping ghost files.
+    fn write_special_files(&mut self) -> io::Result<()> {
+        while self.next_file_index < self.file_infos.len() {
+            let file_info = &self.file_infos[self.next_file_index];
+            if file_info.is_ghost() {
+                self.next_file_index += 1;
+                continue;
+            }
+            let (link_count, has_data) = self.file_links[self.next_file_index];
+            if file_info.is_regular_file() {
+                if has_data {
+                    break;
+                }
+                EntryWriter::new(self.encoder.as_mut().unwrap(),
+                                 self.stripped,
+                                 self.next_file_index,
+                                 file_info,
+                                 link_count,
+                                 0)?
+                    .finish()?;
+                self.next_file_index += 1;
+                continue;
+            }
+            // Only symlinks have data (their target path); directories and
+            // devices have none, whatever size their metadata says.
+            let target = file_info.symlink_target().unwrap_or("");
+            let mut entry_writer =
+                EntryWriter::new(self.encoder.as_mut().unwrap(),
+                                 self.stripped,
+                                 self.next_file_index,
+                                 file_info,
+                                 link_count,
+                                 target.len() as u64)?;
+            entry_writer.write_all(target.as_bytes())?;
+            entry_writer.finish()?;
+            self.next_file_index += 1;
+        }
+        Ok(())
+    }
+
     /// Finishes writing the package, and returns the underlying writer.
     pub fn finish(mut self) -> io::Result<W> { self.do_finish() }
 
     fn do_finish(&mut self) -> io::Result<W> {
+        self.write_special_files()?;
         let mut encoder = self.encoder.take().unwrap();
         cpio::newc::trailer(&mut encoder)?;
         encoder.flush()?;
         let uncompressed_bytes = encoder.total_in();
         let mut writer = encoder.finish()?;
         let total_file_size = writer.seek(SeekFrom::Current(0))?;
-        // TODO: Fill in MD5 digests for individual files in the Header section
+        // All files in a set of hard links share the digest of the last one,
+        // which has the data.
+        for set in self.hard_link_sets.iter() {
+            let digest = self.file_digests[*set.last().unwrap()].clone();
+            for &index in set.iter() {
+                self.file_digests[index] = digest.clone();
+            }
+        }
+        self.header.set_file_digests(self.file_digests.clone());
+        let payload_digest = {
+            writer.seek(SeekFrom::Start(self.archive_start))?;
+            let mut context = PAYLOAD_DIGEST_ALGORITHM.writer();
+            io::copy(&mut writer, &mut context)?;
+            context.finish_hex()
+        };
+        self.header
+            .set_payload_digest(PAYLOAD_DIGEST_ALGORITHM, payload_digest);
+        writer.seek(SeekFrom::Start(self.header_start))?;
+        self.header.write(&mut writer)?;
+        debug_assert_eq!(writer.seek(SeekFrom::Current(0))?,
+                         self.archive_start);
         let header_size = self.archive_start - self.header_start;
         let header_and_archive_size = total_file_size - self.header_start;
         let header_and_archive_md5 = {
@@ -243,10 +538,37 @@ impl<W: Read + Write + Seek> ArchiveBuilder<W> {
                      &mut context)?;
             context.digest()
         };
-        self.signature.set_uncompressed_archive_size(uncompressed_bytes);
-        self.signature.set_header_and_archive_size(header_and_archive_size);
+        let header_sha256 = {
+            writer.seek(SeekFrom::Start(self.header_start))?;
+            let mut context = DigestAlgorithm::Sha256.writer();
+            io::copy(&mut io::Read::by_ref(&mut writer).take(header_size),
+                     &mut context)?;
+            context.finish_hex()
+        };
+        self.signature.set_uncompressed_archive_size(uncompressed_bytes)?;
+        self.signature.set_header_and_archive_size(header_and_archive_size)?;
         self.signature.set_header_and_archive_md5(&header_and_archive_md5);
         self.signature.set_header_sha1(header_sha1);
+        self.signature.set_header_sha256(header_sha256);
+        if let Some(ref key) = self.signing_key {
+            writer.seek(SeekFrom::Start(self.header_start))?;
+            let mut context = key.digest_writer();
+            io::copy(&mut io::Read::by_ref(&mut writer).take(header_size),
+                     &mut context)?;
+            let header_signature = key.sign(context, self.signing_time)?;
+            writer.seek(SeekFrom::Start(self.header_start))?;
+            let mut context = key.digest_writer();
+            io::copy(&mut io::Read::by_ref(&mut writer)
+                         .take(header_and_archive_size),
+                     &mut context)?;
+            let header_and_payload_signature =
+                key.sign(context, self.signing_time)?;
+            self.signature
+                .set_openpgp_signatures(header_signature,
+                                        header_and_payload_signature);
+            self.signature
+                .fit_to_size(self.header_start - self.signature_start)?;
+        }
         writer.seek(SeekFrom::Start(self.signature_start))?;
         self.signature.write(&mut writer)?;
         writer.seek(SeekFrom::Start(total_file_size))?;
@@ -264,58 +586,121 @@ impl<W: Read + Write + Seek> Drop for ArchiveBuilder<W> {
 
 // ========================================================================= //
 
-enum ArchiveEncoder<W: Write> {
-    Bzip2(BzEncoder<W>),
-    Gzip(GzEncoder<W>, u64),
-    Xz(XzEncoder<W>),
+/// Compresses the Archive section of a new package, keeping count of the
+/// uncompressed bytes written.
+struct ArchiveEncoder<W: Write> {
+    writer: W,
+    encoder: Box<dyn PayloadEncoder>,
+    total_in: u64,
 }
 
 impl<W: Write> ArchiveEncoder<W> {
-    fn total_in(&self) -> u64 {
-        match *self {
-            ArchiveEncoder::Bzip2(ref encoder) => encoder.total_in(),
-            ArchiveEncoder::Gzip(_, total_in) => total_in,
-            ArchiveEncoder::Xz(ref encoder) => encoder.total_in(),
+    fn total_in(&self) -> u64 { self.total_in }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.encoder.finish(&mut self.writer)?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ArchiveEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.encode(buf, &mut self.writer)?;
+        self.total_in += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.writer.flush() }
+}
+
+// ========================================================================= //
+
+/// Returns a cpio entry builder for the given file.
+fn cpio_builder(file_info: &FileInfo, link_count: u32)
+                -> cpio::newc::Builder {
+    let mut builder =
+        cpio::newc::Builder::new(file_info.name())
+            .ino(file_info.inode())
+            .nlink(link_count)
+            .mode(file_info.mode().into())
+            .mtime(convert::system_time_to_u32(file_info.modified_time()));
+    if let Some((major, minor)) = file_info.device_numbers() {
+        builder = builder.rdev_major(major.into()).rdev_minor(minor.into());
+    }
+    builder
+}
+
+/// Writes the data for one archive entry, in either cpio format.
+enum EntryWriter<'a, W: 'a + Write> {
+    Newc(cpio::newc::Writer<&'a mut ArchiveEncoder<W>>),
+    Stripped(StrippedWriter<&'a mut ArchiveEncoder<W>>),
+}
+
+impl<'a, W: Write> EntryWriter<'a, W> {
+    /// Starts a new archive entry for the file at `index` in the package
+    /// header, with `size` bytes of data.
+    fn new(encoder: &'a mut ArchiveEncoder<W>, stripped: bool, index: usize,
+           file_info: &FileInfo, link_count: u32, size: u64)
+           -> io::Result<EntryWriter<'a, W>> {
+        if stripped {
+            let writer = StrippedWriter::new(encoder, index, size)?;
+            Ok(EntryWriter::Stripped(writer))
+        } else {
+            // Any file too large for a newc entry forces the stripped format.
+            debug_assert!(size <= u32::MAX as u64);
+            let writer = cpio_builder(file_info, link_count)
+                .write(encoder, size as u32);
+            Ok(EntryWriter::Newc(writer))
         }
     }
 
-    fn finish(self) -> io::Result<W> {
+    fn finish(self) -> io::Result<()> {
         match self {
-            ArchiveEncoder::Bzip2(encoder) => encoder.finish(),
-            ArchiveEncoder::Gzip(encoder, _) => encoder.finish(),
-            ArchiveEncoder::Xz(encoder) => encoder.finish(),
+            EntryWriter::Newc(writer) => writer.finish().map(|_| ()),
+            EntryWriter::Stripped(writer) => writer.finish().map(|_| ()),
         }
     }
 }
 
-impl<W: Write> Write for ArchiveEncoder<W> {
+impl<'a, W: Write> Write for EntryWriter<'a, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match *self {
-            ArchiveEncoder::Bzip2(ref mut encoder) => encoder.write(buf),
-            ArchiveEncoder::Gzip(ref mut encoder, ref mut total_in) => {
-                let bytes_written = encoder.write(buf)?;
-                *total_in += bytes_written as u64;
-                Ok(bytes_written)
-            }
-            ArchiveEncoder::Xz(ref mut encoder) => encoder.write(buf),
+            EntryWriter::Newc(ref mut writer) => writer.write(buf),
+            EntryWriter::Stripped(ref mut writer) => writer.write(buf),
         }
     }
 
     fn flush(&mut self) -> io::Result<()> {
         match *self {
-            ArchiveEncoder::Bzip2(ref mut encoder) => encoder.flush(),
-            ArchiveEncoder::Gzip(ref mut encoder, _) => encoder.flush(),
-            ArchiveEncoder::Xz(ref mut encoder) => encoder.flush(),
+            EntryWriter::Newc(ref mut writer) => writer.flush(),
+            EntryWriter::Stripped(ref mut writer) => writer.flush(),
         }
     }
 }
 
+/// Returns an upper bound on the compressed size of the archive for the
+/// given files, allowing for cpio headers and padding, and for compressors
+/// that slightly expand incompressible data.
+fn max_archive_size(file_infos: &[FileInfo]) -> u64 {
+    // Each cpio entry has a 110-byte header, plus up to 3 bytes of padding
+    // after each of the name and the data.
+    const ENTRY_OVERHEAD: u64 = 128;
+    let mut size = ENTRY_OVERHEAD; // for the trailer
+    for file_info in file_infos.iter().filter(|info| !info.is_ghost()) {
+        size += ENTRY_OVERHEAD + file_info.name().len() as u64 +
+            file_info.size();
+    }
+    size + size / 32 + (1 << 16)
+}
+
 // ========================================================================= //
 
 /// Allows writing data for a single archive file into a new RPM package.
 pub struct FileWriter<'a, W: 'a + Write + Seek> {
-    writer: Option<cpio::newc::Writer<&'a mut ArchiveEncoder<W>>>,
+    writer: Option<EntryWriter<'a, W>>,
     file_info: &'a FileInfo,
+    context: Option<DigestWriter>,
+    digest: &'a mut String,
 }
 
 impl<'a, W: Write + Seek> FileWriter<'a, W> {
@@ -325,7 +710,11 @@ impl<'a, W: Write + Seek> FileWriter<'a, W> {
 
 impl<'a, W: Write + Seek> Write for FileWriter<'a, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.writer.as_mut().unwrap().write(buf)
+        let bytes_written = self.writer.as_mut().unwrap().write(buf)?;
+        if let Some(ref mut context) = self.context {
+            context.write_all(&buf[..bytes_written])?;
+        }
+        Ok(bytes_written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -334,7 +723,12 @@ impl<'a, W: Write + Seek> Write for FileWriter<'a, W> {
 }
 
 impl<'a, W: Write + Seek> Drop for FileWriter<'a, W> {
-    fn drop(&mut self) { let _ = self.writer.take().unwrap().finish(); }
+    fn drop(&mut self) {
+        let _ = self.writer.take().unwrap().finish();
+        if let Some(context) = self.context.take() {
+            *self.digest = context.finish_hex();
+        }
+    }
 }
 
 // ========================================================================= //
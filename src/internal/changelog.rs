@@ -0,0 +1,261 @@
+use internal::convert;
+use std::io;
+use std::time::SystemTime;
+
+// ========================================================================= //
+
+const WEEKDAYS: &[&str] = &["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+const MONTHS: &[&str] = &[
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+    "Nov", "Dec",
+];
+
+/// Changelog dates have no time of day, so (like rpmbuild) we use noon, to
+/// keep the date the same in most time zones.
+const SECONDS_PAST_MIDNIGHT: u32 = 12 * 60 * 60;
+
+// ========================================================================= //
+
+/// An entry in the package changelog.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChangeLogEntry {
+    timestamp: SystemTime,
+    author: String,
+    description: String,
+}
+
+impl ChangeLogEntry {
+    /// Constructs a new changelog entry.
+    pub fn new<S1, S2>(timestamp: SystemTime, author: S1, description: S2)
+                       -> ChangeLogEntry
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        ChangeLogEntry {
+            timestamp,
+            author: author.into(),
+            description: description.into(),
+        }
+    }
+
+    /// Parses the body of a spec file `%changelog` section into a list of
+    /// entries, newest first.  Each entry starts with a line of the form
+    /// `* Wed Jun 05 2019 Author Name <email> - 1.2-3`, followed by the
+    /// lines of its description.  As with rpmbuild, the weekday must match
+    /// the date.
+    pub fn parse_spec_changelog(text: &str)
+                                -> io::Result<Vec<ChangeLogEntry>> {
+        let mut entries: Vec<ChangeLogEntry> = Vec::new();
+        let mut description_lines: Vec<&str> = Vec::new();
+        for line in text.lines() {
+            if entries.is_empty() {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed == "%changelog" {
+                    continue;
+                }
+            }
+            if line.starts_with('*') {
+                if let Some(entry) = entries.last_mut() {
+                    entry.description = join_description(&description_lines);
+                }
+                description_lines.clear();
+                let entry = parse_entry_line(line)?;
+                if let Some(previous) = entries.last() {
+                    if entry.timestamp > previous.timestamp {
+                        invalid_data!("%changelog entries are not in \
+                                       descending chronological order \
+                                       ({:?})",
+                                      line);
+                    }
+                }
+                entries.push(entry);
+            } else if entries.is_empty() {
+                invalid_data!("%changelog entries must start with '*' \
+                               (found {:?})",
+                              line);
+            } else {
+                description_lines.push(line);
+            }
+        }
+        if let Some(entry) = entries.last_mut() {
+            entry.description = join_description(&description_lines);
+        }
+        Ok(entries)
+    }
+
+    /// Returns the timestamp when this change was made.
+    pub fn timestamp(&self) -> SystemTime { self.timestamp }
+
+    /// Returns the name of the author of this change.
+    pub fn author(&self) -> &str { &self.author }
+
+    /// Returns a description of this change.
+    pub fn description(&self) -> &str { &self.description }
+}
+
+fn join_description(lines: &[&str]) -> String {
+    let mut end = lines.len();
+    while end > 0 && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    lines[..end].join("\n")
+}
+
+fn parse_entry_line(line: &str) -> io::Result<ChangeLogEntry> {
+    // The line consists of a date (four fields), followed by the author,
+    // which is everything else (including any version).
+    let mut fields = Vec::new();
+    let mut rest = line[1..].trim_start();
+    for _ in 0..4 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            invalid_data!("Incomplete %changelog entry line: {:?}", line);
+        }
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    let (weekday, month, day, year) = (fields[0], fields[1], fields[2],
+                                       fields[3]);
+    if !WEEKDAYS.contains(&weekday) {
+        invalid_data!("Invalid weekday in %changelog entry line: {:?}", line);
+    }
+    let month = match MONTHS.iter().position(|&name| name == month) {
+        Some(index) => index as u32 + 1,
+        None => {
+            invalid_data!("Invalid month in %changelog entry line: {:?}",
+                          line)
+        }
+    };
+    let year = match year.parse::<u32>() {
+        Ok(year) if year >= 1970 => year,
+        _ => {
+            invalid_data!("Invalid year in %changelog entry line: {:?}", line)
+        }
+    };
+    let day = match day.parse::<u32>() {
+        Ok(day) if day >= 1 && day <= days_in_month(year, month) => day,
+        _ => {
+            invalid_data!("Invalid day in %changelog entry line: {:?}", line)
+        }
+    };
+    let author = rest.trim();
+    if author.is_empty() {
+        invalid_data!("Missing author in %changelog entry line: {:?}", line);
+    }
+    // CHANGELOGTIME is a 32-bit timestamp, which runs out in early 2106.
+    let days = days_since_epoch(year, month, day);
+    let seconds = days * 24 * 60 * 60 + SECONDS_PAST_MIDNIGHT as u64;
+    if seconds > u64::from(u32::MAX) {
+        invalid_data!("Date out of range in %changelog entry line: {:?}",
+                      line);
+    }
+    // 1970-01-01 was a Thursday.
+    if WEEKDAYS[((days + 4) % 7) as usize] != weekday {
+        invalid_data!("Weekday does not match date in %changelog entry \
+                       line: {:?}",
+                      line);
+    }
+    Ok(ChangeLogEntry::new(convert::u32_to_system_time(seconds as u32),
+                           author,
+                           String::new()))
+}
+
+fn is_leap_year(year: u32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn days_since_epoch(year: u32, month: u32, day: u32) -> u64 {
+    let leap_years_before =
+        |year: u64| (year - 1) / 4 - (year - 1) / 100 + (year - 1) / 400;
+    let mut days = 365 * (year as u64 - 1970) +
+        leap_years_before(year as u64) - leap_years_before(1970);
+    for m in 1..month {
+        days += days_in_month(year, m) as u64;
+    }
+    days + day as u64 - 1
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::ChangeLogEntry;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parse_spec_changelog() {
+        let text = "%changelog\n\
+                    * Sat Jun 05 2021 Jane Doe <jane@example.com> - 1.2-3\n\
+                    - Fixed a bug\n\
+                    - Added a feature\n\
+                    \n\
+                    * Sat Feb 29 2020 John Doe <john@example.com> - 1.1-1\n\
+                    - Initial package\n";
+        let entries = ChangeLogEntry::parse_spec_changelog(text).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].author(),
+                   "Jane Doe <jane@example.com> - 1.2-3");
+        assert_eq!(entries[0].description(),
+                   "- Fixed a bug\n- Added a feature");
+        assert_eq!(entries[0].timestamp(),
+                   UNIX_EPOCH + Duration::from_secs(1622894400));
+        assert_eq!(entries[1].author(),
+                   "John Doe <john@example.com> - 1.1-1");
+        assert_eq!(entries[1].description(), "- Initial package");
+        assert_eq!(entries[1].timestamp(),
+                   UNIX_EPOCH + Duration::from_secs(1582977600));
+    }
+
+    #[test]
+    fn parse_spec_changelog_latest_date() {
+        let text = "* Sat Feb 06 2106 Jane Doe\n- Last day\n";
+        let entries = ChangeLogEntry::parse_spec_changelog(text).unwrap();
+        assert_eq!(entries[0].timestamp(),
+                   UNIX_EPOCH + Duration::from_secs(4294900800));
+        let text = "* Sun Feb 07 2106 Jane Doe\n- Too late\n";
+        assert!(ChangeLogEntry::parse_spec_changelog(text).is_err());
+    }
+
+    #[test]
+    fn parse_empty_spec_changelog() {
+        assert!(ChangeLogEntry::parse_spec_changelog("").unwrap().is_empty());
+        assert!(ChangeLogEntry::parse_spec_changelog("%changelog\n")
+                    .unwrap()
+                    .is_empty());
+    }
+
+    #[test]
+    fn parse_invalid_spec_changelog() {
+        let texts = &[
+            "- No header line\n",
+            "* Wed Jun 05\n- Incomplete date\n",
+            "* Wed Foo 05 2019 Jane Doe\n- Bad month\n",
+            "* Wed Jun 31 2019 Jane Doe\n- Bad day\n",
+            "* Frd Jun 05 2019 Jane Doe\n- Bad weekday\n",
+            "* Wed Jun 05 2019\n- Missing author\n",
+            "* Mon Jun 05 2019 Jane Doe\n- Wrong weekday\n",
+            "* Wed Jan 01 2200 A <a@b>\n- Out of range\n",
+            "* Sat Jan 01 4294967295 A <a@b>\n- Way out of range\n",
+            "* Wed Jun 05 2019 Jane Doe\n- Newer\n\
+             * Thu Jun 06 2019 Jane Doe\n- Out of order\n",
+        ];
+        for &text in texts {
+            assert!(ChangeLogEntry::parse_spec_changelog(text).is_err(),
+                    "{:?}",
+                    text);
+        }
+    }
+}
+
+// ========================================================================= //
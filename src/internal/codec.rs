@@ -0,0 +1,849 @@
+use bzip2::Compression as BzCompression;
+use bzip2::read::BzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::{Compress, Crc, FlushCompress, Status};
+use flate2::Compression as GzCompression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use lz4_flex::frame::{FrameDecoder as Lz4Decoder,
+                      FrameEncoder as Lz4Encoder};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::thread;
+use xz2::read::XzDecoder;
+use xz2::stream::{Check, LzmaOptions, MtStreamBuilder, Stream};
+use xz2::write::XzEncoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+// ========================================================================= //
+
+/// The smallest window size, as a power of two, that zstd supports.
+const ZSTD_MIN_WINDOW_LOG: u32 = 10;
+
+/// The largest zstd window size, as a power of two, that can be used for a
+/// payload (the limit for 64-bit platforms).
+const ZSTD_MAX_WINDOW_LOG: u32 = 31;
+
+/// The window size, as a power of two, used for zstd long-window mode when
+/// none is given (the same as `zstd --long`).
+const ZSTD_DEFAULT_LONG_WINDOW_LOG: u32 = 27;
+
+/// The amount of uncompressed data in each independently-compressed block
+/// of a multithreaded gzip payload (the same as pigz's default).
+const GZIP_BLOCK_SIZE: usize = 128 * 1024;
+
+// ========================================================================= //
+
+/// A compression format for the Archive section of a package, identified by
+/// the name stored in the package header (e.g. `"gzip"`).
+///
+/// Codecs don't need to count bytes; the uncompressed sizes that
+/// `Package::validate` checks are measured by the archive reader and writer.
+pub trait PayloadCodec: Send + Sync {
+    /// Returns the compressor name used in the package header.
+    fn name(&self) -> &str;
+
+    /// Returns the `rpmlib()` feature name and version that a package must
+    /// require in order to use this compressor, if any.
+    fn rpmlib_feature(&self) -> Option<(&str, &str)> { None }
+
+    /// Returns a reader that decompresses the data read from `reader`.
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>>;
+
+    /// Returns an encoder for the given compression flags, or an error if
+    /// the flags are invalid for this compressor.  Codecs that can't make use
+    /// of some of the flags (such as the thread count) should ignore them.
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>>;
+}
+
+/// Incrementally compresses the Archive section of a new package, for a
+/// `PayloadCodec`.
+pub trait PayloadEncoder: Send {
+    /// Compresses `data`, writing any compressed output that is ready so far
+    /// to `output`.
+    fn encode(&mut self, data: &[u8], output: &mut dyn Write)
+              -> io::Result<()>;
+
+    /// Ends the compressed stream, writing the rest of the compressed output
+    /// to `output`.
+    fn finish(self: Box<Self>, output: &mut dyn Write) -> io::Result<()>;
+}
+
+// ========================================================================= //
+
+/// The compression settings for the Archive section of a package, as stored
+/// in the header's PAYLOADFLAGS string.  This uses the same syntax as rpm's
+/// `%_binary_payload` macro, `w<level>[T<threads>][L<window>].<io>` (e.g.
+/// `"w2T16.xzdio"`), although rpmbuild usually stores only the middle part
+/// (e.g. `"9"` or `"2T16"`).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PayloadFlags {
+    level: Option<u32>,
+    threads: Option<u32>,
+    long_window: Option<u32>,
+    io: Option<String>,
+}
+
+impl PayloadFlags {
+    /// Creates flags with the given compression level.
+    pub fn new(level: u32) -> PayloadFlags {
+        PayloadFlags {
+            level: Some(level),
+            ..PayloadFlags::default()
+        }
+    }
+
+    /// Parses a PAYLOADFLAGS string.  Every part is optional, so an empty
+    /// string is valid (and means to use the compressor's defaults).
+    pub fn parse(string: &str) -> io::Result<PayloadFlags> {
+        let mut flags = PayloadFlags::default();
+        let mut rest = string;
+        if let Some(index) = rest.find('.') {
+            let io = &rest[(index + 1)..];
+            if io.is_empty() ||
+                !io.chars().all(|chr| chr.is_ascii_alphanumeric())
+            {
+                invalid_data!("Invalid payload flags ({:?})", string);
+            }
+            flags.io = Some(io.to_string());
+            rest = &rest[..index];
+        }
+        if rest.starts_with('w') {
+            rest = &rest[1..];
+        }
+        let (level, mut rest) = split_number(rest);
+        flags.level = level;
+        while let Some(chr) = rest.chars().next() {
+            let (number, remainder) = split_number(&rest[chr.len_utf8()..]);
+            match chr {
+                'T' if flags.threads.is_none() => {
+                    // A bare "T" means to use one thread per CPU.
+                    flags.threads = Some(number.unwrap_or(0));
+                }
+                'L' if flags.long_window.is_none() => {
+                    flags.long_window =
+                        Some(number.unwrap_or(ZSTD_DEFAULT_LONG_WINDOW_LOG));
+                }
+                _ => invalid_data!("Invalid payload flags ({:?})", string),
+            }
+            rest = remainder;
+        }
+        Ok(flags)
+    }
+
+    /// Returns the compression level, if any.  Most compressors use levels
+    /// from 1 (fastest) to 9 (best); zstd goes up to 22.
+    pub fn level(&self) -> Option<u32> { self.level }
+
+    /// Sets the compression level.
+    pub fn with_level(mut self, level: u32) -> PayloadFlags {
+        self.level = Some(level);
+        self
+    }
+
+    /// Returns the number of threads to compress with, if given.  Zero means
+    /// to use one thread per CPU.
+    pub fn threads(&self) -> Option<u32> { self.threads }
+
+    /// Sets the number of threads to compress with (zero means one per CPU).
+    pub fn with_threads(mut self, threads: u32) -> PayloadFlags {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Returns the long-window size (as a power of two), if any.  This is
+    /// only used by zstd.
+    pub fn long_window(&self) -> Option<u32> { self.long_window }
+
+    /// Sets the long-window size, as a power of two.
+    pub fn with_long_window(mut self, window_log: u32) -> PayloadFlags {
+        self.long_window = Some(window_log);
+        self
+    }
+
+    /// Returns the name of the rpm I/O type (e.g. `"xzdio"`), if any.
+    pub fn io(&self) -> Option<&str> { self.io.as_deref() }
+
+    /// Sets the name of the rpm I/O type (e.g. `"xzdio"`).
+    pub fn with_io<S: Into<String>>(mut self, io: S) -> PayloadFlags {
+        self.io = Some(io.into());
+        self
+    }
+
+    /// Returns the number of threads to compress with, with zero resolved to
+    /// the number of CPUs, or `None` if no thread count was given.
+    fn resolved_threads(&self) -> Option<u32> {
+        match self.threads {
+            Some(0) => {
+                let cpus = thread::available_parallelism()
+                    .map(|cpus| cpus.get() as u32)
+                    .unwrap_or(1);
+                Some(cpus)
+            }
+            threads => threads,
+        }
+    }
+}
+
+impl fmt::Display for PayloadFlags {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.io.is_some() {
+            formatter.write_str("w")?;
+        }
+        if let Some(level) = self.level {
+            write!(formatter, "{}", level)?;
+        }
+        if let Some(threads) = self.threads {
+            write!(formatter, "T{}", threads)?;
+        }
+        if let Some(window_log) = self.long_window {
+            write!(formatter, "L{}", window_log)?;
+        }
+        if let Some(ref io) = self.io {
+            write!(formatter, ".{}", io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a leading decimal number (if any) off of a string.
+fn split_number(string: &str) -> (Option<u32>, &str) {
+    let end = string
+        .find(|chr: char| !chr.is_ascii_digit())
+        .unwrap_or(string.len());
+    (string[..end].parse().ok(), &string[end..])
+}
+
+// ========================================================================= //
+
+/// A set of payload codecs, looked up by compressor name.
+#[derive(Clone)]
+pub struct PayloadCodecRegistry {
+    codecs: Vec<Arc<dyn PayloadCodec>>,
+}
+
+impl PayloadCodecRegistry {
+    /// Creates a registry with the built-in codecs: `"gzip"`, `"bzip2"`,
+    /// `"xz"`, `"lzma"`, `"zstd"`, `"lz4"`, and `"identity"` (no
+    /// compression).
+    pub fn new() -> PayloadCodecRegistry {
+        let mut registry = PayloadCodecRegistry::empty();
+        registry.register(GzipCodec);
+        registry.register(Bzip2Codec);
+        registry.register(XzCodec);
+        registry.register(LzmaCodec);
+        registry.register(ZstdCodec);
+        registry.register(Lz4Codec);
+        registry.register(IdentityCodec);
+        registry
+    }
+
+    /// Creates a registry with no codecs at all.
+    pub fn empty() -> PayloadCodecRegistry {
+        PayloadCodecRegistry { codecs: Vec::new() }
+    }
+
+    /// Adds a codec to the registry, replacing any existing codec with the
+    /// same name.
+    pub fn register<C: PayloadCodec + 'static>(&mut self, codec: C) {
+        self.codecs.retain(|existing| existing.name() != codec.name());
+        self.codecs.push(Arc::new(codec));
+    }
+
+    /// Returns the codec for the given compressor name, if any.  An empty
+    /// name is treated as `"identity"`, since some packages with
+    /// uncompressed payloads are written that way.
+    pub fn get(&self, name: &str) -> Option<&dyn PayloadCodec> {
+        let name = if name.is_empty() { "identity" } else { name };
+        self.codecs
+            .iter()
+            .find(|codec| codec.name() == name)
+            .map(|codec| codec.as_ref())
+    }
+}
+
+impl Default for PayloadCodecRegistry {
+    fn default() -> PayloadCodecRegistry { PayloadCodecRegistry::new() }
+}
+
+// ========================================================================= //
+
+/// A `PayloadEncoder` for a writing compressor (such as flate2's
+/// `GzEncoder`) that compresses into an in-memory buffer, which is passed
+/// along to the output after each write.
+struct BufferedEncoder<E: Write + Send> {
+    encoder: E,
+    buffer: fn(&mut E) -> &mut Vec<u8>,
+    finish: fn(E) -> io::Result<Vec<u8>>,
+}
+
+impl<E: Write + Send> PayloadEncoder for BufferedEncoder<E> {
+    fn encode(&mut self, data: &[u8], output: &mut dyn Write)
+              -> io::Result<()> {
+        self.encoder.write_all(data)?;
+        let buffer = (self.buffer)(&mut self.encoder);
+        output.write_all(buffer)?;
+        buffer.clear();
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>, output: &mut dyn Write) -> io::Result<()> {
+        let encoder = *self;
+        output.write_all(&(encoder.finish)(encoder.encoder)?)
+    }
+}
+
+/// Returns the compression level from `flags`, which must be between 1 and
+/// `max` inclusive, or `default` if no level is given.
+fn compression_level(name: &str, flags: &PayloadFlags, max: u32,
+                     default: u32)
+                     -> io::Result<u32> {
+    match flags.level() {
+        None => Ok(default),
+        Some(level) if level >= 1 && level <= max => Ok(level),
+        Some(level) => {
+            invalid_input!("Invalid {} compression level ({})", name, level);
+        }
+    }
+}
+
+// ========================================================================= //
+
+struct GzipCodec;
+
+impl PayloadCodec for GzipCodec {
+    fn name(&self) -> &str { "gzip" }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        Ok(Box::new(GzDecoder::new(reader)))
+    }
+
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        let level = compression_level(self.name(), flags, 9, 6)?;
+        if let Some(threads) = flags.resolved_threads() {
+            return Ok(Box::new(ParallelGzipEncoder::new(level, threads)));
+        }
+        Ok(Box::new(BufferedEncoder {
+                        encoder: GzEncoder::new(Vec::new(),
+                                                GzCompression::new(level)),
+                        buffer: GzEncoder::get_mut,
+                        finish: GzEncoder::finish,
+                    }))
+    }
+}
+
+/// Compresses a gzip payload on several threads, like pigz does: the data is
+/// split into fixed-size blocks, each of which is deflated independently
+/// and ended with a sync flush, so that the results can be concatenated
+/// into a single ordinary gzip stream.  Since the block boundaries don't
+/// depend on the number of threads, neither does the output.
+struct ParallelGzipEncoder {
+    level: u32,
+    threads: usize,
+    header_written: bool,
+    pending: Vec<u8>,
+    crc: Crc,
+}
+
+impl ParallelGzipEncoder {
+    fn new(level: u32, threads: u32) -> ParallelGzipEncoder {
+        ParallelGzipEncoder {
+            level,
+            threads: threads.max(1) as usize,
+            header_written: false,
+            pending: Vec::new(),
+            crc: Crc::new(),
+        }
+    }
+
+    /// Deflates the given data, a block at a time in parallel, and writes
+    /// it to `output`.  If `last` is true, the final block ends the deflate
+    /// stream.
+    fn write_blocks(&mut self, data: &[u8], last: bool,
+                    output: &mut dyn Write)
+                    -> io::Result<()> {
+        if !self.header_written {
+            let extra_flags = match self.level {
+                1 => 4,
+                9 => 2,
+                _ => 0,
+            };
+            // Magic number, deflate method, no flags, no modification time,
+            // and an unknown OS (the same header that flate2 writes).
+            output.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, extra_flags,
+                               255])?;
+            self.header_written = true;
+        }
+        let mut blocks: Vec<&[u8]> = data.chunks(GZIP_BLOCK_SIZE).collect();
+        if blocks.is_empty() {
+            blocks.push(&[]);
+        }
+        let last_index = if last { blocks.len() - 1 } else { blocks.len() };
+        let level = self.level;
+        for (batch_index, batch) in blocks.chunks(self.threads).enumerate() {
+            let first_index = batch_index * self.threads;
+            let results: Vec<io::Result<Vec<u8>>> = thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &block)| {
+                        let last = first_index + index == last_index;
+                        scope.spawn(move || deflate_block(block, level, last))
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect()
+            });
+            for result in results {
+                output.write_all(&result?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PayloadEncoder for ParallelGzipEncoder {
+    fn encode(&mut self, data: &[u8], output: &mut dyn Write)
+              -> io::Result<()> {
+        self.crc.update(data);
+        // Write out whole batches for as long as there is more data than
+        // that, always holding back at least one byte so that the last block
+        // can be marked as such when the encoder is finished.  Batches start
+        // at multiples of the block size, so the output is the same however
+        // the data is split up between calls.
+        let batch_len = self.threads * GZIP_BLOCK_SIZE;
+        let mut data = data;
+        while self.pending.len() + data.len() > batch_len {
+            if self.pending.is_empty() {
+                self.write_blocks(&data[..batch_len], false, output)?;
+                data = &data[batch_len..];
+            } else {
+                let needed = batch_len - self.pending.len();
+                self.pending.extend_from_slice(&data[..needed]);
+                data = &data[needed..];
+                let mut pending = ::std::mem::take(&mut self.pending);
+                self.write_blocks(&pending, false, output)?;
+                pending.clear();
+                self.pending = pending;
+            }
+        }
+        self.pending.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, output: &mut dyn Write) -> io::Result<()> {
+        let pending = ::std::mem::take(&mut self.pending);
+        self.write_blocks(&pending, true, output)?;
+        output.write_all(&self.crc.sum().to_le_bytes())?;
+        output.write_all(&self.crc.amount().to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Deflates one block of a multithreaded gzip payload.  Unless it is the
+/// last block, the output ends with a sync flush (an empty stored block),
+/// so that the next block can follow on from it.
+fn deflate_block(data: &[u8], level: u32, last: bool) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(GzCompression::new(level), false);
+    let flush = if last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+    let mut output = Vec::with_capacity(data.len() + data.len() / 8 + 64);
+    loop {
+        let input = &data[(compress.total_in() as usize)..];
+        let status = compress.compress_vec(input, &mut output, flush)?;
+        let done = match status {
+            Status::StreamEnd => true,
+            _ => {
+                !last && compress.total_in() as usize == data.len() &&
+                    output.len() < output.capacity()
+            }
+        };
+        if done {
+            return Ok(output);
+        }
+        let additional = output.capacity();
+        output.reserve(additional);
+    }
+}
+
+// ========================================================================= //
+
+struct Bzip2Codec;
+
+impl PayloadCodec for Bzip2Codec {
+    fn name(&self) -> &str { "bzip2" }
+
+    fn rpmlib_feature(&self) -> Option<(&str, &str)> {
+        Some(("rpmlib(PayloadIsBzip2)", "3.0.5-1"))
+    }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        Ok(Box::new(BzDecoder::new(reader)))
+    }
+
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        let level = compression_level(self.name(), flags, 9, 9)?;
+        Ok(Box::new(BufferedEncoder {
+                        encoder: BzEncoder::new(Vec::new(),
+                                                BzCompression::new(level)),
+                        buffer: BzEncoder::get_mut,
+                        finish: BzEncoder::finish,
+                    }))
+    }
+}
+
+// ========================================================================= //
+
+struct XzCodec;
+
+impl PayloadCodec for XzCodec {
+    fn name(&self) -> &str { "xz" }
+
+    fn rpmlib_feature(&self) -> Option<(&str, &str)> {
+        Some(("rpmlib(PayloadIsXz)", "5.2-1"))
+    }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        Ok(Box::new(XzDecoder::new(reader)))
+    }
+
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        let level = compression_level(self.name(), flags, 9, 6)?;
+        let stream = match flags.resolved_threads() {
+            Some(threads) => {
+                MtStreamBuilder::new()
+                    .threads(threads)
+                    .preset(level)
+                    .check(Check::Crc64)
+                    .encoder()?
+            }
+            None => Stream::new_easy_encoder(level, Check::Crc64)?,
+        };
+        Ok(Box::new(BufferedEncoder {
+                        encoder: XzEncoder::new_stream(Vec::new(), stream),
+                        buffer: XzEncoder::get_mut,
+                        finish: XzEncoder::finish,
+                    }))
+    }
+}
+
+// ========================================================================= //
+
+/// The legacy "LZMA alone" format, which predates xz.
+struct LzmaCodec;
+
+impl PayloadCodec for LzmaCodec {
+    fn name(&self) -> &str { "lzma" }
+
+    fn rpmlib_feature(&self) -> Option<(&str, &str)> {
+        Some(("rpmlib(PayloadIsLzma)", "4.4.6-1"))
+    }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        let stream = Stream::new_lzma_decoder(u64::max_value())?;
+        Ok(Box::new(XzDecoder::new_stream(reader, stream)))
+    }
+
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        let level = compression_level(self.name(), flags, 9, 6)?;
+        let options = LzmaOptions::new_preset(level)?;
+        let stream = Stream::new_lzma_encoder(&options)?;
+        Ok(Box::new(BufferedEncoder {
+                        encoder: XzEncoder::new_stream(Vec::new(), stream),
+                        buffer: XzEncoder::get_mut,
+                        finish: XzEncoder::finish,
+                    }))
+    }
+}
+
+// ========================================================================= //
+
+struct ZstdCodec;
+
+impl PayloadCodec for ZstdCodec {
+    fn name(&self) -> &str { "zstd" }
+
+    fn rpmlib_feature(&self) -> Option<(&str, &str)> {
+        Some(("rpmlib(PayloadIsZstd)", "5.4.18-1"))
+    }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        let mut decoder = ZstdDecoder::new(reader)?;
+        // Allow any window size, so that payloads compressed in long-window
+        // mode can be read.
+        decoder.window_log_max(ZSTD_MAX_WINDOW_LOG)?;
+        Ok(Box::new(decoder))
+    }
+
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        let level = compression_level(self.name(), flags, 22, 3)?;
+        let mut encoder = ZstdEncoder::new(Vec::new(), level as i32)?;
+        if let Some(window_log) = flags.long_window() {
+            if window_log < ZSTD_MIN_WINDOW_LOG ||
+                window_log > ZSTD_MAX_WINDOW_LOG
+            {
+                invalid_input!("Invalid zstd long window size ({})",
+                               window_log);
+            }
+            encoder.long_distance_matching(true)?;
+            encoder.window_log(window_log)?;
+        }
+        if let Some(threads) = flags.resolved_threads() {
+            encoder.multithread(threads)?;
+        }
+        Ok(Box::new(BufferedEncoder {
+                        encoder,
+                        buffer: ZstdEncoder::get_mut,
+                        finish: ZstdEncoder::finish,
+                    }))
+    }
+}
+
+// ========================================================================= //
+
+/// The LZ4 frame format.  The encoder has no compression levels, so the
+/// level is checked but otherwise ignored.
+struct Lz4Codec;
+
+impl PayloadCodec for Lz4Codec {
+    fn name(&self) -> &str { "lz4" }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        Ok(Box::new(Lz4Decoder::new(reader)))
+    }
+
+    fn encoder(&self, flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        compression_level(self.name(), flags, 9, 1)?;
+        Ok(Box::new(BufferedEncoder {
+                        encoder: Lz4Encoder::new(Vec::new()),
+                        buffer: Lz4Encoder::get_mut,
+                        finish: |encoder| {
+                            encoder.finish().map_err(io::Error::from)
+                        },
+                    }))
+    }
+}
+
+// ========================================================================= //
+
+/// No compression at all.  Any compression level is ignored.
+struct IdentityCodec;
+
+impl PayloadCodec for IdentityCodec {
+    fn name(&self) -> &str { "identity" }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        Ok(reader)
+    }
+
+    fn encoder(&self, _flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        Ok(Box::new(IdentityEncoder))
+    }
+}
+
+struct IdentityEncoder;
+
+impl PayloadEncoder for IdentityEncoder {
+    fn encode(&mut self, data: &[u8], output: &mut dyn Write)
+              -> io::Result<()> {
+        output.write_all(data)
+    }
+
+    fn finish(self: Box<Self>, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{GZIP_BLOCK_SIZE, ParallelGzipEncoder, PayloadCodecRegistry,
+                PayloadEncoder, PayloadFlags};
+    use std::io::Read;
+
+    #[test]
+    fn parse_payload_flags() {
+        let flags = PayloadFlags::parse("9").unwrap();
+        assert_eq!(flags, PayloadFlags::new(9));
+        assert_eq!(flags.to_string(), "9");
+        let flags = PayloadFlags::parse("w9.gzdio").unwrap();
+        assert_eq!(flags, PayloadFlags::new(9).with_io("gzdio"));
+        assert_eq!(flags.to_string(), "w9.gzdio");
+        let flags = PayloadFlags::parse("w2T16.xzdio").unwrap();
+        assert_eq!(flags.level(), Some(2));
+        assert_eq!(flags.threads(), Some(16));
+        assert_eq!(flags.io(), Some("xzdio"));
+        assert_eq!(flags.to_string(), "w2T16.xzdio");
+        let flags = PayloadFlags::parse("19T").unwrap();
+        assert_eq!(flags, PayloadFlags::new(19).with_threads(0));
+        let flags = PayloadFlags::parse("3L").unwrap();
+        assert_eq!(flags, PayloadFlags::new(3).with_long_window(27));
+        assert_eq!(flags.to_string(), "3L27");
+        let flags = PayloadFlags::parse("w.ufdio").unwrap();
+        assert_eq!(flags.level(), None);
+        assert_eq!(flags.io(), Some("ufdio"));
+        assert_eq!(PayloadFlags::parse("").unwrap(), PayloadFlags::default());
+        assert!(PayloadFlags::parse("9x").is_err());
+        assert!(PayloadFlags::parse("9T2T4").is_err());
+        assert!(PayloadFlags::parse("w9.").is_err());
+        assert!(PayloadFlags::parse("w9.gz/dio").is_err());
+    }
+
+    #[test]
+    fn invalid_levels() {
+        let registry = PayloadCodecRegistry::new();
+        let gzip = registry.get("gzip").unwrap();
+        assert!(gzip.encoder(&PayloadFlags::new(0)).is_err());
+        assert!(gzip.encoder(&PayloadFlags::new(10)).is_err());
+        assert!(gzip.encoder(&PayloadFlags::default()).is_ok());
+        let zstd = registry.get("zstd").unwrap();
+        assert!(zstd.encoder(&PayloadFlags::new(22)).is_ok());
+        assert!(zstd.encoder(&PayloadFlags::new(23)).is_err());
+        let flags = PayloadFlags::new(19).with_long_window(9);
+        assert!(zstd.encoder(&flags).is_err());
+        let flags = PayloadFlags::new(19).with_long_window(32);
+        assert!(zstd.encoder(&flags).is_err());
+    }
+
+    #[test]
+    fn round_trip_all_codecs() {
+        let registry = PayloadCodecRegistry::new();
+        let data: Vec<u8> = (0..100_000).map(|n| (n % 251) as u8).collect();
+        let threaded = PayloadFlags::new(6).with_threads(2);
+        for &(name, ref flags) in &[("gzip", PayloadFlags::new(6)),
+                                    ("bzip2", PayloadFlags::new(1)),
+                                    ("xz", PayloadFlags::new(6)),
+                                    ("xz", threaded.clone()),
+                                    ("lzma", PayloadFlags::new(6)),
+                                    ("zstd", PayloadFlags::new(6)),
+                                    ("zstd", threaded.clone()),
+                                    ("lz4", PayloadFlags::new(6)),
+                                    ("identity", PayloadFlags::default())]
+        {
+            let codec = registry.get(name).unwrap();
+            assert_eq!(codec.name(), name);
+            let mut encoder = codec.encoder(flags).unwrap();
+            let mut compressed = Vec::new();
+            for chunk in data.chunks(7777) {
+                encoder.encode(chunk, &mut compressed).unwrap();
+            }
+            encoder.finish(&mut compressed).unwrap();
+            let mut decoder = codec.decoder(Box::new(&compressed[..]))
+                .unwrap();
+            let mut decompressed = Vec::new();
+            decoder.read_to_end(&mut decompressed).unwrap();
+            assert!(decompressed == data, "{} round trip failed", name);
+        }
+    }
+
+    #[test]
+    fn multithreaded_output_is_deterministic() {
+        let registry = PayloadCodecRegistry::new();
+        let data: Vec<u8> = (0..1_000_000u32)
+            .map(|n| (n.wrapping_mul(2_654_435_761) >> 29) as u8)
+            .collect();
+        for name in &["gzip", "xz", "zstd"] {
+            let codec = registry.get(name).unwrap();
+            let mut outputs = Vec::new();
+            for &threads in &[1, 2, 3, 8] {
+                let flags = PayloadFlags::new(1).with_threads(threads);
+                let mut encoder = codec.encoder(&flags).unwrap();
+                let mut compressed = Vec::new();
+                for chunk in data.chunks(10_000) {
+                    encoder.encode(chunk, &mut compressed).unwrap();
+                }
+                encoder.finish(&mut compressed).unwrap();
+                let mut decompressed = Vec::new();
+                codec
+                    .decoder(Box::new(&compressed[..]))
+                    .unwrap()
+                    .read_to_end(&mut decompressed)
+                    .unwrap();
+                assert!(decompressed == data, "{} round trip failed", name);
+                outputs.push(compressed);
+            }
+            assert!(outputs.iter().all(|output| *output == outputs[0]),
+                    "{} output depends on thread count",
+                    name);
+        }
+    }
+
+    #[test]
+    fn multithreaded_gzip_edge_cases() {
+        let codec = PayloadCodecRegistry::new();
+        let codec = codec.get("gzip").unwrap();
+        let flags = PayloadFlags::new(6).with_threads(2);
+        // Empty input, and input that ends exactly on a block boundary.
+        for &len in &[0, super::GZIP_BLOCK_SIZE * 2] {
+            let data = vec![7u8; len];
+            let mut encoder = codec.encoder(&flags).unwrap();
+            let mut compressed = Vec::new();
+            encoder.encode(&data, &mut compressed).unwrap();
+            encoder.finish(&mut compressed).unwrap();
+            let mut decoder = codec.decoder(Box::new(&compressed[..]))
+                .unwrap();
+            let mut decompressed = Vec::new();
+            decoder.read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    fn multithreaded_gzip_flushes_large_writes() {
+        let batch_len = 2 * GZIP_BLOCK_SIZE;
+        let data: Vec<u8> = (0..(5 * batch_len + 3))
+            .map(|n| (n as u32).wrapping_mul(2_654_435_761) as u8)
+            .collect();
+        // A single large write should be compressed right away, leaving less
+        // than one batch pending, and give the same output as small writes.
+        let mut encoder = ParallelGzipEncoder::new(6, 2);
+        let mut one_write = Vec::new();
+        encoder.encode(&data, &mut one_write).unwrap();
+        assert!(!one_write.is_empty());
+        assert_eq!(encoder.pending.len(), 3);
+        Box::new(encoder).finish(&mut one_write).unwrap();
+        let mut encoder = ParallelGzipEncoder::new(6, 2);
+        let mut small_writes = Vec::new();
+        for chunk in data.chunks(1000) {
+            encoder.encode(chunk, &mut small_writes).unwrap();
+            assert!(encoder.pending.len() <= batch_len);
+        }
+        Box::new(encoder).finish(&mut small_writes).unwrap();
+        assert!(one_write == small_writes);
+    }
+
+    #[test]
+    fn registry_lookup() {
+        let mut registry = PayloadCodecRegistry::new();
+        assert_eq!(registry.get("").unwrap().name(), "identity");
+        assert!(registry.get("lzip").is_none());
+        registry = PayloadCodecRegistry::empty();
+        assert!(registry.get("gzip").is_none());
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,714 @@
+use internal::version::compare_dependency_evrs;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+
+// ========================================================================= //
+
+bitflags! {
+    /// Flags for a package dependency, specifying how versions are compared
+    /// and when the dependency is needed (the `RPMSENSE_*` values).
+    pub struct DependencyFlags: u32 {
+        /// The dependency's version must be less than the given version.
+        const LESS = 0x02;
+        /// The dependency's version must be greater than the given version.
+        const GREATER = 0x04;
+        /// The dependency's version must be equal to the given version.
+        const EQUAL = 0x08;
+        /// The dependency is needed by the %posttrans scriptlet.
+        const POSTTRANS = 1 << 5;
+        /// The dependency must be installed before this package (legacy).
+        const PREREQ = 1 << 6;
+        /// The dependency is needed by the %pretrans scriptlet.
+        const PRETRANS = 1 << 7;
+        /// The dependency is a scriptlet interpreter.
+        const INTERP = 1 << 8;
+        /// The dependency is needed by the %pre scriptlet.
+        const SCRIPT_PRE = 1 << 9;
+        /// The dependency is needed by the %post scriptlet.
+        const SCRIPT_POST = 1 << 10;
+        /// The dependency is needed by the %preun scriptlet.
+        const SCRIPT_PREUN = 1 << 11;
+        /// The dependency is needed by the %postun scriptlet.
+        const SCRIPT_POSTUN = 1 << 12;
+        /// The dependency is needed by the %verify scriptlet.
+        const SCRIPT_VERIFY = 1 << 13;
+        /// The dependency was generated automatically by find-requires.
+        const FIND_REQUIRES = 1 << 14;
+        /// The dependency was generated automatically by find-provides.
+        const FIND_PROVIDES = 1 << 15;
+        /// The dependency is for a %triggerin scriptlet.
+        const TRIGGERIN = 1 << 16;
+        /// The dependency is for a %triggerun scriptlet.
+        const TRIGGERUN = 1 << 17;
+        /// The dependency is for a %triggerpostun scriptlet.
+        const TRIGGERPOSTUN = 1 << 18;
+        /// The dependency is only needed by a scriptlet, which may fail
+        /// without it.
+        const MISSINGOK = 1 << 19;
+        /// The dependency is needed by the %preuntrans scriptlet.
+        const PREUNTRANS = 1 << 20;
+        /// The dependency is needed by the %postuntrans scriptlet.
+        const POSTUNTRANS = 1 << 21;
+        /// The dependency is on a feature of rpm itself (`rpmlib(...)`).
+        const RPMLIB = 1 << 24;
+        /// The dependency is for a %triggerprein scriptlet.
+        const TRIGGERPREIN = 1 << 25;
+        /// The dependency is on a public key.
+        const KEYRING = 1 << 26;
+        /// The dependency is on a configuration file.
+        const CONFIG = 1 << 28;
+        /// The dependency is a build-time meta dependency.
+        const META = 1 << 29;
+    }
+}
+
+impl DependencyFlags {
+    /// Returns the flags used for comparing versions.
+    pub fn comparison(&self) -> DependencyFlags {
+        *self &
+            (DependencyFlags::LESS | DependencyFlags::GREATER |
+                 DependencyFlags::EQUAL)
+    }
+
+    /// Returns the comparison operator for these flags (e.g. `">="`), or
+    /// `None` if no version comparison is specified.
+    pub fn operator(&self) -> Option<&'static str> {
+        let less = self.contains(DependencyFlags::LESS);
+        let greater = self.contains(DependencyFlags::GREATER);
+        let equal = self.contains(DependencyFlags::EQUAL);
+        match (less, greater, equal) {
+            (false, false, false) => None,
+            (true, false, false) => Some("<"),
+            (true, false, true) => Some("<="),
+            (false, false, true) => Some("="),
+            (false, true, true) => Some(">="),
+            (false, true, false) => Some(">"),
+            (true, true, _) => Some("<>"),
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// A dependency relationship between packages (e.g. a requirement).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Dependency {
+    name: String,
+    flags: DependencyFlags,
+    version: String,
+}
+
+impl Dependency {
+    /// Constructs a new dependency on the given name, with the given flags
+    /// and EVR (`[epoch:]version[-release]`) string.  The EVR string should
+    /// be empty if the flags don't specify a comparison.
+    pub fn new<S1, S2>(name: S1, flags: DependencyFlags, version: S2)
+                       -> Dependency
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Dependency {
+            name: name.into(),
+            flags,
+            version: version.into(),
+        }
+    }
+
+    /// Constructs a dependency on any version of the given name.
+    pub fn any<S: Into<String>>(name: S) -> Dependency {
+        Dependency::new(name, DependencyFlags::empty(), String::new())
+    }
+
+    /// Constructs a dependency on versions less than the given one.
+    pub fn less<S1, S2>(name: S1, version: S2) -> Dependency
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Dependency::new(name, DependencyFlags::LESS, version)
+    }
+
+    /// Constructs a dependency on versions less than or equal to the given
+    /// one.
+    pub fn less_or_equal<S1, S2>(name: S1, version: S2) -> Dependency
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Dependency::new(name,
+                        DependencyFlags::LESS | DependencyFlags::EQUAL,
+                        version)
+    }
+
+    /// Constructs a dependency on exactly the given version.
+    pub fn equal<S1, S2>(name: S1, version: S2) -> Dependency
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Dependency::new(name, DependencyFlags::EQUAL, version)
+    }
+
+    /// Constructs a dependency on versions greater than or equal to the given
+    /// one.
+    pub fn greater_or_equal<S1, S2>(name: S1, version: S2) -> Dependency
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Dependency::new(name,
+                        DependencyFlags::GREATER | DependencyFlags::EQUAL,
+                        version)
+    }
+
+    /// Constructs a dependency on versions greater than the given one.
+    pub fn greater<S1, S2>(name: S1, version: S2) -> Dependency
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Dependency::new(name, DependencyFlags::GREATER, version)
+    }
+
+    /// Returns this dependency with the given flags added (e.g.
+    /// `DependencyFlags::SCRIPT_POST`).
+    pub fn with_flags(mut self, flags: DependencyFlags) -> Dependency {
+        self.flags |= flags;
+        self
+    }
+
+    /// Returns the name of the dependency (e.g. a package name, a file path,
+    /// or a virtual capability like `"rpmlib(FileDigests)"`).
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Returns the flags for this dependency.
+    pub fn flags(&self) -> DependencyFlags { self.flags }
+
+    /// Returns the EVR (`[epoch:]version[-release]`) string that the
+    /// dependency's version is compared against, or an empty string if there
+    /// is no version comparison.
+    pub fn version(&self) -> &str { &self.version }
+
+    /// Returns true if this dependency's name is a rich (boolean) dependency
+    /// expression, such as `"(foo >= 1.2 if bar)"`.
+    pub fn is_rich(&self) -> bool { self.name.starts_with('(') }
+
+    /// Parses this dependency into a `RichDependency`.  If this is not a rich
+    /// dependency, the result is simply `RichDependency::Simple`.
+    pub fn to_rich(&self) -> io::Result<RichDependency> {
+        if self.is_rich() {
+            RichDependency::parse(&self.name)
+        } else {
+            Ok(RichDependency::Simple(self.clone()))
+        }
+    }
+
+    /// Returns true if this (simple) dependency is satisfied by the given
+    /// provided capability; that is, if the names match and the version
+    /// ranges overlap.
+    pub fn is_satisfied_by(&self, provide: &Dependency) -> bool {
+        if self.name != provide.name {
+            return false;
+        }
+        let required = self.flags.comparison();
+        let provided = provide.flags.comparison();
+        if required.is_empty() || provided.is_empty() {
+            return true;
+        }
+        let less = DependencyFlags::LESS;
+        let greater = DependencyFlags::GREATER;
+        let equal = DependencyFlags::EQUAL;
+        match compare_dependency_evrs(&provide.version, &self.version) {
+            Ordering::Less => {
+                provided.contains(greater) || required.contains(less)
+            }
+            Ordering::Greater => {
+                provided.contains(less) || required.contains(greater)
+            }
+            Ordering::Equal => {
+                (provided.contains(equal) && required.contains(equal)) ||
+                    (provided.contains(less) && required.contains(less)) ||
+                    (provided.contains(greater) &&
+                         required.contains(greater))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.flags.operator() {
+            Some(operator) => {
+                write!(formatter,
+                       "{} {} {}",
+                       self.name,
+                       operator,
+                       self.version)
+            }
+            None => formatter.write_str(&self.name),
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// A parsed rich (boolean) dependency expression, as supported by RPM 4.13
+/// and later (e.g. `"(foo >= 1.2 if bar)"`).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RichDependency {
+    /// A simple dependency on a single name (with an optional version
+    /// comparison).
+    Simple(Dependency),
+    /// `(A and B and ...)`: all of the operands must be satisfied.
+    And(Vec<RichDependency>),
+    /// `(A or B or ...)`: at least one of the operands must be satisfied.
+    Or(Vec<RichDependency>),
+    /// `(A if B)` or `(A if B else C)`: if `B` is satisfied, then `A` must
+    /// be; otherwise `C` (if present) must be.
+    If(Box<RichDependency>, Box<RichDependency>, Option<Box<RichDependency>>),
+    /// `(A unless B)` or `(A unless B else C)`: if `B` is not satisfied,
+    /// then `A` must be; otherwise `C` (if present) must be.
+    Unless(Box<RichDependency>,
+           Box<RichDependency>,
+           Option<Box<RichDependency>>),
+    /// `(A with B with ...)`: all of the operands must be satisfied by the
+    /// same package.
+    With(Vec<RichDependency>),
+    /// `(A without B)`: `A` must be satisfied by a package that does not
+    /// also satisfy `B`.
+    Without(Box<RichDependency>, Box<RichDependency>),
+}
+
+impl RichDependency {
+    /// Parses a rich dependency expression.  The expression must be
+    /// enclosed in parentheses.
+    pub fn parse(string: &str) -> io::Result<RichDependency> {
+        let mut parser = RichParser { string, position: 0 };
+        parser.skip_whitespace();
+        if !parser.consume('(') {
+            invalid_data!("Rich dependency {:?} must start with '('",
+                          string);
+        }
+        let dependency = parser.parse_expression()?;
+        parser.skip_whitespace();
+        if parser.position < string.len() {
+            invalid_data!("Unexpected {:?} at end of rich dependency {:?}",
+                          &string[parser.position..],
+                          string);
+        }
+        Ok(dependency)
+    }
+
+    /// Evaluates this expression against a set of provided capabilities,
+    /// returning true if the dependency is satisfied.
+    ///
+    /// Since the provides are not grouped by package, `with` is treated the
+    /// same as `and`, and `(A without B)` is satisfied if `A` is satisfied
+    /// and `B` is not.
+    pub fn is_satisfied_by(&self, provides: &[Dependency]) -> bool {
+        match *self {
+            RichDependency::Simple(ref dependency) => {
+                provides
+                    .iter()
+                    .any(|provide| dependency.is_satisfied_by(provide))
+            }
+            RichDependency::And(ref operands) |
+            RichDependency::With(ref operands) => {
+                operands.iter().all(|dep| dep.is_satisfied_by(provides))
+            }
+            RichDependency::Or(ref operands) => {
+                operands.iter().any(|dep| dep.is_satisfied_by(provides))
+            }
+            RichDependency::If(ref then, ref condition, ref otherwise) => {
+                if condition.is_satisfied_by(provides) {
+                    then.is_satisfied_by(provides)
+                } else {
+                    match *otherwise {
+                        Some(ref dep) => dep.is_satisfied_by(provides),
+                        None => true,
+                    }
+                }
+            }
+            RichDependency::Unless(ref then, ref condition, ref otherwise) => {
+                if !condition.is_satisfied_by(provides) {
+                    then.is_satisfied_by(provides)
+                } else {
+                    match *otherwise {
+                        Some(ref dep) => dep.is_satisfied_by(provides),
+                        None => true,
+                    }
+                }
+            }
+            RichDependency::Without(ref dependency, ref excluded) => {
+                dependency.is_satisfied_by(provides) &&
+                    !excluded.is_satisfied_by(provides)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RichDependency {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RichDependency::Simple(ref dependency) => {
+                dependency.fmt(formatter)
+            }
+            RichDependency::And(ref operands) => {
+                write_operands(formatter, "and", operands)
+            }
+            RichDependency::Or(ref operands) => {
+                write_operands(formatter, "or", operands)
+            }
+            RichDependency::With(ref operands) => {
+                write_operands(formatter, "with", operands)
+            }
+            RichDependency::If(ref then, ref condition, ref otherwise) => {
+                write!(formatter, "({} if {}", then, condition)?;
+                if let Some(ref otherwise) = *otherwise {
+                    write!(formatter, " else {}", otherwise)?;
+                }
+                formatter.write_str(")")
+            }
+            RichDependency::Unless(ref then, ref condition, ref otherwise) => {
+                write!(formatter, "({} unless {}", then, condition)?;
+                if let Some(ref otherwise) = *otherwise {
+                    write!(formatter, " else {}", otherwise)?;
+                }
+                formatter.write_str(")")
+            }
+            RichDependency::Without(ref dependency, ref excluded) => {
+                write!(formatter, "({} without {})", dependency, excluded)
+            }
+        }
+    }
+}
+
+fn write_operands(formatter: &mut fmt::Formatter, operator: &str,
+                  operands: &[RichDependency])
+                  -> fmt::Result {
+    formatter.write_str("(")?;
+    for (index, operand) in operands.iter().enumerate() {
+        if index > 0 {
+            write!(formatter, " {} ", operator)?;
+        }
+        write!(formatter, "{}", operand)?;
+    }
+    formatter.write_str(")")
+}
+
+// ========================================================================= //
+
+struct RichParser<'a> {
+    string: &'a str,
+    position: usize,
+}
+
+impl<'a> RichParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.string[self.position..].chars().next()
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.peek() {
+            if !ch.is_whitespace() {
+                break;
+            }
+            self.position += ch.len_utf8();
+        }
+    }
+
+    /// Reads a word, ending at whitespace or at an unbalanced closing
+    /// parenthesis (so that names like `perl(Foo)` are read whole).
+    fn read_word(&mut self) -> &'a str {
+        let start = self.position;
+        let mut depth = 0;
+        while let Some(ch) = self.peek() {
+            if ch.is_whitespace() || (ch == ')' && depth == 0) {
+                break;
+            }
+            if ch == '(' {
+                depth += 1;
+            } else if ch == ')' {
+                depth -= 1;
+            }
+            self.position += ch.len_utf8();
+        }
+        &self.string[start..self.position]
+    }
+
+    /// Parses the rest of a parenthesized expression, after the opening
+    /// parenthesis has been consumed.
+    fn parse_expression(&mut self) -> io::Result<RichDependency> {
+        let first = self.parse_term()?;
+        self.skip_whitespace();
+        if self.consume(')') {
+            return Ok(first);
+        }
+        let operator = self.read_word();
+        let dependency = match operator {
+            "and" | "or" | "with" => {
+                let mut operands = vec![first, self.parse_term()?];
+                loop {
+                    self.skip_whitespace();
+                    if self.consume(')') {
+                        break;
+                    }
+                    let next = self.read_word();
+                    if next != operator {
+                        invalid_data!("Cannot mix {:?} and {:?} without \
+                                       parentheses in rich dependency {:?}",
+                                      operator,
+                                      next,
+                                      self.string);
+                    }
+                    operands.push(self.parse_term()?);
+                }
+                match operator {
+                    "and" => RichDependency::And(operands),
+                    "or" => RichDependency::Or(operands),
+                    _ => RichDependency::With(operands),
+                }
+            }
+            "if" | "unless" => {
+                let condition = Box::new(self.parse_term()?);
+                self.skip_whitespace();
+                let otherwise = if self.consume(')') {
+                    None
+                } else {
+                    let next = self.read_word();
+                    if next != "else" {
+                        invalid_data!("Expected \"else\" but found {:?} in \
+                                       rich dependency {:?}",
+                                      next,
+                                      self.string);
+                    }
+                    let otherwise = Box::new(self.parse_term()?);
+                    self.expect_close()?;
+                    Some(otherwise)
+                };
+                if operator == "if" {
+                    RichDependency::If(Box::new(first), condition, otherwise)
+                } else {
+                    RichDependency::Unless(Box::new(first),
+                                           condition,
+                                           otherwise)
+                }
+            }
+            "without" => {
+                let excluded = self.parse_term()?;
+                self.expect_close()?;
+                RichDependency::Without(Box::new(first), Box::new(excluded))
+            }
+            _ => {
+                invalid_data!("Unknown operator {:?} in rich dependency {:?}",
+                              operator,
+                              self.string);
+            }
+        };
+        Ok(dependency)
+    }
+
+    fn expect_close(&mut self) -> io::Result<()> {
+        self.skip_whitespace();
+        if !self.consume(')') {
+            invalid_data!("Expected ')' in rich dependency {:?}",
+                          self.string);
+        }
+        Ok(())
+    }
+
+    /// Parses either a parenthesized expression or a simple dependency.
+    fn parse_term(&mut self) -> io::Result<RichDependency> {
+        self.skip_whitespace();
+        if self.consume('(') {
+            return self.parse_expression();
+        }
+        let name = self.read_word();
+        if name.is_empty() {
+            invalid_data!("Missing dependency name in rich dependency {:?}",
+                          self.string);
+        }
+        self.skip_whitespace();
+        let flags = match self.peek() {
+            Some('<') | Some('>') | Some('=') => {
+                let operator = self.read_word();
+                match operator {
+                    "<" => DependencyFlags::LESS,
+                    "<=" => DependencyFlags::LESS | DependencyFlags::EQUAL,
+                    "=" | "==" => DependencyFlags::EQUAL,
+                    ">=" => DependencyFlags::GREATER | DependencyFlags::EQUAL,
+                    ">" => DependencyFlags::GREATER,
+                    _ => {
+                        invalid_data!("Invalid comparison operator {:?} in \
+                                       rich dependency {:?}",
+                                      operator,
+                                      self.string);
+                    }
+                }
+            }
+            _ => return Ok(RichDependency::Simple(Dependency::any(name))),
+        };
+        self.skip_whitespace();
+        let version = self.read_word();
+        if version.is_empty() {
+            invalid_data!("Missing version for {:?} in rich dependency {:?}",
+                          name,
+                          self.string);
+        }
+        Ok(RichDependency::Simple(Dependency::new(name, flags, version)))
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{Dependency, DependencyFlags, RichDependency};
+
+    #[test]
+    fn dependency_display() {
+        assert_eq!(format!("{}", Dependency::any("foo")), "foo");
+        assert_eq!(format!("{}", Dependency::less("foo", "1.2")), "foo < 1.2");
+        assert_eq!(format!("{}", Dependency::less_or_equal("foo", "1:2-3")),
+                   "foo <= 1:2-3");
+        assert_eq!(format!("{}", Dependency::equal("foo", "1.2")),
+                   "foo = 1.2");
+        assert_eq!(format!("{}", Dependency::greater_or_equal("foo", "1.2")),
+                   "foo >= 1.2");
+        assert_eq!(format!("{}", Dependency::greater("foo", "1.2")),
+                   "foo > 1.2");
+    }
+
+    #[test]
+    fn dependency_flags() {
+        let dependency = Dependency::greater_or_equal("/bin/sh", "4")
+            .with_flags(DependencyFlags::SCRIPT_POST |
+                            DependencyFlags::INTERP);
+        assert_eq!(dependency.flags().bits(), 0x50c);
+        assert_eq!(dependency.flags().comparison(),
+                   DependencyFlags::GREATER | DependencyFlags::EQUAL);
+        assert_eq!(dependency.flags().operator(), Some(">="));
+    }
+
+    #[test]
+    fn simple_dependency_matching() {
+        let provide = Dependency::equal("foo", "1.2-3");
+        assert!(Dependency::any("foo").is_satisfied_by(&provide));
+        assert!(!Dependency::any("bar").is_satisfied_by(&provide));
+        assert!(Dependency::greater_or_equal("foo", "1.2")
+                    .is_satisfied_by(&provide));
+        assert!(Dependency::less("foo", "1.10").is_satisfied_by(&provide));
+        assert!(!Dependency::greater("foo", "1.2").is_satisfied_by(&provide));
+        assert!(!Dependency::equal("foo", "1.2-4").is_satisfied_by(&provide));
+        assert!(!Dependency::equal("foo", "1:1.2").is_satisfied_by(&provide));
+        assert!(Dependency::greater("foo", "1.0")
+                    .is_satisfied_by(&Dependency::any("foo")));
+        assert!(Dependency::greater("foo", "2.0")
+                    .is_satisfied_by(&Dependency::greater("foo", "3.0")));
+        assert!(!Dependency::less("foo", "2.0")
+                     .is_satisfied_by(&Dependency::greater("foo", "3.0")));
+    }
+
+    #[test]
+    fn parse_rich_dependencies() {
+        let dependency = RichDependency::parse("(foo >= 1.2 if bar)")
+            .unwrap();
+        assert_eq!(dependency,
+                   RichDependency::If(
+                       Box::new(RichDependency::Simple(
+                           Dependency::greater_or_equal("foo", "1.2"))),
+                       Box::new(RichDependency::Simple(
+                           Dependency::any("bar"))),
+                       None));
+
+        let dependency = RichDependency::parse("(a or (b with c))").unwrap();
+        assert_eq!(dependency,
+                   RichDependency::Or(vec![
+                       RichDependency::Simple(Dependency::any("a")),
+                       RichDependency::With(vec![
+                           RichDependency::Simple(Dependency::any("b")),
+                           RichDependency::Simple(Dependency::any("c")),
+                       ]),
+                   ]));
+
+        let dependency =
+            RichDependency::parse("(perl(Foo::Bar) and python3(baz) < 2)")
+                .unwrap();
+        assert_eq!(dependency,
+                   RichDependency::And(vec![
+                       RichDependency::Simple(
+                           Dependency::any("perl(Foo::Bar)")),
+                       RichDependency::Simple(
+                           Dependency::less("python3(baz)", "2")),
+                   ]));
+    }
+
+    #[test]
+    fn rich_dependency_display_round_trip() {
+        let strings = &[
+            "(foo >= 1.2 if bar)",
+            "(a or (b with c))",
+            "(a and b and c)",
+            "(a if b else c)",
+            "(a unless b else (c or d))",
+            "(a without b)",
+            "(foo = 1:2.0-3 with bar)",
+        ];
+        for &string in strings {
+            let dependency = RichDependency::parse(string).unwrap();
+            assert_eq!(format!("{}", dependency), string);
+        }
+    }
+
+    #[test]
+    fn invalid_rich_dependencies() {
+        let strings = &[
+            "foo",
+            "(foo",
+            "(a and b or c)",
+            "(a if b or c)",
+            "(a frob b)",
+            "(a and)",
+            "(foo >= )",
+            "(foo => 1)",
+            "(a) b",
+        ];
+        for &string in strings {
+            assert!(RichDependency::parse(string).is_err(), "{:?}", string);
+        }
+    }
+
+    #[test]
+    fn evaluate_rich_dependencies() {
+        let provides = vec![
+            Dependency::equal("foo", "1.3-1"),
+            Dependency::equal("bar", "2.0-1"),
+        ];
+        let satisfied = |string: &str| {
+            RichDependency::parse(string).unwrap().is_satisfied_by(&provides)
+        };
+        assert!(satisfied("(foo >= 1.2 if bar)"));
+        assert!(!satisfied("(foo >= 1.4 if bar)"));
+        assert!(satisfied("(foo >= 1.4 if baz)"));
+        assert!(!satisfied("(foo >= 1.4 if baz else quux)"));
+        assert!(satisfied("(quux or (foo with bar))"));
+        assert!(!satisfied("(quux and foo)"));
+        assert!(satisfied("(quux unless bar)"));
+        assert!(!satisfied("(quux unless baz)"));
+        assert!(satisfied("(foo without baz)"));
+        assert!(!satisfied("(foo without bar < 3)"));
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,181 @@
+use md5;
+use sha1::Sha1;
+use sha2::{self, Digest};
+use std::io::{self, Write};
+
+// ========================================================================= //
+
+/// A cryptographic hash algorithm.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DigestAlgorithm {
+    /// The MD5 algorithm (128 bits).
+    Md5,
+    /// The SHA-1 algorithm (160 bits).
+    Sha1,
+    /// The SHA-224 algorithm (224 bits).
+    Sha224,
+    /// The SHA-256 algorithm (256 bits).
+    Sha256,
+    /// The SHA-384 algorithm (384 bits).
+    Sha384,
+    /// The SHA-512 algorithm (512 bits).
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    /// Returns the algorithm for the given OpenPGP hash algorithm number (as
+    /// used both in OpenPGP signatures and in RPM headers), if known.
+    pub(crate) fn from_number(number: u32) -> Option<DigestAlgorithm> {
+        match number {
+            1 => Some(DigestAlgorithm::Md5),
+            2 => Some(DigestAlgorithm::Sha1),
+            8 => Some(DigestAlgorithm::Sha256),
+            9 => Some(DigestAlgorithm::Sha384),
+            10 => Some(DigestAlgorithm::Sha512),
+            11 => Some(DigestAlgorithm::Sha224),
+            _ => None,
+        }
+    }
+
+    /// Returns the OpenPGP hash algorithm number for this algorithm.
+    pub(crate) fn number(&self) -> u32 {
+        match *self {
+            DigestAlgorithm::Md5 => 1,
+            DigestAlgorithm::Sha1 => 2,
+            DigestAlgorithm::Sha256 => 8,
+            DigestAlgorithm::Sha384 => 9,
+            DigestAlgorithm::Sha512 => 10,
+            DigestAlgorithm::Sha224 => 11,
+        }
+    }
+
+    /// Returns the length of a digest produced by this algorithm, in bytes.
+    pub fn digest_len(&self) -> usize {
+        match *self {
+            DigestAlgorithm::Md5 => 16,
+            DigestAlgorithm::Sha1 => 20,
+            DigestAlgorithm::Sha224 => 28,
+            DigestAlgorithm::Sha256 => 32,
+            DigestAlgorithm::Sha384 => 48,
+            DigestAlgorithm::Sha512 => 64,
+        }
+    }
+
+    /// Returns a new writer that computes a digest with this algorithm.
+    pub(crate) fn writer(&self) -> DigestWriter {
+        let context = match *self {
+            DigestAlgorithm::Md5 => DigestContext::Md5(md5::Context::new()),
+            DigestAlgorithm::Sha1 => DigestContext::Sha1(Sha1::new()),
+            DigestAlgorithm::Sha224 => {
+                DigestContext::Sha224(sha2::Sha224::new())
+            }
+            DigestAlgorithm::Sha256 => {
+                DigestContext::Sha256(sha2::Sha256::new())
+            }
+            DigestAlgorithm::Sha384 => {
+                DigestContext::Sha384(sha2::Sha384::new())
+            }
+            DigestAlgorithm::Sha512 => {
+                DigestContext::Sha512(sha2::Sha512::new())
+            }
+        };
+        DigestWriter { context }
+    }
+}
+
+// ========================================================================= //
+
+enum DigestContext {
+    Md5(md5::Context),
+    Sha1(Sha1),
+    Sha224(sha2::Sha224),
+    Sha256(sha2::Sha256),
+    Sha384(sha2::Sha384),
+    Sha512(sha2::Sha512),
+}
+
+/// Computes a digest of all data written into it.
+pub struct DigestWriter {
+    context: DigestContext,
+}
+
+impl DigestWriter {
+    /// Consumes the writer and returns the digest of all data written so far.
+    pub fn finish(self) -> Vec<u8> {
+        match self.context {
+            DigestContext::Md5(context) => context.compute().0.to_vec(),
+            DigestContext::Sha1(context) => context.digest().bytes().to_vec(),
+            DigestContext::Sha224(context) => context.finalize().to_vec(),
+            DigestContext::Sha256(context) => context.finalize().to_vec(),
+            DigestContext::Sha384(context) => context.finalize().to_vec(),
+            DigestContext::Sha512(context) => context.finalize().to_vec(),
+        }
+    }
+
+    /// Consumes the writer and returns the digest of all data written so far,
+    /// as a lowercase hexadecimal string.
+    pub fn finish_hex(self) -> String {
+        self.finish().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl Write for DigestWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.context {
+            DigestContext::Md5(ref mut context) => context.consume(buf),
+            DigestContext::Sha1(ref mut context) => context.update(buf),
+            DigestContext::Sha224(ref mut context) => context.update(buf),
+            DigestContext::Sha256(ref mut context) => context.update(buf),
+            DigestContext::Sha384(ref mut context) => context.update(buf),
+            DigestContext::Sha512(ref mut context) => context.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::DigestAlgorithm;
+    use std::io::Write;
+
+    const ALL_ALGORITHMS: &[DigestAlgorithm] = &[
+        DigestAlgorithm::Md5,
+        DigestAlgorithm::Sha1,
+        DigestAlgorithm::Sha224,
+        DigestAlgorithm::Sha256,
+        DigestAlgorithm::Sha384,
+        DigestAlgorithm::Sha512,
+    ];
+
+    #[test]
+    fn digest_algorithm_number_round_trip() {
+        for &algorithm in ALL_ALGORITHMS {
+            assert_eq!(DigestAlgorithm::from_number(algorithm.number()),
+                       Some(algorithm));
+        }
+    }
+
+    #[test]
+    fn digest_lengths() {
+        for &algorithm in ALL_ALGORITHMS {
+            let mut writer = algorithm.writer();
+            writer.write_all(b"Hello, world!").unwrap();
+            assert_eq!(writer.finish().len(), algorithm.digest_len());
+        }
+    }
+
+    #[test]
+    fn hex_digest() {
+        let mut writer = DigestAlgorithm::Sha256.writer();
+        writer.write_all(b"abc").unwrap();
+        assert_eq!(writer.finish_hex(),
+                   "ba7816bf8f01cfea414140de5dae2223\
+                    b00361a396177a9cb410ff61f20015ad");
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,432 @@
+use internal::header::{FileFlags, FileInfo};
+use internal::package::ArchiveSection;
+use internal::verify::OwnerNames;
+use std::collections::HashMap;
+use std::fs::{self, File, Permissions};
+use std::io::{self, Read};
+use std::os::unix::fs::{self as unix_fs, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+// ========================================================================= //
+
+/// Mask for the permission bits of a Unix mode.
+const PERMISSION_BITS: u16 = 0o7777;
+
+// ========================================================================= //
+
+/// Options controlling which files `Package::extract_to` extracts, and how.
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    docs: bool,
+    ghosts: bool,
+    ownership: bool,
+    patterns: Vec<String>,
+}
+
+impl ExtractOptions {
+    /// Constructs the default options, which extract all files except for
+    /// `%ghost` files, owned by the current user.
+    pub fn new() -> ExtractOptions {
+        ExtractOptions {
+            docs: true,
+            ghosts: false,
+            ownership: false,
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Returns these options with `%doc` files either extracted (the
+    /// default) or skipped.
+    pub fn with_docs(mut self, docs: bool) -> ExtractOptions {
+        self.docs = docs;
+        self
+    }
+
+    /// Returns these options with `%ghost` files either created (as empty
+    /// files, directories, or symlinks) or skipped (the default).  Ghost
+    /// files have no data in the package archive.
+    pub fn with_ghosts(mut self, ghosts: bool) -> ExtractOptions {
+        self.ghosts = ghosts;
+        self
+    }
+
+    /// Returns these options with the owner user and group of extracted
+    /// files either set from the package metadata or left as the current
+    /// user (the default).  Setting ownership generally requires running as
+    /// root.  Names are looked up in the system's `/etc/passwd` and
+    /// `/etc/group` files; as with rpm, unknown names fall back to root.
+    pub fn with_ownership(mut self, ownership: bool) -> ExtractOptions {
+        self.ownership = ownership;
+        self
+    }
+
+    /// Returns these options with an additional glob pattern (e.g.
+    /// `"/usr/share/man/**"`).  If any patterns are given, only files whose
+    /// install paths match at least one of them are extracted.  Within a
+    /// pattern, `*` and `?` match any characters other than `/`, `**`
+    /// matches any characters at all, and `[...]` matches a character class.
+    pub fn with_pattern<S: Into<String>>(mut self, pattern: S)
+                                         -> ExtractOptions {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    fn includes(&self, file_info: &FileInfo) -> bool {
+        if !self.docs && file_info.flags().contains(FileFlags::DOC) {
+            return false;
+        }
+        if !self.ghosts && file_info.is_ghost() {
+            return false;
+        }
+        self.patterns.is_empty() ||
+            self.patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern, file_info.name()))
+    }
+}
+
+impl Default for ExtractOptions {
+    fn default() -> ExtractOptions { ExtractOptions::new() }
+}
+
+// ========================================================================= //
+
+/// Extracts the files in a package archive into a directory.
+pub(crate) struct Extractor<'o> {
+    root: PathBuf,
+    options: &'o ExtractOptions,
+    owners: Option<OwnerNames>,
+    files: Vec<(PathBuf, FileInfo)>,
+    file_indices: HashMap<PathBuf, usize>,
+    /// Directories created for package files, whose metadata is set last
+    /// (so that creating their contents doesn't change their mtimes, and so
+    /// that read-only directories can still be filled in).
+    directories: Vec<usize>,
+    /// Hard links seen so far whose data hasn't yet been seen, by inode.
+    pending_links: HashMap<u32, Vec<usize>>,
+}
+
+impl<'o> Extractor<'o> {
+    pub(crate) fn new<I>(root: &Path, options: &'o ExtractOptions,
+                         file_infos: I)
+                         -> io::Result<Extractor<'o>>
+    where
+        I: IntoIterator<Item = FileInfo>,
+    {
+        let owners = if options.ownership {
+            Some(OwnerNames::read(Path::new("/"))?)
+        } else {
+            None
+        };
+        let mut files = Vec::new();
+        let mut file_indices = HashMap::new();
+        for file_info in file_infos {
+            let path = relative_path(file_info.name())?;
+            file_indices.insert(path.clone(), files.len());
+            files.push((path, file_info));
+        }
+        fs::create_dir_all(root)?;
+        Ok(Extractor {
+               root: root.to_path_buf(),
+               options,
+               owners,
+               files,
+               file_indices,
+               directories: Vec::new(),
+               pending_links: HashMap::new(),
+           })
+    }
+
+    pub(crate) fn extract(mut self, archive: &mut ArchiveSection)
+                          -> io::Result<()> {
+        while let Some(mut file) = archive.next_file()? {
+            let path = relative_path(file.file_path())?;
+            let index = match self.file_indices.get(&path) {
+                Some(&index) => index,
+                None => {
+                    invalid_data!("Archive file {:?} is not listed in \
+                                   package metadata",
+                                  file.file_path());
+                }
+            };
+            let included = self.options.includes(&self.files[index].1);
+            if !self.files[index].1.is_regular_file() {
+                if included {
+                    self.create_special_file(index)?;
+                }
+                continue;
+            }
+            // In a set of hard links, only the last entry in the archive
+            // has the file data.
+            let link_count = file.link_count();
+            if link_count > 1 && file.file_size() == 0 {
+                if included {
+                    self.pending_links
+                        .entry(file.inode())
+                        .or_default()
+                        .push(index);
+                }
+                continue;
+            }
+            let mut indices = if link_count > 1 {
+                self.pending_links.remove(&file.inode()).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            if included {
+                indices.push(index);
+            }
+            if !indices.is_empty() {
+                self.create_regular_file(&indices, &mut file)?;
+            }
+        }
+        // Any remaining hard links are for empty files:
+        let mut pending_links: Vec<Vec<usize>> =
+            self.pending_links.drain().map(|(_, indices)| indices).collect();
+        pending_links.sort();
+        for indices in pending_links {
+            self.create_regular_file(&indices, &mut io::empty())?;
+        }
+        // Ghost files aren't in the archive at all:
+        if self.options.ghosts {
+            for index in 0..self.files.len() {
+                let file_info = &self.files[index].1;
+                if !file_info.is_ghost() || !self.options.includes(file_info) {
+                    continue;
+                }
+                if file_info.is_regular_file() {
+                    self.create_regular_file(&[index], &mut io::empty())?;
+                } else {
+                    self.create_special_file(index)?;
+                }
+            }
+        }
+        // Set directory metadata last, innermost directories first:
+        let mut directories = self.directories.clone();
+        directories.reverse();
+        for index in directories {
+            let path = self.root.join(&self.files[index].0);
+            self.set_metadata(&path, &self.files[index].1)?;
+        }
+        Ok(())
+    }
+
+    fn create_special_file(&mut self, index: usize) -> io::Result<()> {
+        let path = self.prepare_path(&self.files[index].0)?;
+        let file_info = &self.files[index].1;
+        if file_info.is_directory() {
+            if !path.is_dir() {
+                fs::create_dir(&path)?;
+            }
+            self.directories.push(index);
+        } else if let Some(target) = file_info.symlink_target() {
+            unix_fs::symlink(target, &path)?;
+            self.set_metadata(&path, file_info)?;
+        }
+        // Device files and FIFOs can't be created without special
+        // privileges (and libc), so they are skipped.
+        Ok(())
+    }
+
+    /// Writes a regular file at the first of the given indices, and hard
+    /// links the rest to it.
+    fn create_regular_file<R: Read>(&self, indices: &[usize], data: &mut R)
+                                    -> io::Result<()> {
+        let path = self.prepare_path(&self.files[indices[0]].0)?;
+        io::copy(data, &mut File::create(&path)?)?;
+        self.set_metadata(&path, &self.files[indices[0]].1)?;
+        for &index in indices[1..].iter() {
+            let link_path = self.prepare_path(&self.files[index].0)?;
+            fs::hard_link(&path, &link_path)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure that all parent directories of the given relative path
+    /// exist as real directories (not symlinks) under the root, and removes
+    /// any non-directory already at the path itself, so that a file can be
+    /// safely created there.
+    fn prepare_path(&self, relative: &Path) -> io::Result<PathBuf> {
+        let mut path = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            path.push(component);
+            let is_last = components.peek().is_none();
+            match fs::symlink_metadata(&path) {
+                Ok(ref metadata) if metadata.is_dir() => {}
+                Ok(_) if is_last => fs::remove_file(&path)?,
+                Ok(_) => {
+                    invalid_data!("Refusing to extract {:?} through \
+                                   non-directory {:?}",
+                                  relative,
+                                  path);
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                    if !is_last {
+                        fs::create_dir(&path)?;
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(path)
+    }
+
+    fn set_metadata(&self, path: &Path, file_info: &FileInfo)
+                    -> io::Result<()> {
+        if let Some(ref owners) = self.owners {
+            let uid = owners.user_id(file_info.user_name()).unwrap_or(0);
+            let gid = owners.group_id(file_info.group_name()).unwrap_or(0);
+            if file_info.is_symlink() {
+                unix_fs::lchown(path, Some(uid), Some(gid))?;
+            } else {
+                unix_fs::chown(path, Some(uid), Some(gid))?;
+            }
+        }
+        if file_info.is_symlink() {
+            return Ok(());
+        }
+        // Set the mtime before the permissions, in case the new permissions
+        // don't allow us to open the file.
+        File::open(path)?.set_modified(file_info.modified_time())?;
+        let mode = u32::from(file_info.mode() & PERMISSION_BITS);
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+        Ok(())
+    }
+}
+
+// ========================================================================= //
+
+/// Converts a file path from a package into a relative path under the
+/// extraction root, rejecting any path that would escape the root.
+fn relative_path(name: &str) -> io::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                invalid_data!("Refusing to extract {:?}, which is outside the \
+                               destination directory",
+                              name);
+            }
+            _ => path.push(component),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        invalid_data!("Invalid file path in package: {:?}", name);
+    }
+    Ok(path)
+}
+
+/// Returns true if the path matches the glob pattern.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_matches_chars(&pattern, &path)
+}
+
+fn glob_matches_chars(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(&'*') => {
+            let any_depth = pattern.get(1) == Some(&'*');
+            let rest = if any_depth { &pattern[2..] } else { &pattern[1..] };
+            for skip in 0..(path.len() + 1) {
+                if glob_matches_chars(rest, &path[skip..]) {
+                    return true;
+                }
+                if skip < path.len() && path[skip] == '/' && !any_depth {
+                    return false;
+                }
+            }
+            false
+        }
+        Some(&'?') => {
+            !path.is_empty() && path[0] != '/' &&
+                glob_matches_chars(&pattern[1..], &path[1..])
+        }
+        Some(&'[') => {
+            let class_end = pattern.iter().skip(1).position(|&c| c == ']');
+            match (path.first(), class_end) {
+                (Some(&ch), Some(end)) if ch != '/' => {
+                    let class = &pattern[1..(end + 1)];
+                    class_matches(class, ch) &&
+                        glob_matches_chars(&pattern[(end + 2)..], &path[1..])
+                }
+                (Some(&ch), None) => {
+                    ch == '[' && glob_matches_chars(&pattern[1..], &path[1..])
+                }
+                _ => false,
+            }
+        }
+        Some(&ch) => {
+            path.first() == Some(&ch) &&
+                glob_matches_chars(&pattern[1..], &path[1..])
+        }
+    }
+}
+
+/// Returns true if the character matches the contents of a `[...]`
+/// character class (e.g. `a-z` or `!0-9`).
+fn class_matches(class: &[char], ch: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some(&'!') | Some(&'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    let mut index = 0;
+    while index < class.len() {
+        if index + 2 < class.len() && class[index + 1] == '-' {
+            if class[index] <= ch && ch <= class[index + 2] {
+                matched = true;
+            }
+            index += 3;
+        } else {
+            if class[index] == ch {
+                matched = true;
+            }
+            index += 1;
+        }
+    }
+    matched != negated
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_matches, relative_path};
+    use std::path::PathBuf;
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(relative_path("/usr/bin/hello").unwrap(),
+                   PathBuf::from("usr/bin/hello"));
+        assert_eq!(relative_path("./usr//bin/./hello").unwrap(),
+                   PathBuf::from("usr/bin/hello"));
+        assert!(relative_path("/usr/../../etc/passwd").is_err());
+        assert!(relative_path("../hello").is_err());
+        assert!(relative_path("/").is_err());
+        assert!(relative_path("").is_err());
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches("/usr/bin/hello", "/usr/bin/hello"));
+        assert!(!glob_matches("/usr/bin/hello", "/usr/bin/hello2"));
+        assert!(glob_matches("/usr/bin/*", "/usr/bin/hello"));
+        assert!(!glob_matches("/usr/bin/*", "/usr/bin/x/hello"));
+        assert!(glob_matches("/usr/**", "/usr/bin/x/hello"));
+        assert!(glob_matches("/usr/**/hello", "/usr/bin/x/hello"));
+        assert!(glob_matches("/usr/bin/h?llo", "/usr/bin/hallo"));
+        assert!(!glob_matches("/usr/bin?hello", "/usr/bin/hello"));
+        assert!(glob_matches("/usr/lib[0-9]*/libfoo.so",
+                             "/usr/lib64/libfoo.so"));
+        assert!(!glob_matches("/usr/lib[!0-9]*/libfoo.so",
+                              "/usr/lib64/libfoo.so"));
+        assert!(glob_matches("/etc/*.conf", "/etc/hello.conf"));
+        assert!(!glob_matches("/etc/*.conf", "/etc/hello.cfg"));
+    }
+}
+
+// ========================================================================= //
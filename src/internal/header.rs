@@ -1,9 +1,23 @@
+use internal::changelog::ChangeLogEntry;
+use internal::codec::PayloadFlags;
 use internal::convert;
+use internal::dependency::{Dependency, DependencyFlags};
+use internal::digest::DigestAlgorithm;
 use internal::index::{IndexTable, IndexType, IndexValue};
+use internal::scriptlet::{Scriptlet, ScriptletFlags, ScriptletType};
+use internal::trigger::{FileTrigger, Trigger, TriggerType};
+use internal::verify::VerifyFlags;
+#[cfg(unix)]
+use internal::verify::{FileVerification, OwnerNames};
+use internal::version::{Evr, Nevra};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs::Metadata;
 use std::io::{self, Read, Seek, Write};
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use std::path::Path;
 use std::time::SystemTime;
 
 // ========================================================================= //
@@ -14,12 +28,19 @@ const TAG_NAME: i32 = 1000;
 const TAG_VERSION: i32 = 1001;
 /// Required tag for the release number of the package.
 const TAG_RELEASE: i32 = 1002;
+/// Optional tag for the epoch of the package (a missing epoch is treated the
+/// same as zero).
+const TAG_EPOCH: i32 = 1003;
 /// Required tag for a one-line description of the package.
 const TAG_SUMMARY: i32 = 1004;
 /// Required tag for a longer, multi-line description of the package.
 const TAG_DESCRIPTION: i32 = 1005;
-/// Required tag for the sum of the sizes of the regular files in the archive.
+/// Tag for the sum of the sizes of the regular files in the archive.  Either
+/// this or `TAG_LONGSIZE` is required.
 const TAG_SIZE: i32 = 1009;
+/// Tag for the sum of the sizes of the regular files in the archive, as a
+/// 64-bit value.  Used instead of `TAG_SIZE` when the sum is 4 GiB or more.
+const TAG_LONGSIZE: i32 = 5009;
 /// Optional tag for the author of the package.
 const TAG_VENDOR: i32 = 1011;
 /// Required tag for the license which applies to this package.
@@ -43,6 +64,11 @@ const TAG_PAYLOADFORMAT: i32 = 1124;
 const TAG_PAYLOADCOMPRESSOR: i32 = 1125;
 /// Required tag for the compression level used for the Payload (e.g. `"9"`).
 const TAG_PAYLOADFLAGS: i32 = 1126;
+/// Optional tag for the hex digest of the (compressed) Archive section.
+const TAG_PAYLOADDIGEST: i32 = 5092;
+/// Optional tag for the hash algorithm used for `TAG_PAYLOADDIGEST`, as an
+/// OpenPGP hash algorithm number (e.g. 8 for SHA-256).
+const TAG_PAYLOADDIGESTALGO: i32 = 5093;
 
 /// Optional tag for the preinstall script.
 const TAG_PREIN: i32 = 1023;
@@ -60,13 +86,49 @@ const TAG_POSTINPROG: i32 = 1086;
 const TAG_PREUNPROG: i32 = 1087;
 /// Optional tag for the postuninstall script interpreter (e.g `"/bin/sh"`).
 const TAG_POSTUNPROG: i32 = 1088;
+/// Optional tag for the pretransaction script.
+const TAG_PRETRANS: i32 = 1151;
+/// Optional tag for the posttransaction script.
+const TAG_POSTTRANS: i32 = 1152;
+/// Optional tag for the pretransaction script interpreter.
+const TAG_PRETRANSPROG: i32 = 1153;
+/// Optional tag for the posttransaction script interpreter.
+const TAG_POSTTRANSPROG: i32 = 1154;
+/// Optional tag for the `ScriptletFlags` of the preinstall script.
+const TAG_PREINFLAGS: i32 = 5020;
+/// Optional tag for the `ScriptletFlags` of the postinstall script.
+const TAG_POSTINFLAGS: i32 = 5021;
+/// Optional tag for the `ScriptletFlags` of the preuninstall script.
+const TAG_PREUNFLAGS: i32 = 5022;
+/// Optional tag for the `ScriptletFlags` of the postuninstall script.
+const TAG_POSTUNFLAGS: i32 = 5023;
+/// Optional tag for the `ScriptletFlags` of the pretransaction script.
+const TAG_PRETRANSFLAGS: i32 = 5024;
+/// Optional tag for the `ScriptletFlags` of the posttransaction script.
+const TAG_POSTTRANSFLAGS: i32 = 5025;
+/// Optional tag for the pre-uninstall-transaction script.
+const TAG_PREUNTRANS: i32 = 5103;
+/// Optional tag for the post-uninstall-transaction script.
+const TAG_POSTUNTRANS: i32 = 5104;
+/// Optional tag for the pre-uninstall-transaction script interpreter.
+const TAG_PREUNTRANSPROG: i32 = 5105;
+/// Optional tag for the post-uninstall-transaction script interpreter.
+const TAG_POSTUNTRANSPROG: i32 = 5106;
+/// Optional tag for the `ScriptletFlags` of the pre-uninstall-transaction
+/// script.
+const TAG_PREUNTRANSFLAGS: i32 = 5107;
+/// Optional tag for the `ScriptletFlags` of the post-uninstall-transaction
+/// script.
+const TAG_POSTUNTRANSFLAGS: i32 = 5108;
 
 const TAG_OLDFILENAMES: i32 = 1027;
 const TAG_FILESIZES: i32 = 1028;
+/// Used instead of `TAG_FILESIZES` when any file is 4 GiB or larger.
+const TAG_LONGFILESIZES: i32 = 5008;
 const TAG_FILEMODES: i32 = 1030;
 const TAG_FILERDEVS: i32 = 1033;
 const TAG_FILEMTIMES: i32 = 1034;
-const TAG_FILEMD5S: i32 = 1035;
+const TAG_FILEDIGESTS: i32 = 1035;
 const TAG_FILELINKTOS: i32 = 1036;
 const TAG_FILEFLAGS: i32 = 1037;
 const TAG_FILEUSERNAME: i32 = 1039;
@@ -77,6 +139,9 @@ const TAG_FILELANGS: i32 = 1097;
 const TAG_DIRINDEXES: i32 = 1116;
 const TAG_BASENAMES: i32 = 1117;
 const TAG_DIRNAMES: i32 = 1118;
+/// Optional tag for the hash algorithm used for `TAG_FILEDIGESTS`, as an
+/// OpenPGP hash algorithm number.  If absent, the digests are MD5.
+const TAG_FILEDIGESTALGO: i32 = 5011;
 
 /// Required tag for the names of the dependencies provided by this package.
 const TAG_PROVIDENAME: i32 = 1047;
@@ -96,6 +161,57 @@ const TAG_CONFLICTVERSION: i32 = 1055;
 const TAG_OBSOLETENAME: i32 = 1090;
 const TAG_OBSOLETEFLAGS: i32 = 1114;
 const TAG_OBSOLETEVERSION: i32 = 1115;
+/// Optional tag for the names of any dependencies that this package
+/// recommends (a weak requirement).
+const TAG_RECOMMENDNAME: i32 = 5046;
+const TAG_RECOMMENDVERSION: i32 = 5047;
+const TAG_RECOMMENDFLAGS: i32 = 5048;
+/// Optional tag for the names of any dependencies that this package suggests
+/// (a very weak requirement).
+const TAG_SUGGESTNAME: i32 = 5049;
+const TAG_SUGGESTVERSION: i32 = 5050;
+const TAG_SUGGESTFLAGS: i32 = 5051;
+/// Optional tag for the names of any dependencies that this package
+/// supplements (a weak reverse requirement).
+const TAG_SUPPLEMENTNAME: i32 = 5052;
+const TAG_SUPPLEMENTVERSION: i32 = 5053;
+const TAG_SUPPLEMENTFLAGS: i32 = 5054;
+/// Optional tag for the names of any dependencies that this package enhances
+/// (a very weak reverse requirement).
+const TAG_ENHANCENAME: i32 = 5055;
+const TAG_ENHANCEVERSION: i32 = 5056;
+const TAG_ENHANCEFLAGS: i32 = 5057;
+
+/// Optional tags for scripts that are triggered by other packages, with the
+/// names, versions, and flags of the triggering packages.  `TRIGGERINDEX`
+/// gives the index of the script for each triggering package.
+const TAG_TRIGGERSCRIPTS: i32 = 1065;
+const TAG_TRIGGERNAME: i32 = 1066;
+const TAG_TRIGGERVERSION: i32 = 1067;
+const TAG_TRIGGERFLAGS: i32 = 1068;
+const TAG_TRIGGERINDEX: i32 = 1069;
+const TAG_TRIGGERSCRIPTPROG: i32 = 1092;
+const TAG_TRIGGERSCRIPTFLAGS: i32 = 5027;
+/// Optional tags for scripts that are triggered by files being installed or
+/// removed, with the path prefixes that trigger them (run once per package).
+const TAG_FILETRIGGERSCRIPTS: i32 = 5066;
+const TAG_FILETRIGGERSCRIPTPROG: i32 = 5067;
+const TAG_FILETRIGGERSCRIPTFLAGS: i32 = 5068;
+const TAG_FILETRIGGERNAME: i32 = 5069;
+const TAG_FILETRIGGERINDEX: i32 = 5070;
+const TAG_FILETRIGGERVERSION: i32 = 5071;
+const TAG_FILETRIGGERFLAGS: i32 = 5072;
+const TAG_FILETRIGGERPRIORITIES: i32 = 5081;
+/// Optional tags for file-triggered scripts that are run once per
+/// transaction.
+const TAG_TRANSFILETRIGGERSCRIPTS: i32 = 5073;
+const TAG_TRANSFILETRIGGERSCRIPTPROG: i32 = 5074;
+const TAG_TRANSFILETRIGGERSCRIPTFLAGS: i32 = 5075;
+const TAG_TRANSFILETRIGGERNAME: i32 = 5076;
+const TAG_TRANSFILETRIGGERINDEX: i32 = 5077;
+const TAG_TRANSFILETRIGGERVERSION: i32 = 5078;
+const TAG_TRANSFILETRIGGERFLAGS: i32 = 5079;
+const TAG_TRANSFILETRIGGERPRIORITIES: i32 = 5082;
 
 /// Optional tag for the timestamp (in seconds since the epoch) when the
 /// package was built.
@@ -123,9 +239,11 @@ const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
     (true,  "NAME",         TAG_NAME,         IndexType::String,     None),
     (true,  "VERSION",      TAG_VERSION,      IndexType::String,     None),
     (true,  "RELEASE",      TAG_RELEASE,      IndexType::String,     None),
+    (false, "EPOCH",        TAG_EPOCH,        IndexType::Int32,      Some(1)),
     (true,  "SUMMARY",      TAG_SUMMARY,      IndexType::I18nString, None),
     (true,  "DESCRIPTION",  TAG_DESCRIPTION,  IndexType::I18nString, None),
-    (true,  "SIZE",         TAG_SIZE,         IndexType::Int32,      Some(1)),
+    (false, "SIZE",         TAG_SIZE,         IndexType::Int32,      Some(1)),
+    (false, "LONGSIZE",     TAG_LONGSIZE,     IndexType::Int64,      Some(1)),
     (false, "VENDOR",       TAG_VENDOR,       IndexType::String,     None),
     (true,  "LICENSE",      TAG_LICENSE,      IndexType::String,     None),
     (true,  "GROUP",        TAG_GROUP,        IndexType::I18nString, None),
@@ -137,6 +255,10 @@ const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
     (true,  "PAYLOADCOMPRESSOR", TAG_PAYLOADCOMPRESSOR,
      IndexType::String, None),
     (true,  "PAYLOADFLAGS", TAG_PAYLOADFLAGS, IndexType::String,     None),
+    (false, "PAYLOADDIGEST", TAG_PAYLOADDIGEST, IndexType::StringArray,
+     Some(1)),
+    (false, "PAYLOADDIGESTALGO", TAG_PAYLOADDIGESTALGO,
+     IndexType::Int32, Some(1)),
     // Installation information:
     (false, "PREIN",      TAG_PREIN,      IndexType::String, None),
     (false, "POSTIN",     TAG_POSTIN,     IndexType::String, None),
@@ -146,13 +268,32 @@ const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
     (false, "POSTINPROG", TAG_POSTINPROG, IndexType::String, None),
     (false, "PREUNPROG",  TAG_PREUNPROG,  IndexType::String, None),
     (false, "POSTUNPROG", TAG_POSTUNPROG, IndexType::String, None),
+    (false, "PRETRANS",       TAG_PRETRANS,       IndexType::String, None),
+    (false, "POSTTRANS",      TAG_POSTTRANS,      IndexType::String, None),
+    (false, "PRETRANSPROG",   TAG_PRETRANSPROG,   IndexType::String, None),
+    (false, "POSTTRANSPROG",  TAG_POSTTRANSPROG,  IndexType::String, None),
+    (false, "PREUNTRANS",     TAG_PREUNTRANS,     IndexType::String, None),
+    (false, "POSTUNTRANS",    TAG_POSTUNTRANS,    IndexType::String, None),
+    (false, "PREUNTRANSPROG", TAG_PREUNTRANSPROG, IndexType::String, None),
+    (false, "POSTUNTRANSPROG", TAG_POSTUNTRANSPROG, IndexType::String, None),
+    (false, "PREINFLAGS",     TAG_PREINFLAGS,     IndexType::Int32, Some(1)),
+    (false, "POSTINFLAGS",    TAG_POSTINFLAGS,    IndexType::Int32, Some(1)),
+    (false, "PREUNFLAGS",     TAG_PREUNFLAGS,     IndexType::Int32, Some(1)),
+    (false, "POSTUNFLAGS",    TAG_POSTUNFLAGS,    IndexType::Int32, Some(1)),
+    (false, "PRETRANSFLAGS",  TAG_PRETRANSFLAGS,  IndexType::Int32, Some(1)),
+    (false, "POSTTRANSFLAGS", TAG_POSTTRANSFLAGS, IndexType::Int32, Some(1)),
+    (false, "PREUNTRANSFLAGS", TAG_PREUNTRANSFLAGS, IndexType::Int32,
+     Some(1)),
+    (false, "POSTUNTRANSFLAGS", TAG_POSTUNTRANSFLAGS, IndexType::Int32,
+     Some(1)),
     // File information:
     (false, "OLDFILENAMES",  TAG_OLDFILENAMES,  IndexType::StringArray, None),
-    (true,  "FILESIZES",     TAG_FILESIZES,     IndexType::Int32,       None),
+    (false, "FILESIZES",     TAG_FILESIZES,     IndexType::Int32,       None),
+    (false, "LONGFILESIZES", TAG_LONGFILESIZES, IndexType::Int64,       None),
     (true,  "FILEMODES",     TAG_FILEMODES,     IndexType::Int16,       None),
     (true,  "FILERDEVS",     TAG_FILERDEVS,     IndexType::Int16,       None),
     (true,  "FILEMTIMES",    TAG_FILEMTIMES,    IndexType::Int32,       None),
-    (true,  "FILEMD5S",      TAG_FILEMD5S,      IndexType::StringArray, None),
+    (true,  "FILEDIGESTS",   TAG_FILEDIGESTS,   IndexType::StringArray, None),
     (true,  "FILELINKTOS",   TAG_FILELINKTOS,   IndexType::StringArray, None),
     (true,  "FILEFLAGS",     TAG_FILEFLAGS,     IndexType::Int32,       None),
     (true,  "FILEUSERNAME",  TAG_FILEUSERNAME,  IndexType::StringArray, None),
@@ -163,6 +304,7 @@ const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
     (false, "DIRINDEXES",    TAG_DIRINDEXES,    IndexType::Int32,       None),
     (false, "BASENAMES",     TAG_BASENAMES,     IndexType::StringArray, None),
     (false, "DIRNAMES",      TAG_DIRNAMES,      IndexType::StringArray, None),
+    (false, "FILEDIGESTALGO", TAG_FILEDIGESTALGO, IndexType::Int32,  Some(1)),
     // Dependency information:
     (true,  "PROVIDENAME",   TAG_PROVIDENAME,   IndexType::StringArray, None),
     (true,  "PROVIDEFLAGS",  TAG_PROVIDEFLAGS,  IndexType::Int32,       None),
@@ -176,6 +318,63 @@ const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
     (false, "OBSOLETENAME",  TAG_OBSOLETENAME,  IndexType::StringArray, None),
     (false, "OBSOLETEFLAGS", TAG_OBSOLETEFLAGS, IndexType::Int32,       None),
     (false,"OBSOLETEVERSION",TAG_OBSOLETEVERSION,IndexType::StringArray,None),
+    (false, "RECOMMENDNAME", TAG_RECOMMENDNAME, IndexType::StringArray, None),
+    (false,"RECOMMENDVERSION",TAG_RECOMMENDVERSION,IndexType::StringArray,
+     None),
+    (false, "RECOMMENDFLAGS",TAG_RECOMMENDFLAGS,IndexType::Int32,       None),
+    (false, "SUGGESTNAME",   TAG_SUGGESTNAME,   IndexType::StringArray, None),
+    (false, "SUGGESTVERSION",TAG_SUGGESTVERSION,IndexType::StringArray, None),
+    (false, "SUGGESTFLAGS",  TAG_SUGGESTFLAGS,  IndexType::Int32,       None),
+    (false,"SUPPLEMENTNAME", TAG_SUPPLEMENTNAME,IndexType::StringArray, None),
+    (false,"SUPPLEMENTVERSION",TAG_SUPPLEMENTVERSION,IndexType::StringArray,
+     None),
+    (false,"SUPPLEMENTFLAGS",TAG_SUPPLEMENTFLAGS,IndexType::Int32,      None),
+    (false, "ENHANCENAME",   TAG_ENHANCENAME,   IndexType::StringArray, None),
+    (false, "ENHANCEVERSION",TAG_ENHANCEVERSION,IndexType::StringArray, None),
+    (false, "ENHANCEFLAGS",  TAG_ENHANCEFLAGS,  IndexType::Int32,       None),
+    // Trigger information:
+    (false, "TRIGGERSCRIPTS", TAG_TRIGGERSCRIPTS, IndexType::StringArray,
+     None),
+    (false, "TRIGGERNAME",   TAG_TRIGGERNAME,   IndexType::StringArray, None),
+    (false, "TRIGGERVERSION",TAG_TRIGGERVERSION,IndexType::StringArray, None),
+    (false, "TRIGGERFLAGS",  TAG_TRIGGERFLAGS,  IndexType::Int32,       None),
+    (false, "TRIGGERINDEX",  TAG_TRIGGERINDEX,  IndexType::Int32,       None),
+    (false, "TRIGGERSCRIPTPROG", TAG_TRIGGERSCRIPTPROG,
+     IndexType::StringArray, None),
+    (false, "TRIGGERSCRIPTFLAGS", TAG_TRIGGERSCRIPTFLAGS, IndexType::Int32,
+     None),
+    (false, "FILETRIGGERSCRIPTS", TAG_FILETRIGGERSCRIPTS,
+     IndexType::StringArray, None),
+    (false, "FILETRIGGERSCRIPTPROG", TAG_FILETRIGGERSCRIPTPROG,
+     IndexType::StringArray, None),
+    (false, "FILETRIGGERSCRIPTFLAGS", TAG_FILETRIGGERSCRIPTFLAGS,
+     IndexType::Int32, None),
+    (false, "FILETRIGGERNAME", TAG_FILETRIGGERNAME, IndexType::StringArray,
+     None),
+    (false, "FILETRIGGERINDEX", TAG_FILETRIGGERINDEX, IndexType::Int32,
+     None),
+    (false, "FILETRIGGERVERSION", TAG_FILETRIGGERVERSION,
+     IndexType::StringArray, None),
+    (false, "FILETRIGGERFLAGS", TAG_FILETRIGGERFLAGS, IndexType::Int32,
+     None),
+    (false, "FILETRIGGERPRIORITIES", TAG_FILETRIGGERPRIORITIES,
+     IndexType::Int32, None),
+    (false, "TRANSFILETRIGGERSCRIPTS", TAG_TRANSFILETRIGGERSCRIPTS,
+     IndexType::StringArray, None),
+    (false, "TRANSFILETRIGGERSCRIPTPROG", TAG_TRANSFILETRIGGERSCRIPTPROG,
+     IndexType::StringArray, None),
+    (false, "TRANSFILETRIGGERSCRIPTFLAGS", TAG_TRANSFILETRIGGERSCRIPTFLAGS,
+     IndexType::Int32, None),
+    (false, "TRANSFILETRIGGERNAME", TAG_TRANSFILETRIGGERNAME,
+     IndexType::StringArray, None),
+    (false, "TRANSFILETRIGGERINDEX", TAG_TRANSFILETRIGGERINDEX,
+     IndexType::Int32, None),
+    (false, "TRANSFILETRIGGERVERSION", TAG_TRANSFILETRIGGERVERSION,
+     IndexType::StringArray, None),
+    (false, "TRANSFILETRIGGERFLAGS", TAG_TRANSFILETRIGGERFLAGS,
+     IndexType::Int32, None),
+    (false, "TRANSFILETRIGGERPRIORITIES", TAG_TRANSFILETRIGGERPRIORITIES,
+     IndexType::Int32, None),
     // Other information:
     (false, "BUILDTIME",     TAG_BUILDTIME,     IndexType::Int32,    Some(1)),
     (false, "BUILDHOST",     TAG_BUILDHOST,     IndexType::String,      None),
@@ -186,21 +385,211 @@ const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
     (false, "OPTFLAGS",      TAG_OPTFLAGS,      IndexType::String,      None),
 ];
 
+// Known kinds of dependency entries in the Header section, as the names and
+// tags of their NAME, FLAGS, and VERSION entries.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const DEPENDENCY_ENTRIES: &[(&str, i32, &str, i32, &str, i32)] = &[
+    ("PROVIDENAME",  TAG_PROVIDENAME,  "PROVIDEFLAGS",  TAG_PROVIDEFLAGS,
+     "PROVIDEVERSION",  TAG_PROVIDEVERSION),
+    ("REQUIRENAME",  TAG_REQUIRENAME,  "REQUIREFLAGS",  TAG_REQUIREFLAGS,
+     "REQUIREVERSION",  TAG_REQUIREVERSION),
+    ("CONFLICTNAME", TAG_CONFLICTNAME, "CONFLICTFLAGS", TAG_CONFLICTFLAGS,
+     "CONFLICTVERSION", TAG_CONFLICTVERSION),
+    ("OBSOLETENAME", TAG_OBSOLETENAME, "OBSOLETEFLAGS", TAG_OBSOLETEFLAGS,
+     "OBSOLETEVERSION", TAG_OBSOLETEVERSION),
+    ("RECOMMENDNAME", TAG_RECOMMENDNAME, "RECOMMENDFLAGS", TAG_RECOMMENDFLAGS,
+     "RECOMMENDVERSION", TAG_RECOMMENDVERSION),
+    ("SUGGESTNAME", TAG_SUGGESTNAME, "SUGGESTFLAGS", TAG_SUGGESTFLAGS,
+     "SUGGESTVERSION", TAG_SUGGESTVERSION),
+    ("SUPPLEMENTNAME", TAG_SUPPLEMENTNAME,
+     "SUPPLEMENTFLAGS", TAG_SUPPLEMENTFLAGS,
+     "SUPPLEMENTVERSION", TAG_SUPPLEMENTVERSION),
+    ("ENHANCENAME", TAG_ENHANCENAME, "ENHANCEFLAGS", TAG_ENHANCEFLAGS,
+     "ENHANCEVERSION", TAG_ENHANCEVERSION),
+];
+
+// The NAME, FLAGS, and VERSION tags for each kind of dependency.
+const PROVIDE_TAGS: (i32, i32, i32) =
+    (TAG_PROVIDENAME, TAG_PROVIDEFLAGS, TAG_PROVIDEVERSION);
+const REQUIRE_TAGS: (i32, i32, i32) =
+    (TAG_REQUIRENAME, TAG_REQUIREFLAGS, TAG_REQUIREVERSION);
+const CONFLICT_TAGS: (i32, i32, i32) =
+    (TAG_CONFLICTNAME, TAG_CONFLICTFLAGS, TAG_CONFLICTVERSION);
+const OBSOLETE_TAGS: (i32, i32, i32) =
+    (TAG_OBSOLETENAME, TAG_OBSOLETEFLAGS, TAG_OBSOLETEVERSION);
+const RECOMMEND_TAGS: (i32, i32, i32) =
+    (TAG_RECOMMENDNAME, TAG_RECOMMENDFLAGS, TAG_RECOMMENDVERSION);
+const SUGGEST_TAGS: (i32, i32, i32) =
+    (TAG_SUGGESTNAME, TAG_SUGGESTFLAGS, TAG_SUGGESTVERSION);
+const SUPPLEMENT_TAGS: (i32, i32, i32) =
+    (TAG_SUPPLEMENTNAME, TAG_SUPPLEMENTFLAGS, TAG_SUPPLEMENTVERSION);
+const ENHANCE_TAGS: (i32, i32, i32) =
+    (TAG_ENHANCENAME, TAG_ENHANCEFLAGS, TAG_ENHANCEVERSION);
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 const INSTALLATION_ENTRIES: &[(&str, i32, &str, i32)] = &[
     ("PREIN",  TAG_PREIN,  "PREINPROG",  TAG_PREINPROG),
     ("POSTIN", TAG_POSTIN, "POSTINPROG", TAG_POSTINPROG),
     ("PREUN",  TAG_PREUN,  "PREUNPROG",  TAG_PREUNPROG),
     ("POSTUN", TAG_POSTUN, "POSTUNPROG", TAG_POSTUNPROG),
+    ("PRETRANS",    TAG_PRETRANS,    "PRETRANSPROG",    TAG_PRETRANSPROG),
+    ("POSTTRANS",   TAG_POSTTRANS,   "POSTTRANSPROG",   TAG_POSTTRANSPROG),
+    ("PREUNTRANS",  TAG_PREUNTRANS,  "PREUNTRANSPROG",  TAG_PREUNTRANSPROG),
+    ("POSTUNTRANS", TAG_POSTUNTRANS, "POSTUNTRANSPROG", TAG_POSTUNTRANSPROG),
 ];
 
+/// The names and tags of the entries for one kind of trigger.
+struct TriggerEntries {
+    prefix: &'static str,
+    scripts: i32,
+    script_prog: i32,
+    script_flags: i32,
+    name: i32,
+    version: i32,
+    flags: i32,
+    index: i32,
+    priorities: Option<i32>,
+}
+
+const TRIGGER_ENTRIES: TriggerEntries = TriggerEntries {
+    prefix: "TRIGGER",
+    scripts: TAG_TRIGGERSCRIPTS,
+    script_prog: TAG_TRIGGERSCRIPTPROG,
+    script_flags: TAG_TRIGGERSCRIPTFLAGS,
+    name: TAG_TRIGGERNAME,
+    version: TAG_TRIGGERVERSION,
+    flags: TAG_TRIGGERFLAGS,
+    index: TAG_TRIGGERINDEX,
+    priorities: None,
+};
+
+const FILE_TRIGGER_ENTRIES: TriggerEntries = TriggerEntries {
+    prefix: "FILETRIGGER",
+    scripts: TAG_FILETRIGGERSCRIPTS,
+    script_prog: TAG_FILETRIGGERSCRIPTPROG,
+    script_flags: TAG_FILETRIGGERSCRIPTFLAGS,
+    name: TAG_FILETRIGGERNAME,
+    version: TAG_FILETRIGGERVERSION,
+    flags: TAG_FILETRIGGERFLAGS,
+    index: TAG_FILETRIGGERINDEX,
+    priorities: Some(TAG_FILETRIGGERPRIORITIES),
+};
+
+const TRANS_FILE_TRIGGER_ENTRIES: TriggerEntries = TriggerEntries {
+    prefix: "TRANSFILETRIGGER",
+    scripts: TAG_TRANSFILETRIGGERSCRIPTS,
+    script_prog: TAG_TRANSFILETRIGGERSCRIPTPROG,
+    script_flags: TAG_TRANSFILETRIGGERSCRIPTFLAGS,
+    name: TAG_TRANSFILETRIGGERNAME,
+    version: TAG_TRANSFILETRIGGERVERSION,
+    flags: TAG_TRANSFILETRIGGERFLAGS,
+    index: TAG_TRANSFILETRIGGERINDEX,
+    priorities: Some(TAG_TRANSFILETRIGGERPRIORITIES),
+};
+
+const ALL_TRIGGER_ENTRIES: &[TriggerEntries] =
+    &[TRIGGER_ENTRIES, FILE_TRIGGER_ENTRIES, TRANS_FILE_TRIGGER_ENTRIES];
+
+impl TriggerEntries {
+    fn dependency_tags(&self) -> (i32, i32, i32) {
+        (self.name, self.flags, self.version)
+    }
+
+    /// Checks that the script entries all have the same count, that the
+    /// condition entries all have the same count, and that each
+    /// condition's index refers to a script.
+    fn validate(&self, table: &IndexTable) -> io::Result<()> {
+        let name = |suffix: &str| format!("{}{}", self.prefix, suffix);
+        let script_count =
+            table.get(self.scripts).map(IndexValue::count).unwrap_or(0);
+        let mut script_tags = vec![("SCRIPTPROG", self.script_prog)];
+        if table.has(self.script_flags) {
+            script_tags.push(("SCRIPTFLAGS", self.script_flags));
+        }
+        if let Some(tag) = self.priorities {
+            if table.has(tag) {
+                script_tags.push(("PRIORITIES", tag));
+            }
+        }
+        for (suffix, tag) in script_tags {
+            table
+                .expect_same_counts(SECTION,
+                                    &name("SCRIPTS"),
+                                    self.scripts,
+                                    script_count,
+                                    &name(suffix),
+                                    tag)?;
+        }
+        let condition_count =
+            table.get(self.name).map(IndexValue::count).unwrap_or(0);
+        for &(suffix, tag) in &[("VERSION", self.version),
+                                ("FLAGS", self.flags),
+                                ("INDEX", self.index)]
+        {
+            table
+                .expect_same_counts(SECTION,
+                                    &name("NAME"),
+                                    self.name,
+                                    condition_count,
+                                    &name(suffix),
+                                    tag)?;
+        }
+        if let Some(&IndexValue::Int32(ref values)) = table.get(self.index) {
+            for &value in values.iter() {
+                if (value as usize) >= script_count {
+                    invalid_data!("Invalid value ({}) in {} entry (tag {}) \
+                                   in {} section ({} entry (tag {}) count \
+                                   is {})",
+                                  value,
+                                  name("INDEX"),
+                                  self.index,
+                                  SECTION,
+                                  name("SCRIPTS"),
+                                  self.scripts,
+                                  script_count);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the body, interpreter, and flags tags for a type of scriptlet.
+fn scriptlet_tags(scriptlet_type: ScriptletType) -> (i32, i32, i32) {
+    match scriptlet_type {
+        ScriptletType::PreInstall => {
+            (TAG_PREIN, TAG_PREINPROG, TAG_PREINFLAGS)
+        }
+        ScriptletType::PostInstall => {
+            (TAG_POSTIN, TAG_POSTINPROG, TAG_POSTINFLAGS)
+        }
+        ScriptletType::PreUninstall => {
+            (TAG_PREUN, TAG_PREUNPROG, TAG_PREUNFLAGS)
+        }
+        ScriptletType::PostUninstall => {
+            (TAG_POSTUN, TAG_POSTUNPROG, TAG_POSTUNFLAGS)
+        }
+        ScriptletType::PreTransaction => {
+            (TAG_PRETRANS, TAG_PRETRANSPROG, TAG_PRETRANSFLAGS)
+        }
+        ScriptletType::PostTransaction => {
+            (TAG_POSTTRANS, TAG_POSTTRANSPROG, TAG_POSTTRANSFLAGS)
+        }
+        ScriptletType::PreUninstallTransaction => {
+            (TAG_PREUNTRANS, TAG_PREUNTRANSPROG, TAG_PREUNTRANSFLAGS)
+        }
+        ScriptletType::PostUninstallTransaction => {
+            (TAG_POSTUNTRANS, TAG_POSTUNTRANSPROG, TAG_POSTUNTRANSFLAGS)
+        }
+    }
+}
+
 #[cfg_attr(rustfmt, rustfmt_skip)]
 const FILE_ENTRIES: &[(&str, i32)] = &[
-    ("FILESIZES",     TAG_FILESIZES),
     ("FILEMODES",     TAG_FILEMODES),
     ("FILERDEVS",     TAG_FILERDEVS),
     ("FILEMTIMES",    TAG_FILEMTIMES),
-    ("FILEMD5S",      TAG_FILEMD5S),
+    ("FILEDIGESTS",   TAG_FILEDIGESTS),
     ("FILELINKTOS",   TAG_FILELINKTOS),
     ("FILEFLAGS",     TAG_FILEFLAGS),
     ("FILEUSERNAME",  TAG_FILEUSERNAME),
@@ -210,6 +599,23 @@ const FILE_ENTRIES: &[(&str, i32)] = &[
     ("FILELANGS",     TAG_FILELANGS),
 ];
 
+/// Size tags that have a 64-bit alternative; exactly one of each pair must be
+/// present.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const SIZE_ENTRIES: &[(&str, i32, &str, i32)] = &[
+    ("SIZE",      TAG_SIZE,      "LONGSIZE",      TAG_LONGSIZE),
+    ("FILESIZES", TAG_FILESIZES, "LONGFILESIZES", TAG_LONGFILESIZES),
+];
+
+/// File metadata tags that may be omitted, but must have one value per file
+/// if present.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const OPTIONAL_FILE_ENTRIES: &[(&str, i32)] = &[
+    ("FILESIZES",       TAG_FILESIZES),
+    ("LONGFILESIZES",   TAG_LONGFILESIZES),
+    ("FILEVERIFYFLAGS", TAG_FILEVERIFYFLAGS),
+];
+
 // ========================================================================= //
 
 /// The name of this section.
@@ -217,7 +623,42 @@ const SECTION: &str = "Header";
 
 /// Can be listed under `TAG_REQUIRENAME` to indicate that we're not using
 /// `TAG_OLDFILENAMES`.
-const REQUIRE_COMPRESSED_FILE_NAMES: &str = "rpmlib(CompressedFileNames)";
+const REQUIRE_COMPRESSED_FILE_NAMES: (&str, &str) =
+    ("rpmlib(CompressedFileNames)", "3.0.4-1");
+
+/// Must be listed under `TAG_REQUIRENAME` if the file digests use an
+/// algorithm other than MD5.
+const REQUIRE_FILE_DIGESTS: (&str, &str) = ("rpmlib(FileDigests)", "4.6.0-1");
+
+/// Must be listed under `TAG_REQUIRENAME` if any dependency uses a rich
+/// (boolean) dependency expression.
+const REQUIRE_RICH_DEPENDENCIES: (&str, &str) =
+    ("rpmlib(RichDependencies)", "4.12.0-1");
+
+/// Must be listed under `TAG_REQUIRENAME` if the package has any file
+/// triggers.
+const REQUIRE_FILE_TRIGGERS: (&str, &str) =
+    ("rpmlib(FileTriggers)", "4.12.0-1");
+
+/// Must be listed under `TAG_REQUIRENAME` if the package uses
+/// `TAG_LONGFILESIZES` (that is, has any files of 4 GiB or larger).
+const REQUIRE_LARGE_FILES: (&str, &str) = ("rpmlib(LargeFiles)", "4.12.0-1");
+
+/// The hash algorithm used for file digests in new packages, by default.
+const DEFAULT_FILE_DIGEST_ALGORITHM: DigestAlgorithm = DigestAlgorithm::Sha256;
+
+/// Mask for the file type bits of a Unix mode.
+const S_IFMT: u16 = 0o170000;
+/// File type bits for a regular file.
+const S_IFREG: u16 = 0o100000;
+/// File type bits for a directory.
+const S_IFDIR: u16 = 0o040000;
+/// File type bits for a symbolic link.
+const S_IFLNK: u16 = 0o120000;
+/// File type bits for a character device.
+const S_IFCHR: u16 = 0o020000;
+/// File type bits for a block device.
+const S_IFBLK: u16 = 0o060000;
 
 /// The required value under `TAG_OS`.
 const OS_STRING: &str = "linux";
@@ -231,6 +672,8 @@ const PAYLOAD_FORMAT: &str = "cpio";
 pub struct HeaderSection {
     table: IndexTable,
     use_old_filenames: bool,
+    /// The `rpmlib()` feature required by the payload compressor, if any.
+    payload_feature: Option<(String, String)>,
 }
 
 impl HeaderSection {
@@ -238,6 +681,7 @@ impl HeaderSection {
         let mut table = IndexTable::new();
         table.set_locales(vec!["C".to_string()]);
         table.set(TAG_SIZE, IndexValue::Int32(vec![0]));
+        table.set(TAG_FILESIZES, IndexValue::Int32(Vec::new()));
         table.set(TAG_GROUP,
                   IndexValue::I18nString(vec!["Unspecified".to_string()]));
         table.set(TAG_OS, IndexValue::String(OS_STRING.to_string()));
@@ -246,7 +690,12 @@ impl HeaderSection {
         table.set(TAG_PAYLOADCOMPRESSOR,
                   IndexValue::String("gzip".to_string()));
         table.set(TAG_PAYLOADFLAGS, IndexValue::String("9".to_string()));
-        table.set(TAG_OLDFILENAMES, IndexValue::StringArray(Vec::new()));
+        table.set(TAG_DIRNAMES, IndexValue::StringArray(Vec::new()));
+        table.set(TAG_BASENAMES, IndexValue::StringArray(Vec::new()));
+        table.set(TAG_DIRINDEXES, IndexValue::Int32(Vec::new()));
+        table.set(TAG_FILEDIGESTALGO,
+                  IndexValue::Int32(vec![DEFAULT_FILE_DIGEST_ALGORITHM
+                                             .number()]));
         for &(required, _, tag, itype, _) in ENTRIES {
             if required && !table.has(tag) {
                 table.set(tag, itype.default_value());
@@ -254,7 +703,8 @@ impl HeaderSection {
         }
         HeaderSection {
             table,
-            use_old_filenames: true,
+            use_old_filenames: false,
+            payload_feature: None,
         }
     }
 
@@ -267,6 +717,26 @@ impl HeaderSection {
         for &(required, name, tag, itype, count) in ENTRIES.iter() {
             table.expect_type(SECTION, required, name, tag, itype, count)?;
         }
+        for &(name, tag, long_name, long_tag) in SIZE_ENTRIES.iter() {
+            match (table.has(tag), table.has(long_tag)) {
+                (false, false) => {
+                    invalid_data!("Missing {} entry (tag {}) in {} section",
+                                  name,
+                                  tag,
+                                  SECTION);
+                }
+                (true, true) => {
+                    invalid_data!("{} section has both {} (tag {}) and {} \
+                                   (tag {}) entries",
+                                  SECTION,
+                                  name,
+                                  tag,
+                                  long_name,
+                                  long_tag);
+                }
+                _ => {}
+            }
+        }
 
         // Validate package information:
         table.expect_string_value(SECTION, "OS", TAG_OS, OS_STRING)?;
@@ -275,6 +745,12 @@ impl HeaderSection {
                                  "PAYLOADFORMAT",
                                  TAG_PAYLOADFORMAT,
                                  PAYLOAD_FORMAT)?;
+        if table.has(TAG_PAYLOADDIGEST) && !table.has(TAG_PAYLOADDIGESTALGO) {
+            invalid_data!("Missing PAYLOADDIGESTALGO entry (tag {}) in {} \
+                           section (since using PAYLOADDIGEST)",
+                          TAG_PAYLOADDIGESTALGO,
+                          SECTION);
+        }
 
         // Validate installation information:
         for &(name1, tag1, name2, tag2) in INSTALLATION_ENTRIES.iter() {
@@ -288,26 +764,31 @@ impl HeaderSection {
             }
         }
 
+        // Validate trigger information:
+        for entries in ALL_TRIGGER_ENTRIES.iter() {
+            entries.validate(&table)?;
+        }
+
         // Validate dependency information:
+        for &(name, name_tag, flags_name, flags_tag, version_name, version_tag)
+            in DEPENDENCY_ENTRIES.iter()
         {
-            let requirename_count =
-                table.get(TAG_REQUIRENAME).unwrap().count();
-            let requireflags_count =
-                table.get(TAG_REQUIREFLAGS).unwrap().count();
-            let requireversion_count =
-                table.get(TAG_REQUIREVERSION).unwrap().count();
-            if requireflags_count != requirename_count {
-                invalid_data!("Counts for REQUIRENAME and REQUIREFLAGS \
-                               entries don't match ({} vs. {})",
-                              requirename_count,
-                              requireflags_count);
-            }
-            if requireversion_count != requirename_count {
-                invalid_data!("Counts for REQUIRENAME and REQUIREVERSION \
-                               entries don't match ({} vs. {})",
-                              requirename_count,
-                              requireversion_count);
-            }
+            let count =
+                table.get(name_tag).map(IndexValue::count).unwrap_or(0);
+            table
+                .expect_same_counts(SECTION,
+                                    name,
+                                    name_tag,
+                                    count,
+                                    flags_name,
+                                    flags_tag)?;
+            table
+                .expect_same_counts(SECTION,
+                                    name,
+                                    name_tag,
+                                    count,
+                                    version_name,
+                                    version_tag)?;
         }
 
         // Validate file information:
@@ -315,7 +796,7 @@ impl HeaderSection {
             !table
                 .get_strings(TAG_REQUIRENAME)
                 .unwrap()
-                .contains(&REQUIRE_COMPRESSED_FILE_NAMES.to_string());
+                .contains(&REQUIRE_COMPRESSED_FILE_NAMES.0.to_string());
         if use_old_filenames {
             let file_count = match table.get(TAG_OLDFILENAMES) {
                 Some(value) => value.count(),
@@ -325,7 +806,7 @@ impl HeaderSection {
                                   "OLDFILENAMES",
                                   TAG_OLDFILENAMES,
                                   SECTION,
-                                  REQUIRE_COMPRESSED_FILE_NAMES);
+                                  REQUIRE_COMPRESSED_FILE_NAMES.0);
                 }
             };
             for &(name, tag) in FILE_ENTRIES.iter() {
@@ -337,6 +818,17 @@ impl HeaderSection {
                                         name,
                                         tag)?;
             }
+            for &(name, tag) in OPTIONAL_FILE_ENTRIES.iter() {
+                if table.has(tag) {
+                    table
+                        .expect_same_counts(SECTION,
+                                            "OLDFILENAMES",
+                                            TAG_OLDFILENAMES,
+                                            file_count,
+                                            name,
+                                            tag)?;
+                }
+            }
         } else {
             let dir_count = match table.get(TAG_DIRNAMES) {
                 Some(value) => value.count(),
@@ -345,7 +837,7 @@ impl HeaderSection {
                                    section (since using {})",
                                   TAG_DIRNAMES,
                                   SECTION,
-                                  REQUIRE_COMPRESSED_FILE_NAMES);
+                                  REQUIRE_COMPRESSED_FILE_NAMES.0);
                 }
             };
             let file_count = match table.get(TAG_BASENAMES) {
@@ -355,7 +847,7 @@ impl HeaderSection {
                                    section (since using {})",
                                   TAG_BASENAMES,
                                   SECTION,
-                                  REQUIRE_COMPRESSED_FILE_NAMES);
+                                  REQUIRE_COMPRESSED_FILE_NAMES.0);
                 }
             };
             match table.get(TAG_DIRINDEXES) {
@@ -380,7 +872,7 @@ impl HeaderSection {
                                   "DIRINDEXES",
                                   TAG_DIRINDEXES,
                                   SECTION,
-                                  REQUIRE_COMPRESSED_FILE_NAMES);
+                                  REQUIRE_COMPRESSED_FILE_NAMES.0);
                 }
             }
             table
@@ -399,16 +891,124 @@ impl HeaderSection {
                                         name,
                                         tag)?;
             }
+            for &(name, tag) in OPTIONAL_FILE_ENTRIES.iter() {
+                if table.has(tag) {
+                    table
+                        .expect_same_counts(SECTION,
+                                            "BASENAMES",
+                                            TAG_BASENAMES,
+                                            file_count,
+                                            name,
+                                            tag)?;
+                }
+            }
         }
 
         Ok(HeaderSection {
                table,
                use_old_filenames,
+               payload_feature: None,
            })
     }
 
-    pub(crate) fn done_adding_fields(&mut self) {
+    pub(crate) fn done_adding_fields(&mut self) -> io::Result<()> {
+        for dependency in self.all_dependencies().iter() {
+            if let Err(error) = dependency.to_rich() {
+                invalid_input!("{}", error);
+            }
+        }
+        for entries in ALL_TRIGGER_ENTRIES.iter() {
+            let script_count = self.table
+                .get(entries.scripts)
+                .map(IndexValue::count)
+                .unwrap_or(0);
+            for script_index in 0..script_count {
+                let has_condition = match self.table.get(entries.index) {
+                    Some(&IndexValue::Int32(ref values)) => {
+                        values.contains(&(script_index as u32))
+                    }
+                    _ => false,
+                };
+                if !has_condition {
+                    invalid_input!("Trigger has no conditions");
+                }
+            }
+            // Only regular triggers can be of type PreInstall; rpm doesn't
+            // support %filetriggerprein or %transfiletriggerprein.
+            if entries.priorities.is_some() {
+                let tags = entries.dependency_tags();
+                if DependencyIter::new(&self.table, tags).any(|dep| {
+                    dep.flags().contains(DependencyFlags::TRIGGERPREIN)
+                })
+                {
+                    invalid_input!("File triggers cannot be of type \
+                                    PreInstall");
+                }
+            }
+        }
+        self.add_automatic_dependencies();
         self.table.add_immutable_index();
+        Ok(())
+    }
+
+    /// Returns a list of every kind of dependency in the header.
+    fn all_dependencies(&self) -> Vec<Dependency> {
+        let mut dependencies = Vec::new();
+        for &(_, name_tag, _, flags_tag, _, version_tag) in
+            DEPENDENCY_ENTRIES.iter()
+        {
+            let tags = (name_tag, flags_tag, version_tag);
+            dependencies.extend(DependencyIter::new(&self.table, tags));
+        }
+        dependencies
+    }
+
+    /// Adds the dependencies that rpm expects every package to declare: the
+    /// package providing itself, and any `rpmlib()` features it uses.
+    fn add_automatic_dependencies(&mut self) {
+        let package_name = self.package_name().to_string();
+        if !package_name.is_empty() &&
+            !self.provides().any(|dep| dep.name() == package_name)
+        {
+            let evr = self.evr().to_string();
+            self.add_provides(Dependency::equal(package_name, evr));
+        }
+        let payload_feature = self.payload_feature.clone();
+        let mut features = Vec::new();
+        if !self.use_old_filenames {
+            features.push(REQUIRE_COMPRESSED_FILE_NAMES);
+        }
+        if self.file_digest_algorithm() != Some(DigestAlgorithm::Md5) {
+            features.push(REQUIRE_FILE_DIGESTS);
+        }
+        if self.table.has(TAG_FILETRIGGERSCRIPTS) ||
+            self.table.has(TAG_TRANSFILETRIGGERSCRIPTS)
+        {
+            features.push(REQUIRE_FILE_TRIGGERS);
+        }
+        if self.all_dependencies().iter().any(Dependency::is_rich) {
+            features.push(REQUIRE_RICH_DEPENDENCIES);
+        }
+        if self.table.has(TAG_LONGFILESIZES) {
+            features.push(REQUIRE_LARGE_FILES);
+        }
+        if let Some((ref name, ref version)) = payload_feature {
+            features.push((name.as_str(), version.as_str()));
+        }
+        for (name, version) in features {
+            if !self.requires().any(|dep| dep.name() == name) {
+                let flags = DependencyFlags::RPMLIB | DependencyFlags::LESS |
+                    DependencyFlags::EQUAL;
+                self.add_requires(Dependency::new(name, flags, version));
+            }
+        }
+    }
+
+    /// Sets the `rpmlib()` feature required by the payload compressor, which
+    /// will be added to the requirements by `done_adding_fields`.
+    pub(crate) fn set_payload_rpmlib_feature(&mut self, name: &str,
+                                             version: &str) {
+        self.payload_feature = Some((name.to_string(), version.to_string()));
     }
 
     pub(crate) fn write<W: Write + Seek>(&self, writer: W) -> io::Result<()> {
@@ -445,6 +1045,59 @@ impl HeaderSection {
         self.table.set(TAG_RELEASE, IndexValue::String(release));
     }
 
+    /// Returns the epoch of the package, if any.
+    pub fn epoch(&self) -> Option<u32> {
+        self.table.get_nth_int32(TAG_EPOCH, 0)
+    }
+
+    pub(crate) fn set_epoch(&mut self, epoch: u32) {
+        self.table.set(TAG_EPOCH, IndexValue::Int32(vec![epoch]));
+    }
+
+    /// Returns the epoch, version, and release of the package.
+    pub fn evr(&self) -> Evr {
+        Evr::new(self.epoch(), self.version_string(), self.release_string())
+    }
+
+    /// Returns the full name, epoch, version, release, and architecture of
+    /// the package.
+    pub fn nevra(&self) -> Nevra {
+        Nevra::new(self.package_name(), self.evr(), self.architecture())
+    }
+
+    /// Returns the scriptlet of the given type, if the package has one.
+    pub fn scriptlet(&self, scriptlet_type: ScriptletType)
+                     -> Option<Scriptlet> {
+        let (body_tag, interpreter_tag, flags_tag) =
+            scriptlet_tags(scriptlet_type);
+        let interpreter = self.table.get_string(interpreter_tag)?;
+        let body = self.table.get_string(body_tag).unwrap_or("");
+        let flags = self.table.get_nth_int32(flags_tag, 0).unwrap_or(0);
+        Some(Scriptlet::new(body)
+                 .with_interpreter(interpreter)
+                 .with_flags(ScriptletFlags::from_bits_truncate(flags)))
+    }
+
+    pub(crate) fn set_scriptlet(&mut self, scriptlet_type: ScriptletType,
+                                scriptlet: Scriptlet) {
+        let (body_tag, interpreter_tag, flags_tag) =
+            scriptlet_tags(scriptlet_type);
+        if scriptlet.body().is_empty() {
+            self.table.remove(body_tag);
+        } else {
+            let body = scriptlet.body().to_string();
+            self.table.set(body_tag, IndexValue::String(body));
+        }
+        let interpreter = scriptlet.interpreter().to_string();
+        self.table.set(interpreter_tag, IndexValue::String(interpreter));
+        if scriptlet.flags().is_empty() {
+            self.table.remove(flags_tag);
+        } else {
+            let flags = scriptlet.flags().bits();
+            self.table.set(flags_tag, IndexValue::Int32(vec![flags]));
+        }
+    }
+
     /// Returns a one-line description of the package.
     pub fn summary(&self) -> &str {
         self.table.get_nth_string(TAG_SUMMARY, 0).unwrap()
@@ -465,8 +1118,53 @@ impl HeaderSection {
     }
 
     /// Returns the sum of the sizes of the installed files.
-    pub fn total_install_size(&self) -> u32 {
-        self.table.get_nth_int32(TAG_SIZE, 0).unwrap()
+    pub fn total_install_size(&self) -> u64 {
+        match self.table.get_nth_int64(TAG_LONGSIZE, 0) {
+            Some(size) => size,
+            None => u64::from(self.table.get_nth_int32(TAG_SIZE, 0).unwrap()),
+        }
+    }
+
+    /// Sets the total install size, switching to `TAG_LONGSIZE` if it
+    /// doesn't fit in 32 bits.
+    fn set_total_install_size(&mut self, size: u64) {
+        if size > u64::from(u32::MAX) {
+            self.table.remove(TAG_SIZE);
+            self.table.set(TAG_LONGSIZE, IndexValue::Int64(vec![size]));
+        } else {
+            self.table.remove(TAG_LONGSIZE);
+            self.table.set(TAG_SIZE, IndexValue::Int32(vec![size as u32]));
+        }
+    }
+
+    /// Returns the number of files in the package.
+    fn file_count(&self) -> usize {
+        self.table
+            .get(TAG_FILESIZES)
+            .or_else(|| self.table.get(TAG_LONGFILESIZES))
+            .map(IndexValue::count)
+            .unwrap_or(0)
+    }
+
+    /// Appends a file size, switching from `TAG_FILESIZES` to
+    /// `TAG_LONGFILESIZES` (for all files) once any file is too large for
+    /// 32 bits, as rpmbuild does.
+    fn push_file_size(&mut self, size: u64) {
+        if !self.table.has(TAG_LONGFILESIZES) && size > u64::from(u32::MAX) {
+            let sizes = match self.table.get(TAG_FILESIZES) {
+                Some(&IndexValue::Int32(ref sizes)) => {
+                    sizes.iter().map(|&size| u64::from(size)).collect()
+                }
+                _ => Vec::new(),
+            };
+            self.table.remove(TAG_FILESIZES);
+            self.table.set(TAG_LONGFILESIZES, IndexValue::Int64(sizes));
+        }
+        if self.table.has(TAG_LONGFILESIZES) {
+            self.table.push_int64(TAG_LONGFILESIZES, size);
+        } else {
+            self.table.push_int32(TAG_FILESIZES, size as u32);
+        }
     }
 
     /// Returns the name of the author of the package, if any.
@@ -507,7 +1205,7 @@ impl HeaderSection {
     }
 
     /// Returns the name of the compression type used for the Archive section
-    /// (e.g. "gzip" or "bzip2").
+    /// (e.g. "gzip" or "zstd").
     pub fn payload_compressor(&self) -> &str {
         self.table.get_string(TAG_PAYLOADCOMPRESSOR).unwrap()
     }
@@ -516,18 +1214,349 @@ impl HeaderSection {
         self.table.set(TAG_PAYLOADCOMPRESSOR, IndexValue::String(compressor));
     }
 
-    /// Returns the compression level used for the Archive section (e.g. "9").
+    /// Returns true if the Archive section uses the "stripped" cpio format,
+    /// in which each entry refers to a file in this header by index instead
+    /// of carrying its own metadata.  As with rpm, this is the case for
+    /// packages that use 64-bit file sizes.
+    pub fn has_stripped_payload(&self) -> bool {
+        self.table.has(TAG_LONGFILESIZES) ||
+            self.requires().any(|dep| dep.name() == REQUIRE_LARGE_FILES.0)
+    }
+
+    /// Returns the compression settings used for the Archive section (such
+    /// as the compression level), or an error if the PAYLOADFLAGS string is
+    /// invalid.
+    pub fn payload_flags(&self) -> io::Result<PayloadFlags> {
+        PayloadFlags::parse(self.table.get_string(TAG_PAYLOADFLAGS).unwrap())
+    }
+
+    /// Returns the raw PAYLOADFLAGS string for the Archive section (e.g.
+    /// "9").
+    #[deprecated(note = "use `payload_flags` instead")]
     pub fn payload_compression_level(&self) -> &str {
         self.table.get_string(TAG_PAYLOADFLAGS).unwrap()
     }
 
-    pub(crate) fn set_payload_compression_level(&mut self, level: String) {
-        self.table.set(TAG_PAYLOADFLAGS, IndexValue::String(level));
+    pub(crate) fn set_payload_flags(&mut self, flags: &PayloadFlags) {
+        let flags = flags.to_string();
+        self.table.set(TAG_PAYLOADFLAGS, IndexValue::String(flags));
+    }
+
+    /// Returns the expected hex digest of the (compressed) Archive section,
+    /// if any.
+    pub fn payload_digest(&self) -> Option<&str> {
+        self.table.get_nth_string(TAG_PAYLOADDIGEST, 0)
+    }
+
+    /// Returns the hash algorithm used for the payload digest, if the package
+    /// has a payload digest and the algorithm is a known one.
+    pub fn payload_digest_algorithm(&self) -> Option<DigestAlgorithm> {
+        self.table
+            .get_nth_int32(TAG_PAYLOADDIGESTALGO, 0)
+            .and_then(DigestAlgorithm::from_number)
+    }
+
+    pub(crate) fn set_payload_digest(&mut self, algorithm: DigestAlgorithm,
+                                     digest: String) {
+        debug_assert_eq!(digest.len(), 2 * algorithm.digest_len());
+        self.table
+            .set(TAG_PAYLOADDIGEST, IndexValue::StringArray(vec![digest]));
+        self.table.set(TAG_PAYLOADDIGESTALGO,
+                       IndexValue::Int32(vec![algorithm.number()]));
+    }
+
+    /// Returns an iterator over the capabilities provided by this package.
+    pub fn provides(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, PROVIDE_TAGS)
+    }
+
+    pub(crate) fn add_provides(&mut self, dependency: Dependency) {
+        self.add_dependency(PROVIDE_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the requirements of this package.
+    pub fn requires(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, REQUIRE_TAGS)
+    }
+
+    pub(crate) fn add_requires(&mut self, dependency: Dependency) {
+        self.add_dependency(REQUIRE_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the capabilities that conflict with this
+    /// package.
+    pub fn conflicts(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, CONFLICT_TAGS)
+    }
+
+    pub(crate) fn add_conflicts(&mut self, dependency: Dependency) {
+        self.add_dependency(CONFLICT_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the capabilities that this package obsoletes.
+    pub fn obsoletes(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, OBSOLETE_TAGS)
+    }
+
+    pub(crate) fn add_obsoletes(&mut self, dependency: Dependency) {
+        self.add_dependency(OBSOLETE_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the capabilities that this package recommends
+    /// (weak requirements, which are installed by default if available).
+    pub fn recommends(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, RECOMMEND_TAGS)
+    }
+
+    pub(crate) fn add_recommends(&mut self, dependency: Dependency) {
+        self.add_dependency(RECOMMEND_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the capabilities that this package suggests
+    /// (weak requirements, which are not installed by default).
+    pub fn suggests(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, SUGGEST_TAGS)
+    }
+
+    pub(crate) fn add_suggests(&mut self, dependency: Dependency) {
+        self.add_dependency(SUGGEST_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the capabilities that this package
+    /// supplements (reverse recommendations: this package is installed by
+    /// default along with any of them).
+    pub fn supplements(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, SUPPLEMENT_TAGS)
+    }
+
+    pub(crate) fn add_supplements(&mut self, dependency: Dependency) {
+        self.add_dependency(SUPPLEMENT_TAGS, dependency);
+    }
+
+    /// Returns an iterator over the capabilities that this package enhances
+    /// (reverse suggestions).
+    pub fn enhances(&self) -> DependencyIter {
+        DependencyIter::new(&self.table, ENHANCE_TAGS)
+    }
+
+    pub(crate) fn add_enhances(&mut self, dependency: Dependency) {
+        self.add_dependency(ENHANCE_TAGS, dependency);
+    }
+
+    fn add_dependency(&mut self, tags: (i32, i32, i32),
+                      dependency: Dependency) {
+        let (name_tag, flags_tag, version_tag) = tags;
+        if !self.table.has(name_tag) {
+            self.table.set(name_tag, IndexValue::StringArray(Vec::new()));
+            self.table.set(flags_tag, IndexValue::Int32(Vec::new()));
+            self.table.set(version_tag, IndexValue::StringArray(Vec::new()));
+        }
+        self.table.push_string(name_tag, dependency.name().to_string());
+        self.table.push_int32(flags_tag, dependency.flags().bits());
+        self.table
+            .push_string(version_tag, dependency.version().to_string());
+    }
+
+    /// Returns the triggers that run when other packages are installed or
+    /// uninstalled.
+    pub fn triggers(&self) -> Vec<Trigger> {
+        self.trigger_scripts(&TRIGGER_ENTRIES)
+            .into_iter()
+            .map(|(trigger_type, scriptlet, conditions, _)| {
+                conditions
+                    .into_iter()
+                    .fold(Trigger::new(trigger_type, scriptlet),
+                          Trigger::with_condition)
+            })
+            .collect()
+    }
+
+    pub(crate) fn add_trigger(&mut self, trigger: Trigger) {
+        self.add_trigger_script(&TRIGGER_ENTRIES,
+                                trigger.trigger_type(),
+                                trigger.scriptlet(),
+                                trigger.conditions(),
+                                None);
+    }
+
+    /// Returns the file triggers that run once for each package that
+    /// installs or uninstalls matching files.
+    pub fn file_triggers(&self) -> Vec<FileTrigger> {
+        self.file_trigger_scripts(&FILE_TRIGGER_ENTRIES)
+    }
+
+    pub(crate) fn add_file_trigger(&mut self, trigger: FileTrigger) {
+        self.add_file_trigger_script(&FILE_TRIGGER_ENTRIES, trigger);
+    }
+
+    /// Returns the file triggers that run once per transaction that installs
+    /// or uninstalls matching files.
+    pub fn transaction_file_triggers(&self) -> Vec<FileTrigger> {
+        self.file_trigger_scripts(&TRANS_FILE_TRIGGER_ENTRIES)
+    }
+
+    pub(crate) fn add_transaction_file_trigger(&mut self,
+                                               trigger: FileTrigger) {
+        self.add_file_trigger_script(&TRANS_FILE_TRIGGER_ENTRIES, trigger);
+    }
+
+    fn file_trigger_scripts(&self, entries: &TriggerEntries)
+                            -> Vec<FileTrigger> {
+        self.trigger_scripts(entries)
+            .into_iter()
+            .map(|(trigger_type, scriptlet, conditions, priority)| {
+                let mut trigger = FileTrigger::new(trigger_type, scriptlet);
+                if let Some(priority) = priority {
+                    trigger = trigger.with_priority(priority);
+                }
+                conditions.into_iter().fold(trigger, |trigger, condition| {
+                    trigger.with_prefix(condition.name())
+                })
+            })
+            .collect()
+    }
+
+    fn add_file_trigger_script(&mut self, entries: &TriggerEntries,
+                               trigger: FileTrigger) {
+        let conditions: Vec<Dependency> = trigger
+            .prefixes()
+            .iter()
+            .map(|prefix| Dependency::any(prefix.as_str()))
+            .collect();
+        self.add_trigger_script(entries,
+                                trigger.trigger_type(),
+                                trigger.scriptlet(),
+                                &conditions,
+                                Some(trigger.priority()));
+    }
+
+    /// Returns each trigger script for the given kind of trigger, along with
+    /// its type, its conditions (with the trigger type flags removed), and
+    /// its priority (if any).  Scripts with no conditions are skipped.
+    fn trigger_scripts(&self, entries: &TriggerEntries)
+                       -> Vec<(TriggerType,
+                               Scriptlet,
+                               Vec<Dependency>,
+                               Option<u32>)> {
+        let conditions: Vec<(u32, Dependency)> =
+            DependencyIter::new(&self.table, entries.dependency_tags())
+                .enumerate()
+                .map(|(index, condition)| {
+                    let script_index =
+                        self.table.get_nth_int32(entries.index, index);
+                    (script_index.unwrap(), condition)
+                })
+                .collect();
+        let script_count = self.table
+            .get(entries.scripts)
+            .map(IndexValue::count)
+            .unwrap_or(0);
+        let mut scripts = Vec::new();
+        for script_index in 0..script_count {
+            let mut trigger_type = None;
+            let mut script_conditions = Vec::new();
+            for &(index, ref condition) in conditions.iter() {
+                if index as usize != script_index {
+                    continue;
+                }
+                let flags = condition.flags();
+                if trigger_type.is_none() {
+                    trigger_type = TriggerType::from_dependency_flags(flags);
+                }
+                let flags = flags - TriggerType::all_dependency_flags();
+                script_conditions.push(Dependency::new(condition.name(),
+                                                       flags,
+                                                       condition.version()));
+            }
+            let trigger_type = match trigger_type {
+                Some(trigger_type) => trigger_type,
+                None => continue,
+            };
+            let body = self.table
+                .get_nth_string(entries.scripts, script_index)
+                .unwrap_or("");
+            let mut scriptlet = Scriptlet::new(body);
+            if let Some(interpreter) =
+                self.table.get_nth_string(entries.script_prog, script_index)
+            {
+                scriptlet = scriptlet.with_interpreter(interpreter);
+            }
+            if let Some(flags) =
+                self.table.get_nth_int32(entries.script_flags, script_index)
+            {
+                let flags = ScriptletFlags::from_bits_truncate(flags);
+                scriptlet = scriptlet.with_flags(flags);
+            }
+            let priority = entries.priorities.and_then(|tag| {
+                self.table.get_nth_int32(tag, script_index)
+            });
+            scripts.push((trigger_type,
+                          scriptlet,
+                          script_conditions,
+                          priority));
+        }
+        scripts
+    }
+
+    fn add_trigger_script(&mut self, entries: &TriggerEntries,
+                          trigger_type: TriggerType, scriptlet: &Scriptlet,
+                          conditions: &[Dependency], priority: Option<u32>) {
+        if !self.table.has(entries.scripts) {
+            self.table
+                .set(entries.scripts, IndexValue::StringArray(Vec::new()));
+            self.table
+                .set(entries.script_prog, IndexValue::StringArray(Vec::new()));
+            self.table
+                .set(entries.script_flags, IndexValue::Int32(Vec::new()));
+            self.table.set(entries.index, IndexValue::Int32(Vec::new()));
+            if let Some(tag) = entries.priorities {
+                self.table.set(tag, IndexValue::Int32(Vec::new()));
+            }
+        }
+        let script_index =
+            self.table.get(entries.scripts).unwrap().count() as u32;
+        self.table
+            .push_string(entries.scripts, scriptlet.body().to_string());
+        self.table.push_string(entries.script_prog,
+                               scriptlet.interpreter().to_string());
+        self.table
+            .push_int32(entries.script_flags, scriptlet.flags().bits());
+        if let (Some(tag), Some(priority)) = (entries.priorities, priority) {
+            self.table.push_int32(tag, priority);
+        }
+        for condition in conditions.iter() {
+            let flags = condition.flags() | trigger_type.dependency_flag();
+            let condition = Dependency::new(condition.name(),
+                                            flags,
+                                            condition.version());
+            self.add_dependency(entries.dependency_tags(), condition);
+            self.table.push_int32(entries.index, script_index);
+        }
+    }
+
+    /// Returns the hash algorithm used for the file digests in this package,
+    /// or `None` if the algorithm is not a known one.
+    pub fn file_digest_algorithm(&self) -> Option<DigestAlgorithm> {
+        match self.table.get_nth_int32(TAG_FILEDIGESTALGO, 0) {
+            Some(number) => DigestAlgorithm::from_number(number),
+            None => Some(DigestAlgorithm::Md5),
+        }
+    }
+
+    pub(crate) fn set_file_digest_algorithm(&mut self,
+                                            algorithm: DigestAlgorithm) {
+        self.table.set(TAG_FILEDIGESTALGO,
+                       IndexValue::Int32(vec![algorithm.number()]));
+    }
+
+    pub(crate) fn set_file_digests(&mut self, digests: Vec<String>) {
+        debug_assert_eq!(digests.len(), self.file_count());
+        self.table.set(TAG_FILEDIGESTS, IndexValue::StringArray(digests));
     }
 
     /// Returns an iterator over the files in the package.
     pub fn files(&self) -> FileInfoIter {
-        let length = self.table.get(TAG_FILESIZES).unwrap().count();
+        let length = self.file_count();
         FileInfoIter {
             table: &self.table,
             use_old_filenames: self.use_old_filenames,
@@ -536,6 +1565,84 @@ impl HeaderSection {
         }
     }
 
+    /// Verifies the installed files of this package against a filesystem
+    /// tree under `root` (e.g. `/`, or an unpacked container image), like
+    /// `rpm -V`.  User and group names are looked up in the `/etc/passwd`
+    /// and `/etc/group` files under `root`.  Returns one result for each
+    /// file in the package, in order.
+    #[cfg(unix)]
+    pub fn verify_files<P: AsRef<Path>>(&self, root: P)
+                                        -> io::Result<Vec<FileVerification>> {
+        let root = root.as_ref();
+        let owners = OwnerNames::read(root)?;
+        let algorithm = self.file_digest_algorithm();
+        let verifications = self.files()
+            .map(|file_info| {
+                     FileVerification::verify(&file_info,
+                                              algorithm,
+                                              &owners,
+                                              root)
+                 })
+            .collect();
+        Ok(verifications)
+    }
+
+    /// Returns the sets of files in the package that are hard links to each
+    /// other, as lists of indices into `files()`.  Only sets of two or more
+    /// files are included.
+    pub fn hard_link_sets(&self) -> Vec<Vec<usize>> {
+        let mut sets: Vec<Vec<usize>> = Vec::new();
+        let mut set_indices: HashMap<(u32, u32), usize> = HashMap::new();
+        for (index, file_info) in self.files().enumerate() {
+            if let Some(key) = file_info.hard_link_key() {
+                match set_indices.entry(key) {
+                    Entry::Occupied(entry) => sets[*entry.get()].push(index),
+                    Entry::Vacant(entry) => {
+                        entry.insert(sets.len());
+                        sets.push(vec![index]);
+                    }
+                }
+            }
+        }
+        sets.retain(|set| set.len() > 1);
+        sets
+    }
+
+    /// Renumbers file inodes (as rpmbuild does), so that every file has a
+    /// distinct inode number except for hard links, which share one.  Also
+    /// makes sure that each set of hard links is only counted once in the
+    /// total install size.
+    pub(crate) fn normalize_file_inodes(&mut self) {
+        let mut inodes = Vec::new();
+        let mut set_inodes = HashMap::new();
+        let mut total_install_size = 0;
+        for file_info in self.files() {
+            let next_inode = inodes.len() as u32 + 1;
+            let inode = match file_info.hard_link_key() {
+                Some(key) => {
+                    match set_inodes.entry(key) {
+                        Entry::Occupied(entry) => *entry.get(),
+                        Entry::Vacant(entry) => {
+                            total_install_size += file_info.size;
+                            *entry.insert(next_inode)
+                        }
+                    }
+                }
+                None => {
+                    if !file_info.is_ghost() {
+                        total_install_size += file_info.size;
+                    }
+                    next_inode
+                }
+            };
+            inodes.push(inode);
+        }
+        let devices = vec![1; inodes.len()];
+        self.table.set(TAG_FILEINODES, IndexValue::Int32(inodes));
+        self.table.set(TAG_FILEDEVICES, IndexValue::Int32(devices));
+        self.set_total_install_size(total_install_size);
+    }
+
     pub(crate) fn add_file(&mut self, file_info: FileInfo) {
         if self.use_old_filenames {
             self.table.push_string(TAG_OLDFILENAMES, file_info.name.clone());
@@ -557,20 +1664,29 @@ impl HeaderSection {
             self.table.push_string(TAG_BASENAMES, basename.to_string());
             self.table.push_int32(TAG_DIRINDEXES, dirindex);
         }
-        self.table.push_int32(TAG_FILESIZES, file_info.size);
+        self.push_file_size(file_info.size);
         self.table.push_int16(TAG_FILEMODES, file_info.mode);
         self.table.push_int16(TAG_FILERDEVS, file_info.rdev);
         self.table.push_int32(TAG_FILEMTIMES, file_info.mtime);
-        self.table.push_string(TAG_FILEMD5S, file_info.md5.clone());
+        self.table.push_string(TAG_FILEDIGESTS, file_info.digest.clone());
         self.table.push_string(TAG_FILELINKTOS, file_info.linkto.clone());
-        self.table.push_int32(TAG_FILEFLAGS, file_info.flags);
+        self.table.push_int32(TAG_FILEFLAGS, file_info.flags.bits());
         self.table.push_string(TAG_FILEUSERNAME, file_info.user.clone());
         self.table.push_string(TAG_FILEGROUPNAME, file_info.group.clone());
         self.table.push_int32(TAG_FILEDEVICES, file_info.device);
         self.table.push_int32(TAG_FILEINODES, file_info.inode);
         self.table.push_string(TAG_FILELANGS, file_info.lang.clone());
-        let total_install_size = self.total_install_size() + file_info.size;
-        self.table.set(TAG_SIZE, IndexValue::Int32(vec![total_install_size]));
+        if !self.table.has(TAG_FILEVERIFYFLAGS) {
+            self.table.set(TAG_FILEVERIFYFLAGS, IndexValue::Int32(Vec::new()));
+        }
+        self.table
+            .push_int32(TAG_FILEVERIFYFLAGS, file_info.verify_flags.bits());
+        // Like rpmbuild, don't count ghost files, which aren't installed.
+        if !file_info.is_ghost() {
+            let total_install_size =
+                self.total_install_size() + file_info.size;
+            self.set_total_install_size(total_install_size);
+        }
     }
 
     /// Returns the timestamp when the package was built, if present.
@@ -587,13 +1703,74 @@ impl HeaderSection {
 
     /// Returns an iterator over the entries in the package changelog.
     pub fn changelog(&self) -> ChangeLogIter {
-        let length = self.table.get(TAG_CHANGELOGTIME).unwrap().count();
+        let length = self.table
+            .get(TAG_CHANGELOGTIME)
+            .map(IndexValue::count)
+            .unwrap_or(0);
         ChangeLogIter {
             table: &self.table,
             next_index: 0,
             length,
         }
     }
+
+    /// Adds an entry to the changelog, keeping the entries sorted from
+    /// newest to oldest.  Entries with the same timestamp are kept in the
+    /// order they were added.
+    pub(crate) fn add_changelog_entry(&mut self, entry: ChangeLogEntry) {
+        let mut entries: Vec<ChangeLogEntry> = self.changelog().collect();
+        let index = entries
+            .iter()
+            .position(|other| other.timestamp() < entry.timestamp())
+            .unwrap_or(entries.len());
+        entries.insert(index, entry);
+        let mut times = Vec::with_capacity(entries.len());
+        let mut authors = Vec::with_capacity(entries.len());
+        let mut descriptions = Vec::with_capacity(entries.len());
+        for entry in entries.into_iter() {
+            times.push(convert::system_time_to_u32(entry.timestamp()));
+            authors.push(entry.author().to_string());
+            descriptions.push(entry.description().to_string());
+        }
+        self.table.set(TAG_CHANGELOGTIME, IndexValue::Int32(times));
+        self.table.set(TAG_CHANGELOGNAME, IndexValue::StringArray(authors));
+        self.table
+            .set(TAG_CHANGELOGTEXT, IndexValue::StringArray(descriptions));
+    }
+}
+
+// ========================================================================= //
+
+bitflags! {
+    /// Attributes of a file in the package, as set by spec file directives
+    /// such as `%config` or `%doc` (the `RPMFILE_*` values).
+    pub struct FileFlags: u32 {
+        /// The file is a configuration file (`%config`).
+        const CONFIG = 1 << 0;
+        /// The file is documentation (`%doc`).
+        const DOC = 1 << 1;
+        /// The file is an icon (legacy, unused).
+        const ICON = 1 << 2;
+        /// The file need not exist on the installed system
+        /// (`%config(missingok)`).
+        const MISSINGOK = 1 << 3;
+        /// A modified file is not replaced when the package is upgraded
+        /// (`%config(noreplace)`).
+        const NOREPLACE = 1 << 4;
+        /// The file is the spec file of a source package.
+        const SPECFILE = 1 << 5;
+        /// The file is owned by the package, but is not included in the
+        /// payload (`%ghost`).
+        const GHOST = 1 << 6;
+        /// The file is a license (`%license`).
+        const LICENSE = 1 << 7;
+        /// The file is a README (`%readme`).
+        const README = 1 << 8;
+        /// The file is a public key (`%pubkey`).
+        const PUBKEY = 1 << 11;
+        /// The file is a build artifact (`%artifact`).
+        const ARTIFACT = 1 << 12;
+    }
 }
 
 // ========================================================================= //
@@ -602,40 +1779,152 @@ impl HeaderSection {
 #[allow(dead_code)]
 pub struct FileInfo {
     name: String,
-    size: u32,
+    size: u64,
     mode: u16,
     rdev: u16,
     mtime: u32,
-    md5: String,
+    digest: String,
     linkto: String,
-    flags: u32,
+    flags: FileFlags,
     user: String,
     group: String,
     device: u32,
     inode: u32,
     lang: String,
+    verify_flags: VerifyFlags,
 }
 
 impl FileInfo {
-    /// Constructs a new `FileInfo` with all other fields set to defaults.
-    pub fn new<S: Into<String>>(install_path: S, file_size: u32) -> FileInfo {
+    /// Constructs a new `FileInfo` for a regular file, with all other fields
+    /// set to defaults.
+    pub fn new<S: Into<String>>(install_path: S, file_size: u64) -> FileInfo {
         FileInfo {
             name: install_path.into(),
             size: file_size,
-            mode: 0o644,
+            mode: S_IFREG | 0o644,
             rdev: 0,
             mtime: 0,
-            md5: String::new(),
+            digest: String::new(),
             linkto: String::new(),
-            flags: 0,
+            flags: FileFlags::empty(),
             user: "root".to_string(),
             group: "root".to_string(),
             device: 0,
             inode: 0,
             lang: String::new(),
+            verify_flags: VerifyFlags::all(),
         }
     }
 
+    /// Constructs a new `FileInfo` for a directory.
+    pub fn directory<S: Into<String>>(install_path: S) -> FileInfo {
+        let mut file_info = FileInfo::new(install_path, 0);
+        file_info.mode = S_IFDIR | 0o755;
+        file_info
+    }
+
+    /// Constructs a new `FileInfo` for a symbolic link pointing to the given
+    /// target path.
+    pub fn symlink<S1, S2>(install_path: S1, target: S2) -> FileInfo
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        let target = target.into();
+        let mut file_info = FileInfo::new(install_path, target.len() as u64);
+        file_info.mode = S_IFLNK | 0o777;
+        file_info.linkto = target;
+        file_info
+    }
+
+    /// Constructs a new `FileInfo` for a character device with the given
+    /// major and minor device numbers.
+    pub fn char_device<S: Into<String>>(install_path: S, major: u8,
+                                        minor: u8)
+                                        -> FileInfo {
+        FileInfo::device_file(install_path.into(), S_IFCHR, major, minor)
+    }
+
+    /// Constructs a new `FileInfo` for a block device with the given major
+    /// and minor device numbers.
+    pub fn block_device<S: Into<String>>(install_path: S, major: u8,
+                                         minor: u8)
+                                         -> FileInfo {
+        FileInfo::device_file(install_path.into(), S_IFBLK, major, minor)
+    }
+
+    fn device_file(install_path: String, file_type: u16, major: u8,
+                   minor: u8)
+                   -> FileInfo {
+        let mut file_info = FileInfo::new(install_path, 0);
+        file_info.mode = file_type | 0o600;
+        file_info.rdev = ((major as u16) << 8) | (minor as u16);
+        file_info
+    }
+
+    /// Returns this `FileInfo` with the given permission bits (e.g.
+    /// `0o755`).  Any file type bits in `mode` are ignored; the file type is
+    /// determined by the constructor used.
+    pub fn with_mode(mut self, mode: u16) -> FileInfo {
+        self.mode = (self.mode & S_IFMT) | (mode & !S_IFMT);
+        self
+    }
+
+    /// Returns this `FileInfo` with the given owner user name.
+    pub fn with_user<S: Into<String>>(mut self, user: S) -> FileInfo {
+        self.user = user.into();
+        self
+    }
+
+    /// Returns this `FileInfo` with the given owner group name.
+    pub fn with_group<S: Into<String>>(mut self, group: S) -> FileInfo {
+        self.group = group.into();
+        self
+    }
+
+    /// Returns this `FileInfo` with the given last-modified timestamp.
+    pub fn with_modified_time(mut self, timestamp: SystemTime) -> FileInfo {
+        self.mtime = convert::system_time_to_u32(timestamp);
+        self
+    }
+
+    /// Returns this `FileInfo` with the given flags (e.g.
+    /// `FileFlags::CONFIG` for a `%config` file).
+    pub fn with_flags(mut self, flags: FileFlags) -> FileInfo {
+        self.flags = flags;
+        self
+    }
+
+    /// Returns this `FileInfo` with the given language (for files that are
+    /// only installed for a particular locale).
+    pub fn with_language<S: Into<String>>(mut self, lang: S) -> FileInfo {
+        self.lang = lang.into();
+        self
+    }
+
+    /// Returns this `FileInfo` with the given verify flags, which control
+    /// which attributes are checked when the installed file is verified
+    /// (e.g. for `%verify(not mtime)`).
+    pub fn with_verify_flags(mut self, flags: VerifyFlags) -> FileInfo {
+        self.verify_flags = flags;
+        self
+    }
+
+    /// Returns this `FileInfo` with the given inode number.  Regular files
+    /// with the same device and (nonzero) inode numbers are hard links to
+    /// each other.
+    pub fn with_inode(mut self, inode: u32) -> FileInfo {
+        self.inode = inode;
+        self
+    }
+
+    /// Returns this `FileInfo` with the given device number (which only
+    /// serves to distinguish inode numbers from different filesystems).
+    pub fn with_device(mut self, device: u32) -> FileInfo {
+        self.device = device;
+        self
+    }
+
     /// Constructs a new `FileInfo` from file metadata.
     pub fn from_metadata<S: Into<String>>(install_path: S,
                                           metadata: &Metadata)
@@ -648,18 +1937,19 @@ impl FileInfo {
                               -> io::Result<FileInfo> {
         let file_info = FileInfo {
             name: install_path,
-            size: metadata.len() as u32,
+            size: metadata.len(),
             mode: metadata.mode() as u16,
             rdev: metadata.rdev() as u16,
             mtime: metadata.mtime() as u32,
-            md5: String::new(),
+            digest: String::new(),
             linkto: String::new(),
-            flags: 0,
+            flags: FileFlags::empty(),
             user: "root".to_string(),
             group: "root".to_string(),
-            device: 0,
+            device: metadata.dev() as u32,
             inode: metadata.ino() as u32,
             lang: String::new(),
+            verify_flags: VerifyFlags::all(),
         };
         Ok(file_info)
     }
@@ -670,18 +1960,19 @@ impl FileInfo {
         let modified_time = metadata.modified()?;
         let file_info = FileInfo {
             name: install_path,
-            size: metadata.len() as u32,
+            size: metadata.len(),
             mode: if metadata.readonly() { 0o444 } else { 0o664 },
             rdev: 0,
             mtime: convert::system_time_to_u32(modified_time),
-            md5: String::new(),
+            digest: String::new(),
             linkto: String::new(),
-            flags: 0,
+            flags: FileFlags::empty(),
             user: "root".to_string(),
             group: "root".to_string(),
             device: 0,
             inode: 0,
             lang: String::new(),
+            verify_flags: VerifyFlags::all(),
         };
         Ok(file_info)
     }
@@ -690,7 +1981,7 @@ impl FileInfo {
     pub fn name(&self) -> &str { &self.name }
 
     /// Returns the size of the file, in bytes.
-    pub fn size(&self) -> u32 { self.size }
+    pub fn size(&self) -> u64 { self.size }
 
     /// Returns the Unix mode bits for this file.
     pub fn mode(&self) -> u16 { self.mode }
@@ -700,8 +1991,39 @@ impl FileInfo {
         convert::u32_to_system_time(self.mtime)
     }
 
-    /// Returns the file's expected MD5 checksum.
-    pub fn md5_checksum(&self) -> &str { &self.md5 }
+    /// Returns true if this is a regular file (as opposed to e.g. a directory
+    /// or symlink), and thus should have a digest.
+    pub fn is_regular_file(&self) -> bool {
+        let file_type = self.mode & S_IFMT;
+        file_type == S_IFREG || file_type == 0
+    }
+
+    /// Returns true if this is a directory.
+    pub fn is_directory(&self) -> bool { self.mode & S_IFMT == S_IFDIR }
+
+    /// Returns true if this is a symbolic link.
+    pub fn is_symlink(&self) -> bool { self.mode & S_IFMT == S_IFLNK }
+
+    /// Returns the major and minor device numbers if this is a character or
+    /// block device.
+    pub fn device_numbers(&self) -> Option<(u8, u8)> {
+        let file_type = self.mode & S_IFMT;
+        if file_type == S_IFCHR || file_type == S_IFBLK {
+            Some(((self.rdev >> 8) as u8, self.rdev as u8))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the file's expected digest, as a hex string (or an empty
+    /// string if there is no digest).  The hash algorithm is given by
+    /// `HeaderSection::file_digest_algorithm`.
+    pub fn digest(&self) -> &str { &self.digest }
+
+    /// Returns the file's expected digest.  Despite the name, this is only an
+    /// MD5 checksum if the package's file digest algorithm is MD5.
+    #[deprecated(note = "use `digest` instead")]
+    pub fn md5_checksum(&self) -> &str { &self.digest }
 
     /// Returns the target path if this file is a symbolic link.
     pub fn symlink_target(&self) -> Option<&str> {
@@ -718,8 +2040,36 @@ impl FileInfo {
     /// Returns the name of the group for this file.
     pub fn group_name(&self) -> &str { &self.group }
 
+    /// Returns the flags for this file.
+    pub fn flags(&self) -> FileFlags { self.flags }
+
+    /// Returns true if this is a `%ghost` file, which has no data in the
+    /// package archive.
+    pub fn is_ghost(&self) -> bool { self.flags.contains(FileFlags::GHOST) }
+
+    /// Returns the language of this file, or an empty string if it is not
+    /// specific to any locale.
+    pub fn language(&self) -> &str { &self.lang }
+
     /// Returns the original inode number of the file.
     pub fn inode(&self) -> u32 { self.inode }
+
+    /// Returns the original device number of the file.
+    pub fn device(&self) -> u32 { self.device }
+
+    /// Returns the key identifying the set of hard links that this file
+    /// belongs to, if it can be hard linked at all.
+    fn hard_link_key(&self) -> Option<(u32, u32)> {
+        if self.is_regular_file() && !self.is_ghost() && self.inode != 0 {
+            Some((self.device, self.inode))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the attributes that are checked when the installed file is
+    /// verified.
+    pub fn verify_flags(&self) -> VerifyFlags { self.verify_flags }
 }
 
 // ========================================================================= //
@@ -758,25 +2108,38 @@ impl<'a> Iterator for FileInfoIter<'a> {
             name.push_str(base_name);
             name
         };
-        let md5 = self.table.get_nth_string(TAG_FILEMD5S, idx).unwrap();
+        let digest = self.table.get_nth_string(TAG_FILEDIGESTS, idx).unwrap();
         let linkto = self.table.get_nth_string(TAG_FILELINKTOS, idx).unwrap();
         let user = self.table.get_nth_string(TAG_FILEUSERNAME, idx).unwrap();
         let group = self.table.get_nth_string(TAG_FILEGROUPNAME, idx).unwrap();
         let lang = self.table.get_nth_string(TAG_FILELANGS, idx).unwrap();
+        let size = match self.table.get_nth_int64(TAG_LONGFILESIZES, idx) {
+            Some(size) => size,
+            None => {
+                let size = self.table.get_nth_int32(TAG_FILESIZES, idx);
+                u64::from(size.unwrap())
+            }
+        };
+        let flags = self.table.get_nth_int32(TAG_FILEFLAGS, idx).unwrap();
+        let verify_flags = self.table
+            .get_nth_int32(TAG_FILEVERIFYFLAGS, idx)
+            .map(VerifyFlags::from_bits_truncate)
+            .unwrap_or_else(VerifyFlags::all);
         let file_info = FileInfo {
             name,
-            size: self.table.get_nth_int32(TAG_FILESIZES, idx).unwrap(),
+            size,
             mode: self.table.get_nth_int16(TAG_FILEMODES, idx).unwrap(),
             rdev: self.table.get_nth_int16(TAG_FILERDEVS, idx).unwrap(),
             mtime: self.table.get_nth_int32(TAG_FILEMTIMES, idx).unwrap(),
-            md5: md5.to_string(),
+            digest: digest.to_string(),
             linkto: linkto.to_string(),
-            flags: self.table.get_nth_int32(TAG_FILEFLAGS, idx).unwrap(),
+            flags: FileFlags::from_bits_truncate(flags),
             user: user.to_string(),
             group: group.to_string(),
             device: self.table.get_nth_int32(TAG_FILEDEVICES, idx).unwrap(),
             inode: self.table.get_nth_int32(TAG_FILEINODES, idx).unwrap(),
             lang: lang.to_string(),
+            verify_flags,
         };
         Some(file_info)
     }
@@ -791,24 +2154,55 @@ impl<'a> ExactSizeIterator for FileInfoIter<'a> {}
 
 // ========================================================================= //
 
-/// An entry in the package changelog.
-pub struct ChangeLogEntry {
-    timestamp: SystemTime,
-    author: String,
-    description: String,
+/// An iterator over one kind of dependency (e.g. requirements) of a package.
+pub struct DependencyIter<'a> {
+    table: &'a IndexTable,
+    tags: (i32, i32, i32),
+    next_index: usize,
+    length: usize,
+}
+
+impl<'a> DependencyIter<'a> {
+    fn new(table: &'a IndexTable, tags: (i32, i32, i32))
+           -> DependencyIter<'a> {
+        let length = table.get(tags.0).map(IndexValue::count).unwrap_or(0);
+        DependencyIter {
+            table,
+            tags,
+            next_index: 0,
+            length,
+        }
+    }
 }
 
-impl ChangeLogEntry {
-    /// Returns the timestamp when this change was made.
-    pub fn timestamp(&self) -> SystemTime { self.timestamp }
+impl<'a> Iterator for DependencyIter<'a> {
+    type Item = Dependency;
 
-    /// Returns the name of the author of this change.
-    pub fn author(&self) -> &str { &self.author }
+    fn next(&mut self) -> Option<Dependency> {
+        let idx = self.next_index;
+        if idx == self.length {
+            return None;
+        }
+        self.next_index += 1;
+        let (name_tag, flags_tag, version_tag) = self.tags;
+        let name = self.table.get_nth_string(name_tag, idx).unwrap();
+        let flags = self.table.get_nth_int32(flags_tag, idx).unwrap();
+        let version = self.table.get_nth_string(version_tag, idx).unwrap();
+        let dependency =
+            Dependency::new(name,
+                            DependencyFlags::from_bits_truncate(flags),
+                            version);
+        Some(dependency)
+    }
 
-    /// Returns a description of this change.
-    pub fn description(&self) -> &str { &self.description }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.length - self.next_index;
+        (remaining, Some(remaining))
+    }
 }
 
+impl<'a> ExactSizeIterator for DependencyIter<'a> {}
+
 // ========================================================================= //
 
 /// An iterator over entries in the package changelog.
@@ -832,12 +2226,9 @@ impl<'a> Iterator for ChangeLogIter<'a> {
             self.table.get_nth_string(TAG_CHANGELOGNAME, idx).unwrap();
         let description =
             self.table.get_nth_string(TAG_CHANGELOGTEXT, idx).unwrap();
-        let entry = ChangeLogEntry {
-            timestamp: convert::u32_to_system_time(time),
-            author: author.to_string(),
-            description: description.to_string(),
-        };
-        Some(entry)
+        Some(ChangeLogEntry::new(convert::u32_to_system_time(time),
+                                 author,
+                                 description))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -852,8 +2243,14 @@ impl<'a> ExactSizeIterator for ChangeLogIter<'a> {}
 
 #[cfg(test)]
 mod tests {
-    use super::ENTRIES;
+    use super::{ENTRIES, FileInfo, HeaderSection, TAG_FILESIZES,
+                TAG_LONGFILESIZES, TAG_LONGSIZE, TAG_SIZE, TAG_TRIGGERINDEX};
+    use internal::dependency::Dependency;
+    use internal::index::IndexValue;
+    use internal::scriptlet::Scriptlet;
+    use internal::trigger::{Trigger, TriggerType};
     use std::collections::HashSet;
+    use std::io::Cursor;
 
     #[test]
     fn tags_are_unique() {
@@ -863,6 +2260,55 @@ mod tests {
             tags.insert(tag);
         }
     }
+
+    #[test]
+    fn invalid_trigger_index() {
+        let mut header = HeaderSection::new();
+        header.add_trigger(Trigger::new(TriggerType::Install,
+                                        Scriptlet::new("echo hi"))
+                               .with_condition(Dependency::any("foo")));
+        header.done_adding_fields().unwrap();
+        let mut bytes = Cursor::new(Vec::new());
+        header.write(&mut bytes).unwrap();
+        assert!(HeaderSection::read(Cursor::new(bytes.into_inner())).is_ok());
+
+        header.table.set(TAG_TRIGGERINDEX, IndexValue::Int32(vec![1]));
+        let mut bytes = Cursor::new(Vec::new());
+        header.write(&mut bytes).unwrap();
+        let error = HeaderSection::read(Cursor::new(bytes.into_inner()))
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(),
+                   "Invalid value (1) in TRIGGERINDEX entry (tag 1069) in \
+                    Header section (TRIGGERSCRIPTS entry (tag 1065) count is \
+                    1)");
+    }
+
+    #[test]
+    fn large_file_sizes() {
+        let mut header = HeaderSection::new();
+        header.add_file(FileInfo::new("/small", 1000));
+        assert!(header.table.has(TAG_FILESIZES));
+        assert!(header.table.has(TAG_SIZE));
+        header.add_file(FileInfo::new("/large", 5 << 30));
+        assert!(!header.table.has(TAG_FILESIZES));
+        assert!(header.table.has(TAG_LONGFILESIZES));
+        assert!(!header.table.has(TAG_SIZE));
+        assert!(header.table.has(TAG_LONGSIZE));
+        header.done_adding_fields().unwrap();
+        assert!(header
+                    .requires()
+                    .any(|dep| dep.name() == "rpmlib(LargeFiles)"));
+
+        let mut bytes = Cursor::new(Vec::new());
+        header.write(&mut bytes).unwrap();
+        let header = HeaderSection::read(Cursor::new(bytes.into_inner()))
+            .unwrap();
+        assert_eq!(header.total_install_size(), (5 << 30) + 1000);
+        let sizes: Vec<u64> =
+            header.files().map(|file_info| file_info.size()).collect();
+        assert_eq!(sizes, vec![1000, 5 << 30]);
+    }
 }
 
 // ========================================================================= //
@@ -26,6 +26,7 @@ const ENTRIES: &[(&str, i32, IndexType, Option<usize>)] = &[
 // ========================================================================= //
 
 /// A key-value table.
+#[derive(Clone)]
 pub struct IndexTable {
     values: BTreeMap<i32, IndexValue>,
 }
@@ -156,6 +157,9 @@ impl IndexTable {
         self.values.insert(tag, value);
     }
 
+    /// Removes the value for the given tag, if it is present.
+    pub(crate) fn remove(&mut self, tag: i32) { self.values.remove(&tag); }
+
     /// Returns the value for the given tag, if it is present and is a string.
     pub(crate) fn get_string(&self, tag: i32) -> Option<&str> {
         match self.get(tag) {
@@ -279,6 +283,38 @@ impl IndexTable {
         }
     }
 
+    /// Returns the nth value for the given tag, if it is present, and is an
+    /// int64 array, and has that many values.
+    pub(crate) fn get_nth_int64(&self, tag: i32, n: usize) -> Option<u64> {
+        match self.get(tag) {
+            Some(&IndexValue::Int64(ref values)) => {
+                if n < values.len() {
+                    Some(values[n])
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Adds an `i64` onto the end of an existing array.  Panics if there is
+    /// not already an `Int64` entry for the given tag.
+    pub(crate) fn push_int64(&mut self, tag: i32, value: u64) {
+        match self.values.get_mut(&tag) {
+            Some(&mut IndexValue::Int64(ref mut array)) => {
+                array.push(value);
+            }
+            Some(value) => {
+                panic!("Internal error: Entry for tag {} is {:?}, not {:?}",
+                       tag,
+                       value.index_type(),
+                       IndexType::Int64);
+            }
+            None => panic!("Internal error: No entry for tag {}", tag),
+        }
+    }
+
     pub(crate) fn add_signatures_index(&mut self) {
         self.add_meta_index(TAG_HEADERSIGNATURES);
     }
@@ -2,9 +2,22 @@
 mod macros;
 
 pub mod builder;
+pub mod changelog;
+pub mod codec;
 pub mod convert;
+pub mod dependency;
+pub mod digest;
+#[cfg(unix)]
+pub mod extract;
 pub mod header;
 pub mod index;
 pub mod lead;
 pub mod package;
+pub mod pgp;
+pub mod scriptlet;
 pub mod signature;
+pub mod streaming;
+pub mod stripped;
+pub mod trigger;
+pub mod verify;
+pub mod version;
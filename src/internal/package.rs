@@ -1,41 +1,57 @@
-use bzip2::read::BzDecoder;
 use cpio::NewcReader;
-use flate2::read::GzDecoder;
-use internal::convert::Sha1Writer;
+use internal::codec::PayloadCodecRegistry;
+use internal::convert::{self, Sha1Writer};
+use internal::digest::{DigestAlgorithm, DigestWriter};
+#[cfg(unix)]
+use internal::extract::{ExtractOptions, Extractor};
 use internal::header::{FileInfo, HeaderSection};
 use internal::lead::LeadSection;
-use internal::signature::SignatureSection;
+use internal::pgp::{self, Keyring, ParsedSignature, SigningKey};
+use internal::signature::{SignatureRegion, SignatureSection,
+                          SignatureVerification};
+use internal::stripped::{self, StrippedReader};
 use md5;
-use std::io::{self, Read, Seek, SeekFrom};
-use xz2::read::XzDecoder;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::path::Path;
+use std::time::SystemTime;
 
 // ========================================================================= //
 
 /// An RPM package file.
 pub struct Package<R: Read + Seek> {
     reader: R,
+    lead_start: u64,
     lead: LeadSection,
+    signature_start: u64,
     signature: SignatureSection,
     header_start: u64,
     header: HeaderSection,
     archive_start: u64,
+    payload_codecs: PayloadCodecRegistry,
 }
 
 impl<R: Read + Seek> Package<R> {
     /// Reads in an existing RPM package file.
     pub fn read(mut reader: R) -> io::Result<Package<R>> {
+        let lead_start = reader.seek(SeekFrom::Current(0))?;
         let lead = LeadSection::read(reader.by_ref())?;
+        let signature_start = reader.seek(SeekFrom::Current(0))?;
         let signature = SignatureSection::read(reader.by_ref())?;
         let header_start = reader.seek(SeekFrom::Current(0))?;
         let header = HeaderSection::read(reader.by_ref())?;
         let archive_start = reader.seek(SeekFrom::Current(0))?;
         let package = Package {
             reader,
+            lead_start,
             lead,
+            signature_start,
             signature,
             header_start,
             header,
             archive_start,
+            payload_codecs: PayloadCodecRegistry::new(),
         };
         Ok(package)
     }
@@ -49,26 +65,54 @@ impl<R: Read + Seek> Package<R> {
     /// Returns the header section.
     pub fn header(&self) -> &HeaderSection { &self.header }
 
+    /// Sets the codecs available for decompressing the Archive section.  By
+    /// default, all of the built-in codecs are available.
+    pub fn set_payload_codecs(&mut self, codecs: PayloadCodecRegistry) {
+        self.payload_codecs = codecs;
+    }
+
     /// Reads files from the Archive section.
-    pub fn read_archive(&mut self) -> io::Result<ArchiveSection<R>> {
+    pub fn read_archive(&mut self) -> io::Result<ArchiveSection> {
         self.reader.seek(SeekFrom::Start(self.archive_start))?;
-        ArchiveSection::new(self.header.payload_compressor(), &mut self.reader)
+        ArchiveSection::new(&self.payload_codecs,
+                            &self.header,
+                            Box::new(&mut self.reader))
+    }
+
+    /// Extracts the files in the package into the directory at `path`
+    /// (creating it if necessary), with the modes and timestamps given in
+    /// the package metadata.  Any file path that would escape the
+    /// directory, or that would be written through a symlink, is rejected
+    /// with an error.  Device files are not extracted.
+    #[cfg(unix)]
+    pub fn extract_to<P: AsRef<Path>>(&mut self, path: P,
+                                      options: &ExtractOptions)
+                                      -> io::Result<()> {
+        let extractor =
+            Extractor::new(path.as_ref(), options, self.header.files())?;
+        extractor.extract(&mut self.read_archive()?)
     }
 
     /// Validates the package checksums and signature; returns an error if any
-    /// of the validation checks fail.
+    /// of the validation checks fail.  OpenPGP signatures can't be verified
+    /// without any keys; use `validate_with_keyring` to check them.
     pub fn validate(&mut self) -> io::Result<()> {
+        self.validate_with_keyring(&Keyring::new()).map(|_| ())
+    }
+
+    /// Validates the package checksums and signature, verifying any OpenPGP
+    /// signatures made by keys in the given keyring.  Returns an error if any
+    /// of the validation checks fail (including if a signature from a known
+    /// key is invalid); otherwise, returns the results for each OpenPGP
+    /// signature in the package.  Signatures that use an unsupported format
+    /// or algorithm are reported as unsupported rather than as errors.
+    pub fn validate_with_keyring(&mut self, keyring: &Keyring)
+                                 -> io::Result<Vec<SignatureVerification>> {
         // Check header and archive size:
         let archive_end = self.reader.seek(SeekFrom::End(0))?;
         let actual_header_and_archive_size = archive_end - self.header_start;
-        let expected_header_and_archive_size = self.signature
-            .header_and_archive_size();
-        if actual_header_and_archive_size != expected_header_and_archive_size {
-            invalid_data!("Actual package header/archive size ({}) does not \
-                           match expected size from package signature ({})",
-                          actual_header_and_archive_size,
-                          expected_header_and_archive_size);
-        }
+        check_header_and_archive_size(&self.signature,
+                                      actual_header_and_archive_size)?;
 
         // Check header and archive MD5:
         let actual_header_and_archive_md5 = {
@@ -77,18 +121,11 @@ impl<R: Read + Seek> Package<R> {
             io::copy(&mut self.reader, &mut context)?;
             context.compute()
         };
-        let expected_header_and_archive_md5 =
-            md5::Digest(*self.signature.header_and_archive_md5());
-        if actual_header_and_archive_md5 != expected_header_and_archive_md5 {
-            invalid_data!("Actual package header/archive MD5 digest ({:x}) \
-                           does not match expected digest from package \
-                           signature ({:x})",
-                          actual_header_and_archive_md5,
-                          expected_header_and_archive_md5);
-        }
+        check_header_and_archive_md5(&self.signature,
+                                     actual_header_and_archive_md5)?;
 
         // Check header SHA1, if present:
-        if let Some(expected_header_sha1) = self.signature.header_sha1() {
+        if self.signature.header_sha1().is_some() {
             let actual_header_sha1 = {
                 let header_size = self.archive_start - self.header_start;
                 self.reader.seek(SeekFrom::Start(self.header_start))?;
@@ -97,186 +134,787 @@ impl<R: Read + Seek> Package<R> {
                          &mut context)?;
                 context.digest()
             };
-            if actual_header_sha1 != expected_header_sha1 {
-                invalid_data!("Actual package header SHA1 digest ({}) does \
-                               not match expected digest from package \
-                               signature ({})",
-                              actual_header_sha1,
-                              expected_header_sha1);
-            }
+            check_header_sha1(&self.signature, &actual_header_sha1)?;
         }
 
-        // TODO: check PGP/GPG signature, if present
-
-        let opt_uncompressed_archive_size = self.signature
-            .uncompressed_archive_size();
+        // Check header SHA256, if present:
+        if self.signature.header_sha256().is_some() {
+            let actual_header_sha256 = {
+                let header_size = self.archive_start - self.header_start;
+                self.reader.seek(SeekFrom::Start(self.header_start))?;
+                let mut context = DigestAlgorithm::Sha256.writer();
+                io::copy(&mut self.reader.by_ref().take(header_size),
+                         &mut context)?;
+                context.finish_hex()
+            };
+            check_header_sha256(&self.signature, &actual_header_sha256)?;
+        }
 
-        // Check individual archive file sizes and MD5 checksums:
-        let file_infos: Vec<FileInfo> = self.header.files().collect();
-        let expected_total_install_size = self.header.total_install_size();
-        let mut actual_total_install_size = 0;
-        let mut file_index = 0;
-        let mut archive = self.read_archive()?;
-        while let Some(mut file) = archive.next_file()? {
-            let file_info = &file_infos[file_index];
-            if file.file_size() != file_info.size() {
-                invalid_data!("Actual file size ({}) for {:?} does not match \
-                               expected size from package metadata ({})",
-                              file.file_size(),
-                              file_info.name(),
-                              file_info.size());
-            }
-            actual_total_install_size += file.file_size();
-            if !file_info.md5_checksum().is_empty() {
-                let actual_file_md5 = {
-                    let mut context = md5::Context::new();
-                    io::copy(&mut file, &mut context)?;
-                    format!("{:x}", context.compute())
+        // Check payload digest, if present:
+        if self.header.payload_digest().is_some() {
+            let actual_payload_digest =
+                match self.header.payload_digest_algorithm() {
+                    Some(algorithm) => {
+                        self.reader.seek(SeekFrom::Start(self.archive_start))?;
+                        let mut context = algorithm.writer();
+                        io::copy(&mut self.reader, &mut context)?;
+                        Some(context.finish_hex())
+                    }
+                    None => None,
                 };
-                let expected_file_md5 =
-                    file_info.md5_checksum().to_lowercase();
-                if actual_file_md5 != expected_file_md5 {
-                    invalid_data!("Actual file MD5 digest ({}) for {:?} does \
-                                   not match expected digest from package \
-                                   metadata ({})",
-                                  actual_file_md5,
-                                  file_info.name(),
-                                  expected_file_md5);
+            check_payload_digest(&self.header, actual_payload_digest)?;
+        }
+
+        // Check OpenPGP signatures, if present:
+        let mut verifications = Vec::new();
+        let header_start = self.header_start;
+        let header_size = self.archive_start - self.header_start;
+        for (name, region, data) in self.signature.openpgp_signatures() {
+            let signature = match parse_openpgp_signature(name, data)? {
+                ParsedSignature::Supported(signature) => signature,
+                ParsedSignature::Unsupported(key_id) => {
+                    let verification =
+                        SignatureVerification::unsupported(region, key_id);
+                    verifications.push(verification);
+                    continue;
                 }
+            };
+            let reader = &mut self.reader;
+            let digest = || {
+                let signed_size = match region {
+                    SignatureRegion::Header => header_size,
+                    SignatureRegion::HeaderAndPayload => {
+                        actual_header_and_archive_size
+                    }
+                };
+                reader.seek(SeekFrom::Start(header_start))?;
+                let mut writer = signature.digest_writer();
+                io::copy(&mut reader.by_ref().take(signed_size),
+                         &mut writer)?;
+                Ok(writer)
+            };
+            verifications.push(verify_openpgp_signature(name,
+                                                        region,
+                                                        &signature,
+                                                        keyring,
+                                                        digest)?);
+        }
+
+        // Check archive file sizes and digests:
+        self.reader.seek(SeekFrom::Start(self.archive_start))?;
+        let mut archive: ArchiveSection =
+            ArchiveSection::new(&self.payload_codecs,
+                                &self.header,
+                                Box::new(&mut self.reader))?;
+        check_archive_files(&self.header, &self.signature, &mut archive)?;
+
+        Ok(verifications)
+    }
+
+    /// Writes a copy of this package to `writer`, signed with the given key.
+    /// Any existing signatures of the same kind (RSA/EdDSA) are replaced, but
+    /// other signatures (e.g. DSA) are kept.  This is the equivalent of
+    /// `rpmsign --addsign`.
+    pub fn write_with_signature_added<W: Write>(&mut self, key: &SigningKey,
+                                                writer: W)
+                                                -> io::Result<()> {
+        let signature = self.signature.clone();
+        self.write_with_signature(signature, Some(key), writer)
+    }
+
+    /// Writes a copy of this package to `writer`, with all existing
+    /// signatures replaced by new ones made with the given key.  This is the
+    /// equivalent of `rpmsign --resign`.
+    pub fn write_resigned<W: Write>(&mut self, key: &SigningKey, writer: W)
+                                    -> io::Result<()> {
+        let mut signature = self.signature.clone();
+        signature.remove_openpgp_signatures();
+        self.write_with_signature(signature, Some(key), writer)
+    }
+
+    /// Writes a copy of this package to `writer`, with all OpenPGP signatures
+    /// removed.  This is the equivalent of `rpmsign --delsign`.
+    pub fn write_without_signatures<W: Write>(&mut self, writer: W)
+                                              -> io::Result<()> {
+        let mut signature = self.signature.clone();
+        signature.remove_openpgp_signatures();
+        self.write_with_signature(signature, None, writer)
+    }
+
+    /// Writes a copy of this package to `writer`, with the Lead, Header, and
+    /// Archive sections copied through unchanged, and with the given Signature
+    /// section (plus new signatures made by `key`, if any).
+    fn write_with_signature<W: Write>(&mut self,
+                                      mut signature: SignatureSection,
+                                      key: Option<&SigningKey>, mut writer: W)
+                                      -> io::Result<()> {
+        let header_size = self.archive_start - self.header_start;
+        let archive_end = self.reader.seek(SeekFrom::End(0))?;
+        let header_and_archive_size = archive_end - self.header_start;
+        if header_and_archive_size != signature.header_and_archive_size() {
+            invalid_data!("Actual package header/archive size ({}) does not \
+                           match expected size from package signature ({})",
+                          header_and_archive_size,
+                          signature.header_and_archive_size());
+        }
+        if let Some(key) = key {
+            let timestamp = convert::system_time_to_u32(SystemTime::now());
+            self.reader.seek(SeekFrom::Start(self.header_start))?;
+            let mut context = key.digest_writer();
+            io::copy(&mut self.reader.by_ref().take(header_size),
+                     &mut context)?;
+            let header_signature = key.sign(context, timestamp)?;
+            self.reader.seek(SeekFrom::Start(self.header_start))?;
+            let mut context = key.digest_writer();
+            io::copy(&mut self.reader.by_ref().take(header_and_archive_size),
+                     &mut context)?;
+            let header_and_payload_signature = key.sign(context, timestamp)?;
+            signature.set_openpgp_signatures(header_signature,
+                                             header_and_payload_signature);
+        }
+        // If the original Signature section reserved some space, try to keep
+        // the new section the same size, so that the Header doesn't move.
+        signature.refit_reserved_space(self.header_start -
+                                           self.signature_start);
+        self.reader.seek(SeekFrom::Start(self.lead_start))?;
+        io::copy(&mut self.reader
+                     .by_ref()
+                     .take(self.signature_start - self.lead_start),
+                 &mut writer)?;
+        writer.write_all(&signature.to_bytes()?)?;
+        self.reader.seek(SeekFrom::Start(self.header_start))?;
+        io::copy(&mut self.reader.by_ref().take(header_and_archive_size),
+                 &mut writer)?;
+        Ok(())
+    }
+}
+
+// ========================================================================= //
+
+/// Checks the actual combined size of the Header and Archive sections
+/// against the size recorded in the package signature.
+pub(crate) fn check_header_and_archive_size(signature: &SignatureSection,
+                                            actual_size: u64)
+                                            -> io::Result<()> {
+    let expected_size = signature.header_and_archive_size();
+    if actual_size != expected_size {
+        invalid_data!("Actual package header/archive size ({}) does not \
+                       match expected size from package signature ({})",
+                      actual_size,
+                      expected_size);
+    }
+    Ok(())
+}
+
+/// Checks the actual MD5 digest of the Header and Archive sections against
+/// the digest recorded in the package signature.
+pub(crate) fn check_header_and_archive_md5(signature: &SignatureSection,
+                                           actual_md5: md5::Digest)
+                                           -> io::Result<()> {
+    let expected_md5 = md5::Digest(*signature.header_and_archive_md5());
+    if actual_md5 != expected_md5 {
+        invalid_data!("Actual package header/archive MD5 digest ({:x}) does \
+                       not match expected digest from package signature \
+                       ({:x})",
+                      actual_md5,
+                      expected_md5);
+    }
+    Ok(())
+}
+
+/// Checks the actual SHA1 digest of the Header section against the digest
+/// recorded in the package signature, if any.
+pub(crate) fn check_header_sha1(signature: &SignatureSection,
+                                actual_sha1: &str)
+                                -> io::Result<()> {
+    if let Some(expected_sha1) = signature.header_sha1() {
+        if actual_sha1 != expected_sha1 {
+            invalid_data!("Actual package header SHA1 digest ({}) does not \
+                           match expected digest from package signature \
+                           ({})",
+                          actual_sha1,
+                          expected_sha1);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the actual SHA256 digest of the Header section against the digest
+/// recorded in the package signature, if any.
+pub(crate) fn check_header_sha256(signature: &SignatureSection,
+                                  actual_sha256: &str)
+                                  -> io::Result<()> {
+    if let Some(expected_sha256) = signature.header_sha256() {
+        if actual_sha256 != expected_sha256.to_lowercase() {
+            invalid_data!("Actual package header SHA256 digest ({}) does not \
+                           match expected digest from package signature \
+                           ({})",
+                          actual_sha256,
+                          expected_sha256);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the actual digest of the Archive section against the payload
+/// digest recorded in the package header, if any.  The actual digest should
+/// be `None` if the header's payload digest algorithm is unsupported.
+pub(crate) fn check_payload_digest(header: &HeaderSection,
+                                   actual_digest: Option<String>)
+                                   -> io::Result<()> {
+    if let Some(expected_digest) = header.payload_digest() {
+        let actual_digest = match actual_digest {
+            Some(digest) => digest,
+            None => {
+                invalid_data!("Unsupported payload digest algorithm in \
+                               package header");
             }
-            file_index += 1;
+        };
+        if actual_digest != expected_digest.to_lowercase() {
+            invalid_data!("Actual payload digest ({}) does not match \
+                           expected digest from package header ({})",
+                          actual_digest,
+                          expected_digest);
         }
+    }
+    Ok(())
+}
 
-        // Check total install size:
-        if actual_total_install_size != expected_total_install_size {
-            invalid_data!("Actual total install size ({}) does not match \
-                           expected size from package header ({})",
-                          actual_total_install_size,
-                          expected_total_install_size);
+/// Parses an OpenPGP signature from the package signature.  A signature that
+/// is well-formed but unsupported isn't an error, but a malformed one is.
+pub(crate) fn parse_openpgp_signature(name: &str, data: &[u8])
+                                      -> io::Result<ParsedSignature> {
+    match pgp::Signature::parse(data) {
+        Ok(parsed) => Ok(parsed),
+        Err(error) => {
+            invalid_data!("Malformed {} signature in package signature: {}",
+                          name,
+                          error);
         }
+    }
+}
 
-        // Check total archive uncompressed size, if present:
-        if let Some(expected_uncompressed_archive_size) =
-            opt_uncompressed_archive_size
+/// Verifies an OpenPGP signature from the package signature, if its key is
+/// in the given keyring.  The `digest` function is only called in that
+/// case, and should return a digest writer that has been fed the region of
+/// the package that the signature covers.
+pub(crate) fn verify_openpgp_signature<F>(name: &str,
+                                          region: SignatureRegion,
+                                          signature: &pgp::Signature,
+                                          keyring: &Keyring, digest: F)
+                                          -> io::Result<SignatureVerification>
+where
+    F: FnOnce() -> io::Result<DigestWriter>,
+{
+    let key_id = signature.issuer();
+    let verified = match key_id.and_then(|key_id| keyring.get(key_id)) {
+        Some(key) => {
+            if !signature.verify(key, digest()?) {
+                invalid_data!("{} signature in package signature does not \
+                               match key {:016X}",
+                              name,
+                              key.key_id());
+            }
+            true
+        }
+        None => false,
+    };
+    Ok(SignatureVerification::new(region,
+                                  key_id,
+                                  signature.algorithm(),
+                                  signature.digest_algorithm(),
+                                  verified))
+}
+
+/// Reads through every file in the archive, checking that each non-ghost
+/// file in the package metadata appears exactly once (with the data for each
+/// set of hard links appearing somewhere), and checking the file sizes and
+/// digests against the package metadata, as well as the total install size
+/// and uncompressed archive size.
+pub(crate) fn check_archive_files(header: &HeaderSection,
+                                  signature: &SignatureSection,
+                                  archive: &mut ArchiveSection)
+                                  -> io::Result<()> {
+    let file_infos: Vec<FileInfo> = header.files().collect();
+    let opt_file_digest_algorithm = header.file_digest_algorithm();
+    let expected_total_install_size = header.total_install_size();
+    let mut actual_total_install_size = 0;
+    // Archive files are matched up with the metadata by path, since ghost
+    // files aren't in the archive, and sets of hard links may be stored out
+    // of order.
+    let file_indices: HashMap<&str, usize> = file_infos
+        .iter()
+        .enumerate()
+        .map(|(index, file_info)| {
+                 (archive_path_key(file_info.name()), index)
+             })
+        .collect();
+    let hard_link_sets = header.hard_link_sets();
+    let mut link_set_indices = vec![None; file_infos.len()];
+    for (set_index, set) in hard_link_sets.iter().enumerate() {
+        for &index in set.iter() {
+            link_set_indices[index] = Some(set_index);
+        }
+    }
+    let mut seen = vec![false; file_infos.len()];
+    let mut link_set_has_data = vec![false; hard_link_sets.len()];
+    while let Some(mut file) = archive.next_file()? {
+        let index = match file_indices
+            .get(archive_path_key(file.file_path()))
         {
-            let actual_uncompressed_archive_size = archive.decoder.total_out();
-            if actual_uncompressed_archive_size !=
-                expected_uncompressed_archive_size
-            {
-                invalid_data!("Actual uncompressed archive size ({}) does \
-                               not match expected size from package signature \
-                               ({})",
-                              actual_uncompressed_archive_size,
-                              expected_uncompressed_archive_size);
+            Some(&index) => index,
+            None => {
+                invalid_data!("Package archive contains a file ({:?}) \
+                               that is not listed in package metadata",
+                              file.file_path());
             }
+        };
+        let file_info = &file_infos[index];
+        if seen[index] {
+            invalid_data!("Package archive contains more than one entry \
+                           for {:?}",
+                          file_info.name());
         }
+        seen[index] = true;
+        if file_info.is_regular_file() {
+            let link_count = match link_set_indices[index] {
+                Some(set_index) => hard_link_sets[set_index].len() as u32,
+                None => 1,
+            };
+            if file.link_count() != link_count {
+                invalid_data!("Actual link count ({}) for {:?} does not \
+                               match number of hard links in package \
+                               metadata ({})",
+                              file.link_count(),
+                              file_info.name(),
+                              link_count);
+            }
+        }
+        if let Some(set_index) = link_set_indices[index] {
+            // Within a set of hard links, only the last archive entry
+            // carries the file data.
+            if file.file_size() == 0 && file_info.size() != 0 {
+                continue;
+            }
+            link_set_has_data[set_index] = true;
+        }
+        if file.file_size() != file_info.size() {
+            invalid_data!("Actual file size ({}) for {:?} does not match \
+                           expected size from package metadata ({})",
+                          file.file_size(),
+                          file_info.name(),
+                          file_info.size());
+        }
+        actual_total_install_size += file.file_size();
+        if !file_info.digest().is_empty() {
+            let algorithm = match opt_file_digest_algorithm {
+                Some(algorithm) => algorithm,
+                None => {
+                    invalid_data!("Unsupported file digest algorithm in \
+                                   package header");
+                }
+            };
+            let actual_file_digest = {
+                let mut context = algorithm.writer();
+                io::copy(&mut file, &mut context)?;
+                context.finish_hex()
+            };
+            let expected_file_digest = file_info.digest().to_lowercase();
+            if actual_file_digest != expected_file_digest {
+                invalid_data!("Actual file {:?} digest ({}) for {:?} \
+                               does not match expected digest from \
+                               package metadata ({})",
+                              algorithm,
+                              actual_file_digest,
+                              file_info.name(),
+                              expected_file_digest);
+            }
+        }
+    }
+    for (index, file_info) in file_infos.iter().enumerate() {
+        if !seen[index] && !file_info.is_ghost() {
+            invalid_data!("Package archive is missing file {:?}, which is \
+                           listed in package metadata",
+                          file_info.name());
+        }
+    }
+    for (set_index, set) in hard_link_sets.iter().enumerate() {
+        if !link_set_has_data[set_index] {
+            invalid_data!("Package archive is missing the data for hard \
+                           links to {:?}",
+                          file_infos[set[0]].name());
+        }
+    }
 
-        Ok(())
+    // Check total install size:
+    if actual_total_install_size != expected_total_install_size {
+        invalid_data!("Actual total install size ({}) does not match \
+                       expected size from package header ({})",
+                      actual_total_install_size,
+                      expected_total_install_size);
     }
+
+    // Check total archive uncompressed size, if present:
+    if let Some(expected_uncompressed_archive_size) =
+        signature.uncompressed_archive_size()
+    {
+        let actual_uncompressed_archive_size = archive.decoder.total_out();
+        if actual_uncompressed_archive_size !=
+            expected_uncompressed_archive_size
+        {
+            invalid_data!("Actual uncompressed archive size ({}) does \
+                           not match expected size from package signature \
+                           ({})",
+                          actual_uncompressed_archive_size,
+                          expected_uncompressed_archive_size);
+        }
+    }
+
+    Ok(())
 }
 
 // ========================================================================= //
 
 /// The "Archive" section of an RPM package file.
-pub struct ArchiveSection<'p, R: 'p + Read + Seek> {
-    decoder: ArchiveDecoder<'p, R>,
+pub struct ArchiveSection<'p> {
+    decoder: ArchiveDecoder<'p>,
+    /// For a stripped cpio archive, the metadata for each file in the
+    /// package header, since the archive entries don't carry any.
+    stripped_entries: Option<Vec<StrippedEntry>>,
     done: bool,
 }
 
-impl<'p, R: 'p + Read + Seek> ArchiveSection<'p, R> {
-    fn new(compressor: &str, reader: &'p mut R)
-           -> io::Result<ArchiveSection<'p, R>> {
-        let decoder = match compressor {
-            "bzip2" => ArchiveDecoder::Bzip2(BzDecoder::new(reader)),
-            "gzip" => ArchiveDecoder::Gzip(GzDecoder::new(reader), 0),
-            "xz" => ArchiveDecoder::Xz(XzDecoder::new(reader)),
-            _ => {
+impl<'p> ArchiveSection<'p> {
+    /// Creates a reader for the Archive section described by `header`,
+    /// reading the compressed data from `reader`.
+    pub(crate) fn new(codecs: &PayloadCodecRegistry, header: &HeaderSection,
+                      reader: Box<dyn Read + 'p>)
+                      -> io::Result<ArchiveSection<'p>> {
+        let compressor = header.payload_compressor();
+        let stripped_entries = if header.has_stripped_payload() {
+            Some(stripped_entries(header))
+        } else {
+            None
+        };
+        let codec = match codecs.get(compressor) {
+            Some(codec) => codec,
+            None => {
                 invalid_data!("Unsupported payload compressor ({:?})",
                               compressor);
             }
         };
         Ok(ArchiveSection {
-               decoder,
+               decoder: ArchiveDecoder {
+                   decoder: codec.decoder(reader)?,
+                   total_out: 0,
+               },
+               stripped_entries,
                done: false,
            })
     }
 }
 
-impl<'a, 'p: 'a, R: 'p + Read + Seek> ArchiveSection<'p, R> {
+impl<'a, 'p: 'a> ArchiveSection<'p> {
     /// Returns a reader for the next file in the archive, if any.
     pub fn next_file(&'a mut self)
-                     -> io::Result<Option<FileReader<'a, 'p, R>>> {
+                     -> io::Result<Option<FileReader<'a, 'p>>> {
         if self.done {
             return Ok(None);
         }
-        let reader = NewcReader::new(&mut self.decoder)?;
-        if reader.entry().is_trailer() {
-            self.done = true;
-            return Ok(None);
+        let entries = match self.stripped_entries {
+            Some(ref entries) => entries,
+            None => {
+                let reader = NewcReader::new(&mut self.decoder)?;
+                if reader.entry().is_trailer() {
+                    self.done = true;
+                    return Ok(None);
+                }
+                let reader = EntryReader::Newc(reader);
+                return Ok(Some(FileReader { reader: Some(reader) }));
+            }
+        };
+        let mut magic = [0u8; 6];
+        self.decoder.read_exact(&mut magic)?;
+        if &magic != stripped::MAGIC_NUMBER {
+            // The trailer of a stripped archive is still a newc entry.
+            let decoder = &mut self.decoder;
+            let reader = NewcReader::new(Cursor::new(magic).chain(decoder))?;
+            if reader.entry().is_trailer() {
+                self.done = true;
+                return Ok(None);
+            }
+            invalid_data!("Stripped cpio archive contains a non-stripped \
+                           entry ({:?})",
+                          reader.entry().name());
         }
+        let index = stripped::read_file_index(&mut self.decoder)?;
+        let entry = match entries.get(index) {
+            Some(entry) => entry,
+            None => {
+                invalid_data!("Stripped cpio entry has invalid file index \
+                               ({}); package only has {} files",
+                              index,
+                              entries.len());
+            }
+        };
+        let reader = StrippedReader::new(&mut self.decoder, entry.size);
+        let reader = EntryReader::Stripped(reader, entry);
         Ok(Some(FileReader { reader: Some(reader) }))
     }
 }
 
-// ========================================================================= //
-
-enum ArchiveDecoder<'p, R: 'p + Read> {
-    Bzip2(BzDecoder<&'p mut R>),
-    Gzip(GzDecoder<&'p mut R>, u64),
-    Xz(XzDecoder<&'p mut R>),
+/// The metadata for a file in a stripped cpio archive, taken from the
+/// package header.
+struct StrippedEntry {
+    path: String,
+    /// The size of the entry's data, which (as with newc entries) is zero
+    /// for all but the last file in a set of hard links.
+    size: u64,
+    inode: u32,
+    link_count: u32,
 }
 
-impl<'p, R: Read> ArchiveDecoder<'p, R> {
-    fn total_out(&self) -> u64 {
-        match *self {
-            ArchiveDecoder::Bzip2(ref decoder) => decoder.total_out(),
-            ArchiveDecoder::Gzip(_, total_out) => total_out,
-            ArchiveDecoder::Xz(ref decoder) => decoder.total_out(),
+/// Returns the metadata for each file in the package header, for reading a
+/// stripped cpio archive.
+fn stripped_entries(header: &HeaderSection) -> Vec<StrippedEntry> {
+    let mut entries: Vec<StrippedEntry> = header
+        .files()
+        .map(|file_info| {
+            let size = if file_info.is_regular_file() ||
+                file_info.is_symlink()
+            {
+                file_info.size()
+            } else {
+                0
+            };
+            StrippedEntry {
+                path: file_info.name().to_string(),
+                size,
+                inode: file_info.inode(),
+                link_count: 1,
+            }
+        })
+        .collect();
+    for set in header.hard_link_sets() {
+        for &index in set.iter() {
+            entries[index].link_count = set.len() as u32;
+            if index != *set.last().unwrap() {
+                entries[index].size = 0;
+            }
         }
     }
+    entries
+}
+
+/// Returns the form of a file path used to match archive entries with
+/// package metadata (rpmbuild writes archive paths with a leading `./`).
+fn archive_path_key(path: &str) -> &str {
+    let path = if path.starts_with("./") { &path[1..] } else { path };
+    path.trim_start_matches('/')
+}
+
+// ========================================================================= //
+
+/// Decompresses the Archive section of a package, keeping count of the
+/// uncompressed bytes read.
+struct ArchiveDecoder<'p> {
+    decoder: Box<dyn Read + 'p>,
+    total_out: u64,
+}
+
+impl<'p> ArchiveDecoder<'p> {
+    fn total_out(&self) -> u64 { self.total_out }
 }
 
-impl<'p, R: Read> Read for ArchiveDecoder<'p, R> {
+impl<'p> Read for ArchiveDecoder<'p> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match *self {
-            ArchiveDecoder::Bzip2(ref mut decoder) => decoder.read(buf),
-            ArchiveDecoder::Gzip(ref mut decoder, ref mut total_out) => {
-                let bytes_read = decoder.read(buf)?;
-                *total_out += bytes_read as u64;
-                Ok(bytes_read)
-            }
-            ArchiveDecoder::Xz(ref mut decoder) => decoder.read(buf),
+        // The cpio readers ask for zero bytes at the end of each entry,
+        // which the zstd decoder treats as an error.
+        if buf.is_empty() {
+            return Ok(0);
         }
+        let bytes_read = self.decoder.read(buf)?;
+        self.total_out += bytes_read as u64;
+        Ok(bytes_read)
     }
 }
 
 // ========================================================================= //
 
 /// Reads data for one file in a package.
-pub struct FileReader<'a, 'p: 'a, R: 'p + Read> {
-    reader: Option<NewcReader<&'a mut ArchiveDecoder<'p, R>>>,
+pub struct FileReader<'a, 'p: 'a> {
+    reader: Option<EntryReader<'a, 'p>>,
+}
+
+enum EntryReader<'a, 'p: 'a> {
+    Newc(NewcReader<&'a mut ArchiveDecoder<'p>>),
+    Stripped(StrippedReader<&'a mut ArchiveDecoder<'p>>,
+             &'a StrippedEntry),
 }
 
-impl<'a, 'p, R: Read> FileReader<'a, 'p, R> {
+impl<'a, 'p> FileReader<'a, 'p> {
     /// Returns the install path of the file.
     pub fn file_path(&self) -> &str {
-        self.reader.as_ref().unwrap().entry().name()
+        match *self.reader.as_ref().unwrap() {
+            EntryReader::Newc(ref reader) => reader.entry().name(),
+            EntryReader::Stripped(_, entry) => &entry.path,
+        }
     }
 
     /// Returns the size of the file, in bytes.
-    pub fn file_size(&self) -> u32 {
-        self.reader.as_ref().unwrap().entry().file_size()
+    pub fn file_size(&self) -> u64 {
+        match *self.reader.as_ref().unwrap() {
+            EntryReader::Newc(ref reader) => {
+                u64::from(reader.entry().file_size())
+            }
+            EntryReader::Stripped(_, entry) => entry.size,
+        }
+    }
+
+    /// Returns the inode number of the file within the archive, which is
+    /// shared by hard links to the same file.
+    pub(crate) fn inode(&self) -> u32 {
+        match *self.reader.as_ref().unwrap() {
+            EntryReader::Newc(ref reader) => reader.entry().ino(),
+            EntryReader::Stripped(_, entry) => entry.inode,
+        }
+    }
+
+    /// Returns the number of hard links to the file within the archive.
+    pub(crate) fn link_count(&self) -> u32 {
+        match *self.reader.as_ref().unwrap() {
+            EntryReader::Newc(ref reader) => reader.entry().nlink(),
+            EntryReader::Stripped(_, entry) => entry.link_count,
+        }
     }
 }
 
-impl<'a, 'p, R: Read> Read for FileReader<'a, 'p, R> {
+impl<'a, 'p> Read for FileReader<'a, 'p> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.reader.as_mut().unwrap().read(buf)
+        match *self.reader.as_mut().unwrap() {
+            EntryReader::Newc(ref mut reader) => reader.read(buf),
+            EntryReader::Stripped(ref mut reader, _) => reader.read(buf),
+        }
     }
 }
 
-impl<'a, 'p, R: Read> Drop for FileReader<'a, 'p, R> {
-    fn drop(&mut self) { let _ = self.reader.take().unwrap().finish(); }
+impl<'a, 'p> Drop for FileReader<'a, 'p> {
+    fn drop(&mut self) {
+        match self.reader.take().unwrap() {
+            EntryReader::Newc(reader) => {
+                let _ = reader.finish();
+            }
+            EntryReader::Stripped(reader, _) => {
+                let _ = reader.finish();
+            }
+        }
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveSection, Package, check_archive_files};
+    use cpio;
+    use internal::builder::PackageBuilder;
+    use internal::codec::PayloadCodecRegistry;
+    use internal::header::{FileFlags, FileInfo};
+    use internal::lead::PackageType;
+    use std::io::{Cursor, Write};
+
+    /// Builds an uncompressed package containing a regular file, a pair of
+    /// hard links, and a ghost file.
+    fn build_package() -> Package<Cursor<Vec<u8>>> {
+        let mut builder = PackageBuilder::new(PackageType::Binary);
+        builder.set_payload_compression("identity", 0);
+        builder.add_file(FileInfo::new("/usr/bin/hello", 6).with_inode(1));
+        builder.add_file(FileInfo::new("/usr/bin/howdy", 6).with_inode(1));
+        builder.add_file(FileInfo::new("/usr/lib/empty", 0));
+        builder.add_file(FileInfo::new("/var/log/hello.log", 0)
+                             .with_flags(FileFlags::GHOST));
+        let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut writer) = builder.next_file().unwrap() {
+            if writer.file_path() == "/usr/bin/howdy" {
+                writer.write_all(b"Hello\n").unwrap();
+            }
+        }
+        let package_data = builder.finish().unwrap().into_inner();
+        Package::read(Cursor::new(package_data)).unwrap()
+    }
+
+    /// Checks the package's metadata against a cpio archive made up of the
+    /// given entries (path, link count, and data), and returns the error
+    /// message, if any.
+    fn check_entries(package: &Package<Cursor<Vec<u8>>>,
+                     entries: &[(&str, u32, &[u8])])
+                     -> Option<String> {
+        let mut data = Vec::new();
+        for &(path, link_count, contents) in entries {
+            let mut writer = cpio::newc::Builder::new(path)
+                .mode(0o100644)
+                .nlink(link_count)
+                .write(data, contents.len() as u32);
+            writer.write_all(contents).unwrap();
+            data = writer.finish().unwrap();
+        }
+        let data = cpio::newc::trailer(data).unwrap();
+        let mut archive: ArchiveSection =
+            ArchiveSection::new(&PayloadCodecRegistry::new(),
+                                &package.header,
+                                Box::new(data.as_slice()))
+                .unwrap();
+        check_archive_files(&package.header, &package.signature, &mut archive)
+            .err()
+            .map(|error| error.to_string())
+    }
+
+    #[test]
+    fn archive_must_match_file_list() {
+        let mut package = build_package();
+        package.validate().unwrap();
+        let hello: (&str, u32, &[u8]) = ("./usr/bin/hello", 2, b"");
+        let howdy: (&str, u32, &[u8]) = ("./usr/bin/howdy", 2, b"Hello\n");
+        let empty: (&str, u32, &[u8]) = ("./usr/lib/empty", 1, b"");
+
+        // A missing (empty) file:
+        let error = check_entries(&package, &[hello, howdy]).unwrap();
+        assert!(error.contains("missing file \"/usr/lib/empty\""),
+                "{}",
+                error);
+
+        // A duplicated entry, even if it makes up for a missing file:
+        let error = check_entries(&package, &[hello, howdy, empty, empty])
+            .unwrap();
+        assert!(error.contains("more than one entry"), "{}", error);
+        let error = check_entries(&package, &[howdy, howdy, empty]).unwrap();
+        assert!(error.contains("more than one entry"), "{}", error);
+
+        // A set of hard links whose data never appears:
+        let error = check_entries(&package,
+                                  &[hello,
+                                    ("./usr/bin/howdy", 2, b""),
+                                    empty])
+            .unwrap();
+        assert!(error.contains("missing the data for hard links"),
+                "{}",
+                error);
+
+        // An empty entry claiming to be a hard link, for a file that isn't
+        // one:
+        let error = check_entries(&package,
+                                  &[hello,
+                                    howdy,
+                                    ("./usr/lib/empty", 2, b"")])
+            .unwrap();
+        assert!(error.contains("link count"), "{}", error);
+        let error = check_entries(&package,
+                                  &[("./usr/bin/hello", 3, b""),
+                                    howdy,
+                                    empty])
+            .unwrap();
+        assert!(error.contains("link count"), "{}", error);
+    }
 }
 
 // ========================================================================= //
@@ -0,0 +1,1047 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use dsa;
+use dsa::signature::hazmat::PrehashVerifier;
+use ed25519_dalek::{self, Signer};
+use internal::digest::{DigestAlgorithm, DigestWriter};
+use rsa::{self, BigUint, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+use rsa::traits::PublicKeyParts;
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read, Write};
+
+// ========================================================================= //
+
+/// Packet tag for a signature.
+const PACKET_SIGNATURE: u8 = 2;
+/// Packet tag for a primary secret key (which includes the public key).
+const PACKET_SECRET_KEY: u8 = 5;
+/// Packet tag for a primary public key.
+const PACKET_PUBLIC_KEY: u8 = 6;
+/// Packet tag for a secret subkey (which includes the public subkey).
+const PACKET_SECRET_SUBKEY: u8 = 7;
+/// Packet tag for a public subkey.
+const PACKET_PUBLIC_SUBKEY: u8 = 14;
+
+/// Signature subpacket type for the signature creation time.
+const SUBPACKET_CREATION_TIME: u8 = 2;
+/// Signature subpacket type for the key ID of the signer.
+const SUBPACKET_ISSUER: u8 = 16;
+/// Signature subpacket type for the fingerprint of the signer's key.
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+
+/// Signature type for a signature over a binary document.
+const SIGNATURE_TYPE_BINARY: u8 = 0x00;
+
+const ALGORITHM_RSA: u8 = 1;
+const ALGORITHM_RSA_SIGN_ONLY: u8 = 3;
+const ALGORITHM_DSA: u8 = 17;
+const ALGORITHM_EDDSA_LEGACY: u8 = 22;
+const ALGORITHM_ED25519: u8 = 27;
+
+/// The curve OID (1.3.6.1.4.1.11591.15.1) used by legacy EdDSA keys.
+const ED25519_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f,
+                             0x01];
+
+/// The largest RSA modulus (in bits) that we'll accept.
+const MAX_RSA_BITS: usize = 16384;
+
+/// The hash algorithm used for new signatures.
+const SIGNING_DIGEST_ALGORITHM: DigestAlgorithm = DigestAlgorithm::Sha256;
+
+/// The length of a version 4 fingerprint (a SHA-1 hash).
+const FINGERPRINT_LEN: usize = 20;
+
+const ARMOR_BEGIN: &str = "-----BEGIN PGP ";
+const ARMOR_END: &str = "-----END PGP ";
+
+// ========================================================================= //
+
+/// A public-key algorithm used for OpenPGP signatures.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PublicKeyAlgorithm {
+    /// RSA with PKCS#1 v1.5 signatures.
+    Rsa,
+    /// The NIST Digital Signature Algorithm.
+    Dsa,
+    /// EdDSA over Curve25519 (Ed25519).
+    EdDsa,
+}
+
+impl PublicKeyAlgorithm {
+    fn from_number(number: u8) -> Option<PublicKeyAlgorithm> {
+        match number {
+            ALGORITHM_RSA | ALGORITHM_RSA_SIGN_ONLY => {
+                Some(PublicKeyAlgorithm::Rsa)
+            }
+            ALGORITHM_DSA => Some(PublicKeyAlgorithm::Dsa),
+            ALGORITHM_EDDSA_LEGACY | ALGORITHM_ED25519 => {
+                Some(PublicKeyAlgorithm::EdDsa)
+            }
+            _ => None,
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// A collection of OpenPGP public keys, used for verifying package
+/// signatures.
+pub struct Keyring {
+    keys: BTreeMap<u64, PublicKey>,
+}
+
+impl Keyring {
+    /// Creates a new, empty keyring.
+    pub fn new() -> Keyring { Keyring { keys: BTreeMap::new() } }
+
+    /// Reads one or more OpenPGP keys (either ASCII-armored or binary) and
+    /// adds them to the keyring.  All signing-capable primary keys and
+    /// subkeys are added; keys using unsupported algorithms are skipped.
+    /// Note that key binding signatures, expiration dates, and revocations
+    /// are not checked.
+    pub fn add_keys<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        for block in decode_blocks(&data)? {
+            for packet in read_packets(&block)? {
+                match packet.tag {
+                    PACKET_PUBLIC_KEY |
+                    PACKET_PUBLIC_SUBKEY |
+                    PACKET_SECRET_KEY |
+                    PACKET_SECRET_SUBKEY => {
+                        let mut cursor = Cursor::new(packet.body.as_slice());
+                        if let Some(key) = PublicKey::read(&mut cursor)? {
+                            self.keys.insert(key.key_id, key);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the IDs of all keys in the keyring.
+    pub fn key_ids(&self) -> Vec<u64> { self.keys.keys().cloned().collect() }
+
+    /// Returns true if the keyring contains no keys.
+    pub fn is_empty(&self) -> bool { self.keys.is_empty() }
+
+    pub(crate) fn get(&self, key_id: u64) -> Option<&PublicKey> {
+        self.keys.get(&key_id)
+    }
+}
+
+impl Default for Keyring {
+    fn default() -> Keyring { Keyring::new() }
+}
+
+// ========================================================================= //
+
+enum PublicKeyMaterial {
+    Rsa(RsaPublicKey),
+    Dsa(dsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+}
+
+/// An OpenPGP public key (either a primary key or a subkey).
+pub(crate) struct PublicKey {
+    algorithm: u8,
+    fingerprint: Vec<u8>,
+    key_id: u64,
+    material: PublicKeyMaterial,
+}
+
+impl PublicKey {
+    /// Reads the public portion of a key packet body, leaving the cursor
+    /// positioned just after it.  Returns `None` if the key uses an
+    /// unsupported version or algorithm.
+    fn read(cursor: &mut Cursor<&[u8]>) -> io::Result<Option<PublicKey>> {
+        let start = cursor.position() as usize;
+        let version = cursor.read_u8()?;
+        if version != 4 {
+            return Ok(None);
+        }
+        let _creation_time = cursor.read_u32::<BigEndian>()?;
+        let algorithm = cursor.read_u8()?;
+        let material = match algorithm {
+            ALGORITHM_RSA | ALGORITHM_RSA_SIGN_ONLY => {
+                let n = read_mpi(cursor)?;
+                let e = read_mpi(cursor)?;
+                let n = BigUint::from_bytes_be(&n);
+                let e = BigUint::from_bytes_be(&e);
+                match RsaPublicKey::new_with_max_size(n, e, MAX_RSA_BITS) {
+                    Ok(key) => PublicKeyMaterial::Rsa(key),
+                    Err(_) => invalid_data!("Invalid OpenPGP RSA public key"),
+                }
+            }
+            ALGORITHM_DSA => {
+                let p = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let q = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let g = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let y = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let key = dsa::Components::from_components(p, q, g)
+                    .and_then(|components| {
+                                  dsa::VerifyingKey::from_components(components,
+                                                                     y)
+                              });
+                match key {
+                    Ok(key) => PublicKeyMaterial::Dsa(key),
+                    Err(_) => invalid_data!("Invalid OpenPGP DSA public key"),
+                }
+            }
+            ALGORITHM_EDDSA_LEGACY => {
+                let oid_len = cursor.read_u8()? as usize;
+                let mut oid = vec![0u8; oid_len];
+                cursor.read_exact(&mut oid)?;
+                let point = read_mpi(cursor)?;
+                if oid.as_slice() != ED25519_OID {
+                    return Ok(None);
+                }
+                // The public point is prefixed with a 0x40 byte to indicate
+                // that it is in native (compressed) format.
+                if point.len() != 33 || point[0] != 0x40 {
+                    invalid_data!("Invalid OpenPGP EdDSA public key");
+                }
+                read_ed25519_key(&point[1..])?
+            }
+            ALGORITHM_ED25519 => {
+                let mut point = [0u8; 32];
+                cursor.read_exact(&mut point)?;
+                read_ed25519_key(&point)?
+            }
+            _ => return Ok(None),
+        };
+        let end = cursor.position() as usize;
+        let fingerprint = v4_fingerprint(&cursor.get_ref()[start..end]);
+        let key_id = key_id_from_fingerprint(&fingerprint);
+        let key = PublicKey {
+            algorithm,
+            fingerprint,
+            key_id,
+            material,
+        };
+        Ok(Some(key))
+    }
+
+    /// Returns the 64-bit key ID of this key.
+    pub(crate) fn key_id(&self) -> u64 { self.key_id }
+}
+
+fn read_ed25519_key(point: &[u8]) -> io::Result<PublicKeyMaterial> {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(point);
+    match ed25519_dalek::VerifyingKey::from_bytes(&bytes) {
+        Ok(key) => Ok(PublicKeyMaterial::EdDsa(key)),
+        Err(_) => invalid_data!("Invalid OpenPGP Ed25519 public key"),
+    }
+}
+
+/// Computes the fingerprint of a version 4 key from its public key packet
+/// body.
+fn v4_fingerprint(public_key_body: &[u8]) -> Vec<u8> {
+    let mut writer = DigestAlgorithm::Sha1.writer();
+    writer.write_all(&[0x99]).unwrap();
+    writer
+        .write_u16::<BigEndian>(public_key_body.len() as u16)
+        .unwrap();
+    writer.write_all(public_key_body).unwrap();
+    writer.finish()
+}
+
+/// Returns the key ID for a key fingerprint (the low 64 bits of the
+/// fingerprint).
+fn key_id_from_fingerprint(fingerprint: &[u8]) -> u64 {
+    let mut cursor = &fingerprint[fingerprint.len() - 8..];
+    cursor.read_u64::<BigEndian>().unwrap()
+}
+
+// ========================================================================= //
+
+enum SecretKeyMaterial {
+    Rsa(RsaPrivateKey),
+    EdDsa(ed25519_dalek::SigningKey),
+}
+
+/// An OpenPGP secret key, used for signing new packages.
+pub struct SigningKey {
+    public: PublicKey,
+    material: SecretKeyMaterial,
+}
+
+impl SigningKey {
+    /// Reads an unencrypted OpenPGP secret key (either ASCII-armored or
+    /// binary), such as one exported by `gpg --export-secret-keys`.  The first
+    /// RSA or EdDSA secret key found (normally the primary key) is used.
+    pub fn read<R: Read>(mut reader: R) -> io::Result<SigningKey> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        for block in decode_blocks(&data)? {
+            for packet in read_packets(&block)? {
+                if packet.tag != PACKET_SECRET_KEY &&
+                    packet.tag != PACKET_SECRET_SUBKEY
+                {
+                    continue;
+                }
+                let mut cursor = Cursor::new(packet.body.as_slice());
+                if let Some(public) = PublicKey::read(&mut cursor)? {
+                    if let Some(key) = SigningKey::read_secret(public,
+                                                               &mut cursor)?
+                    {
+                        return Ok(key);
+                    }
+                }
+            }
+        }
+        invalid_data!("No supported OpenPGP secret key found");
+    }
+
+    fn read_secret(public: PublicKey, cursor: &mut Cursor<&[u8]>)
+                   -> io::Result<Option<SigningKey>> {
+        if let PublicKeyMaterial::Dsa(_) = public.material {
+            return Ok(None);
+        }
+        let s2k_usage = cursor.read_u8()?;
+        if s2k_usage != 0 {
+            invalid_data!("Encrypted OpenPGP secret keys are not supported \
+                           (key {:016X})",
+                          public.key_id);
+        }
+        let material = match public.material {
+            PublicKeyMaterial::Rsa(ref key) => {
+                let d = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let p = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let q = BigUint::from_bytes_be(&read_mpi(cursor)?);
+                let _u = read_mpi(cursor)?;
+                match RsaPrivateKey::from_components(key.n().clone(),
+                                                     key.e().clone(),
+                                                     d,
+                                                     vec![p, q]) {
+                    Ok(key) => SecretKeyMaterial::Rsa(key),
+                    Err(_) => invalid_data!("Invalid OpenPGP RSA secret key"),
+                }
+            }
+            PublicKeyMaterial::EdDsa(ref key) => {
+                let mut seed = [0u8; 32];
+                if public.algorithm == ALGORITHM_ED25519 {
+                    cursor.read_exact(&mut seed)?;
+                } else {
+                    let mpi = read_mpi(cursor)?;
+                    if mpi.len() > 32 {
+                        invalid_data!("Invalid OpenPGP EdDSA secret key");
+                    }
+                    seed[32 - mpi.len()..].copy_from_slice(&mpi);
+                }
+                let signing_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+                if signing_key.verifying_key() != *key {
+                    invalid_data!("OpenPGP EdDSA secret key does not match \
+                                   its public key");
+                }
+                SecretKeyMaterial::EdDsa(signing_key)
+            }
+            PublicKeyMaterial::Dsa(_) => unreachable!(),
+        };
+        Ok(Some(SigningKey { public, material }))
+    }
+
+    /// Returns the 64-bit ID of this key.
+    pub fn key_id(&self) -> u64 { self.public.key_id }
+
+    /// Returns the public-key algorithm of this key.
+    pub fn algorithm(&self) -> PublicKeyAlgorithm {
+        PublicKeyAlgorithm::from_number(self.public.algorithm).unwrap()
+    }
+
+    /// Returns the maximum length of a signature packet made by this key.
+    pub(crate) fn max_signature_len(&self) -> usize {
+        let value_len = match self.material {
+            SecretKeyMaterial::Rsa(ref key) => 2 + key.size(),
+            SecretKeyMaterial::EdDsa(_) => {
+                if self.public.algorithm == ALGORITHM_ED25519 {
+                    64
+                } else {
+                    2 * (2 + 32)
+                }
+            }
+        };
+        // Packet header, then version/type/algorithms, hashed subpackets
+        // (creation time and issuer fingerprint), unhashed subpackets
+        // (issuer), left 16 bits of the hash, and finally the signature.
+        3 + 4 + (2 + 6 + 3 + FINGERPRINT_LEN) + (2 + 10) + 2 + value_len
+    }
+
+    /// Returns a writer for hashing the data to be signed.  Once all the data
+    /// has been written, pass the writer to `sign`.
+    pub(crate) fn digest_writer(&self) -> DigestWriter {
+        SIGNING_DIGEST_ALGORITHM.writer()
+    }
+
+    /// Signs the data that was written into `writer`, and returns the
+    /// encoded OpenPGP signature packet.
+    pub(crate) fn sign(&self, mut writer: DigestWriter, timestamp: u32)
+                       -> io::Result<Vec<u8>> {
+        let mut hashed = Vec::new();
+        hashed.write_u8(5)?;
+        hashed.write_u8(SUBPACKET_CREATION_TIME)?;
+        hashed.write_u32::<BigEndian>(timestamp)?;
+        hashed.write_u8(2 + FINGERPRINT_LEN as u8)?;
+        hashed.write_u8(SUBPACKET_ISSUER_FINGERPRINT)?;
+        hashed.write_u8(4)?;
+        hashed.write_all(&self.public.fingerprint)?;
+        let mut body = vec![4,
+                            SIGNATURE_TYPE_BINARY,
+                            self.public.algorithm,
+                            SIGNING_DIGEST_ALGORITHM.number() as u8];
+        body.write_u16::<BigEndian>(hashed.len() as u16)?;
+        body.write_all(&hashed)?;
+        writer.write_all(&body)?;
+        writer.write_all(&v4_trailer(body.len()))?;
+        let digest = writer.finish();
+        body.write_u16::<BigEndian>(10)?;
+        body.write_u8(9)?;
+        body.write_u8(SUBPACKET_ISSUER)?;
+        body.write_u64::<BigEndian>(self.public.key_id)?;
+        body.write_all(&digest[..2])?;
+        match self.material {
+            SecretKeyMaterial::Rsa(ref key) => {
+                let scheme = pkcs1v15_scheme(SIGNING_DIGEST_ALGORITHM);
+                match key.sign(scheme, &digest) {
+                    Ok(value) => write_mpi(&mut body, &value)?,
+                    Err(error) => {
+                        invalid_input!("Failed to make RSA signature: {}",
+                                       error);
+                    }
+                }
+            }
+            SecretKeyMaterial::EdDsa(ref key) => {
+                let value = key.sign(&digest).to_bytes();
+                if self.public.algorithm == ALGORITHM_ED25519 {
+                    body.write_all(&value)?;
+                } else {
+                    write_mpi(&mut body, &value[..32])?;
+                    write_mpi(&mut body, &value[32..])?;
+                }
+            }
+        }
+        // Use an old-format packet header with a two-byte length.
+        let mut packet = vec![0x80 | (PACKET_SIGNATURE << 2) | 1];
+        packet.write_u16::<BigEndian>(body.len() as u16)?;
+        packet.write_all(&body)?;
+        debug_assert!(packet.len() <= self.max_signature_len());
+        Ok(packet)
+    }
+}
+
+// ========================================================================= //
+
+enum SignatureValue {
+    Rsa(Vec<u8>),
+    Dsa(BigUint, BigUint),
+    EdDsa([u8; 64]),
+}
+
+/// The result of parsing an OpenPGP signature packet.
+pub(crate) enum ParsedSignature {
+    /// The signature was parsed, and can be verified.
+    Supported(Signature),
+    /// The signature is well-formed, but uses an unsupported version, hash
+    /// algorithm, or public-key algorithm.  Includes the ID of the key that
+    /// made the signature, if known.
+    Unsupported(Option<u64>),
+}
+
+/// A parsed OpenPGP signature packet.
+pub(crate) struct Signature {
+    algorithm: PublicKeyAlgorithm,
+    digest_algorithm: DigestAlgorithm,
+    issuer: Option<u64>,
+    trailer: Vec<u8>,
+    left16: [u8; 2],
+    value: SignatureValue,
+}
+
+impl Signature {
+    /// Parses a binary OpenPGP signature packet (including the packet
+    /// header), as stored in an RPM signature entry.  Returns an error if the
+    /// packet is malformed.
+    pub(crate) fn parse(data: &[u8]) -> io::Result<ParsedSignature> {
+        let mut packets = read_packets(data)?;
+        if packets.len() != 1 || packets[0].tag != PACKET_SIGNATURE {
+            invalid_data!("Expected a single OpenPGP signature packet");
+        }
+        let body = packets.pop().unwrap().body;
+        let mut cursor = Cursor::new(body.as_slice());
+        let version = cursor.read_u8()?;
+        let signature_type;
+        let algorithm;
+        let digest_algorithm;
+        let mut issuer = None;
+        let trailer;
+        match version {
+            3 => {
+                let hashed_len = cursor.read_u8()?;
+                if hashed_len != 5 {
+                    invalid_data!("Invalid hashed length in version 3 \
+                                   OpenPGP signature ({})",
+                                  hashed_len);
+                }
+                let mut hashed = [0u8; 5];
+                cursor.read_exact(&mut hashed)?;
+                signature_type = hashed[0];
+                trailer = hashed.to_vec();
+                issuer = Some(cursor.read_u64::<BigEndian>()?);
+                algorithm = cursor.read_u8()?;
+                digest_algorithm = cursor.read_u8()?;
+            }
+            4 => {
+                signature_type = cursor.read_u8()?;
+                algorithm = cursor.read_u8()?;
+                digest_algorithm = cursor.read_u8()?;
+                let hashed_len = cursor.read_u16::<BigEndian>()? as usize;
+                let mut hashed = vec![0u8; hashed_len];
+                cursor.read_exact(&mut hashed)?;
+                let unhashed_len = cursor.read_u16::<BigEndian>()? as usize;
+                let mut unhashed = vec![0u8; unhashed_len];
+                cursor.read_exact(&mut unhashed)?;
+                for (kind, value) in read_subpackets(&hashed)?
+                    .into_iter()
+                    .chain(read_subpackets(&unhashed)?)
+                {
+                    match kind {
+                        SUBPACKET_ISSUER if value.len() == 8 => {
+                            issuer = Some(key_id_from_fingerprint(value));
+                        }
+                        SUBPACKET_ISSUER_FINGERPRINT if value.len() == 21 &&
+                                                        value[0] == 4 => {
+                            issuer = Some(key_id_from_fingerprint(value));
+                        }
+                        _ => {}
+                    }
+                }
+                let hashed_end = 6 + hashed_len;
+                let mut bytes = body[..hashed_end].to_vec();
+                bytes.extend_from_slice(&v4_trailer(hashed_end));
+                trailer = bytes;
+            }
+            _ => return Ok(ParsedSignature::Unsupported(None)),
+        }
+        if signature_type != SIGNATURE_TYPE_BINARY {
+            invalid_data!("Unsupported OpenPGP signature type (0x{:02x})",
+                          signature_type);
+        }
+        let digest_algorithm =
+            match DigestAlgorithm::from_number(digest_algorithm as u32) {
+                Some(digest_algorithm) => digest_algorithm,
+                None => return Ok(ParsedSignature::Unsupported(issuer)),
+            };
+        let public_key_algorithm =
+            match PublicKeyAlgorithm::from_number(algorithm) {
+                Some(public_key_algorithm) => public_key_algorithm,
+                None => return Ok(ParsedSignature::Unsupported(issuer)),
+            };
+        let mut left16 = [0u8; 2];
+        cursor.read_exact(&mut left16)?;
+        let value = match algorithm {
+            ALGORITHM_RSA | ALGORITHM_RSA_SIGN_ONLY => {
+                SignatureValue::Rsa(read_mpi(&mut cursor)?)
+            }
+            ALGORITHM_DSA => {
+                let r = BigUint::from_bytes_be(&read_mpi(&mut cursor)?);
+                let s = BigUint::from_bytes_be(&read_mpi(&mut cursor)?);
+                SignatureValue::Dsa(r, s)
+            }
+            ALGORITHM_EDDSA_LEGACY => {
+                let r = read_mpi(&mut cursor)?;
+                let s = read_mpi(&mut cursor)?;
+                if r.len() > 32 || s.len() > 32 {
+                    invalid_data!("Invalid OpenPGP EdDSA signature");
+                }
+                // MPIs have leading zeros stripped, so pad each half of the
+                // signature back out to 32 bytes.
+                let mut bytes = [0u8; 64];
+                bytes[32 - r.len()..32].copy_from_slice(&r);
+                bytes[64 - s.len()..].copy_from_slice(&s);
+                SignatureValue::EdDsa(bytes)
+            }
+            ALGORITHM_ED25519 => {
+                let mut bytes = [0u8; 64];
+                cursor.read_exact(&mut bytes)?;
+                SignatureValue::EdDsa(bytes)
+            }
+            _ => unreachable!(),
+        };
+        let signature = Signature {
+            algorithm: public_key_algorithm,
+            digest_algorithm,
+            issuer,
+            trailer,
+            left16,
+            value,
+        };
+        Ok(ParsedSignature::Supported(signature))
+    }
+
+    /// Returns the public-key algorithm used to make this signature.
+    pub(crate) fn algorithm(&self) -> PublicKeyAlgorithm { self.algorithm }
+
+    /// Returns the hash algorithm used to make this signature.
+    pub(crate) fn digest_algorithm(&self) -> DigestAlgorithm {
+        self.digest_algorithm
+    }
+
+    /// Returns the ID of the key that made this signature, if known.
+    pub(crate) fn issuer(&self) -> Option<u64> { self.issuer }
+
+    /// Returns a writer for hashing the signed data.  Once all the signed
+    /// data has been written, pass the writer to `verify`.
+    pub(crate) fn digest_writer(&self) -> DigestWriter {
+        self.digest_algorithm.writer()
+    }
+
+    /// Returns true if this is a valid signature by `key` over the data that
+    /// was written into `writer`.
+    pub(crate) fn verify(&self, key: &PublicKey, mut writer: DigestWriter)
+                         -> bool {
+        writer.write_all(&self.trailer).unwrap();
+        let digest = writer.finish();
+        if digest[..2] != self.left16 {
+            return false;
+        }
+        match (&key.material, &self.value) {
+            (&PublicKeyMaterial::Rsa(ref key),
+             &SignatureValue::Rsa(ref value)) => {
+                let size = rsa::traits::PublicKeyParts::size(key);
+                if value.len() > size {
+                    return false;
+                }
+                let mut padded = vec![0u8; size - value.len()];
+                padded.extend_from_slice(value);
+                let scheme = pkcs1v15_scheme(self.digest_algorithm);
+                key.verify(scheme, &digest, &padded).is_ok()
+            }
+            (&PublicKeyMaterial::Dsa(ref key),
+             &SignatureValue::Dsa(ref r, ref s)) => {
+                match dsa::Signature::from_components(r.clone(), s.clone()) {
+                    Ok(signature) => {
+                        key.verify_prehash(&digest, &signature).is_ok()
+                    }
+                    Err(_) => false,
+                }
+            }
+            (&PublicKeyMaterial::EdDsa(ref key),
+             &SignatureValue::EdDsa(ref value)) => {
+                let signature = ed25519_dalek::Signature::from_bytes(value);
+                key.verify_strict(&digest, &signature).is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the final trailer that is hashed after the hashed portion of a
+/// version 4 signature packet, which contains the version and the length of
+/// the hashed portion.
+fn v4_trailer(hashed_len: usize) -> [u8; 6] {
+    let len = hashed_len as u32;
+    [4, 0xff, (len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]
+}
+
+/// Returns the PKCS#1 v1.5 signature scheme (i.e. the ASN.1 DigestInfo
+/// prefix) for the given digest algorithm.
+fn pkcs1v15_scheme(algorithm: DigestAlgorithm) -> Pkcs1v15Sign {
+    let prefix: &[u8] = match algorithm {
+        DigestAlgorithm::Md5 => {
+            &[0x30, 0x20, 0x30, 0x0c, 0x06, 0x08, 0x2a, 0x86, 0x48, 0x86,
+              0xf7, 0x0d, 0x02, 0x05, 0x05, 0x00, 0x04, 0x10]
+        }
+        DigestAlgorithm::Sha1 => {
+            &[0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02,
+              0x1a, 0x05, 0x00, 0x04, 0x14]
+        }
+        DigestAlgorithm::Sha224 => {
+            &[0x30, 0x2d, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01,
+              0x65, 0x03, 0x04, 0x02, 0x04, 0x05, 0x00, 0x04, 0x1c]
+        }
+        DigestAlgorithm::Sha256 => {
+            &[0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01,
+              0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20]
+        }
+        DigestAlgorithm::Sha384 => {
+            &[0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01,
+              0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30]
+        }
+        DigestAlgorithm::Sha512 => {
+            &[0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01,
+              0x65, 0x03, 0x04, 0x02, 0x03, 0x05, 0x00, 0x04, 0x40]
+        }
+    };
+    Pkcs1v15Sign {
+        hash_len: Some(algorithm.digest_len()),
+        prefix: prefix.to_vec().into_boxed_slice(),
+    }
+}
+
+// ========================================================================= //
+
+struct Packet {
+    tag: u8,
+    body: Vec<u8>,
+}
+
+/// Splits binary OpenPGP data into packets.
+fn read_packets(data: &[u8]) -> io::Result<Vec<Packet>> {
+    let mut cursor = Cursor::new(data);
+    let mut packets = Vec::new();
+    while (cursor.position() as usize) < data.len() {
+        let header = cursor.read_u8()?;
+        if header & 0x80 == 0 {
+            invalid_data!("Invalid OpenPGP packet header (0x{:02x})", header);
+        }
+        let (tag, length) = if header & 0x40 == 0 {
+            // Old-format packet header:
+            let tag = (header >> 2) & 0x0f;
+            let length = match header & 0x03 {
+                0 => cursor.read_u8()? as usize,
+                1 => cursor.read_u16::<BigEndian>()? as usize,
+                2 => cursor.read_u32::<BigEndian>()? as usize,
+                _ => data.len() - cursor.position() as usize,
+            };
+            (tag, length)
+        } else {
+            // New-format packet header:
+            let tag = header & 0x3f;
+            let first = cursor.read_u8()? as usize;
+            let length = if first < 192 {
+                first
+            } else if first < 224 {
+                ((first - 192) << 8) + cursor.read_u8()? as usize + 192
+            } else if first == 255 {
+                cursor.read_u32::<BigEndian>()? as usize
+            } else {
+                invalid_data!("Partial-length OpenPGP packets are not \
+                               supported");
+            };
+            (tag, length)
+        };
+        if length > data.len() - cursor.position() as usize {
+            invalid_data!("Truncated OpenPGP packet (tag {})", tag);
+        }
+        let mut body = vec![0u8; length];
+        cursor.read_exact(&mut body)?;
+        packets.push(Packet { tag, body });
+    }
+    Ok(packets)
+}
+
+/// Splits the subpacket area of a version 4 signature into (type, value)
+/// pairs.
+fn read_subpackets(data: &[u8]) -> io::Result<Vec<(u8, &[u8])>> {
+    let mut cursor = Cursor::new(data);
+    let mut subpackets = Vec::new();
+    while (cursor.position() as usize) < data.len() {
+        let first = cursor.read_u8()? as usize;
+        let length = if first < 192 {
+            first
+        } else if first < 255 {
+            ((first - 192) << 8) + cursor.read_u8()? as usize + 192
+        } else {
+            cursor.read_u32::<BigEndian>()? as usize
+        };
+        let start = cursor.position() as usize;
+        if length == 0 || length > data.len() - start {
+            invalid_data!("Invalid OpenPGP signature subpacket length");
+        }
+        // The high bit of the type is the "critical" flag.
+        let kind = data[start] & 0x7f;
+        subpackets.push((kind, &data[(start + 1)..(start + length)]));
+        cursor.set_position((start + length) as u64);
+    }
+    Ok(subpackets)
+}
+
+/// Reads an OpenPGP multiprecision integer, returning its big-endian bytes.
+fn read_mpi<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let bits = reader.read_u16::<BigEndian>()? as usize;
+    let mut bytes = vec![0u8; (bits + 7) / 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Writes an OpenPGP multiprecision integer, given its big-endian bytes.
+fn write_mpi<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let start = bytes.iter().position(|&byte| byte != 0).unwrap_or(bytes.len());
+    let bytes = &bytes[start..];
+    let bits = match bytes.first() {
+        Some(&first) => 8 * bytes.len() - first.leading_zeros() as usize,
+        None => 0,
+    };
+    writer.write_u16::<BigEndian>(bits as u16)?;
+    writer.write_all(bytes)
+}
+
+// ========================================================================= //
+
+/// Decodes OpenPGP data that may be either binary or ASCII-armored, and
+/// returns the binary contents of each block.
+fn decode_blocks(data: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    if data.first().map(|&byte| byte & 0x80 != 0).unwrap_or(false) {
+        return Ok(vec![data.to_vec()]);
+    }
+    let text = match ::std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => invalid_data!("OpenPGP data is neither binary nor armored"),
+    };
+    let mut blocks = Vec::new();
+    let mut lines = text.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if !line.starts_with(ARMOR_BEGIN) {
+            continue;
+        }
+        // Skip armor headers, which end with a blank line.
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+        }
+        let mut base64 = String::new();
+        let mut checksum = None;
+        let mut ended = false;
+        for line in lines.by_ref() {
+            if line.starts_with(ARMOR_END) {
+                ended = true;
+                break;
+            } else if line.starts_with('=') {
+                checksum = Some(decode_base64(&line[1..])?);
+            } else {
+                base64.push_str(line);
+            }
+        }
+        if !ended {
+            invalid_data!("Unterminated OpenPGP armor block");
+        }
+        let block = decode_base64(&base64)?;
+        if let Some(checksum) = checksum {
+            let crc = crc24(&block);
+            if checksum != [(crc >> 16) as u8, (crc >> 8) as u8, crc as u8] {
+                invalid_data!("Invalid checksum in OpenPGP armor block");
+            }
+        }
+        blocks.push(block);
+    }
+    if blocks.is_empty() {
+        invalid_data!("No OpenPGP armor blocks found");
+    }
+    Ok(blocks)
+}
+
+fn decode_base64(text: &str) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(text.len() * 3 / 4);
+    let mut accumulator: u32 = 0;
+    let mut num_bits = 0;
+    for byte in text.bytes() {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => invalid_data!("Invalid character in base64 data"),
+        };
+        accumulator = (accumulator << 6) | value as u32;
+        num_bits += 6;
+        if num_bits >= 8 {
+            num_bits -= 8;
+            output.push((accumulator >> num_bits) as u8);
+        }
+    }
+    Ok(output)
+}
+
+/// Computes the CRC-24 checksum used by OpenPGP ASCII armor.
+fn crc24(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xb704ce;
+    for &byte in data {
+        crc ^= (byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= 0x1864cfb;
+            }
+        }
+    }
+    crc & 0xffffff
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{Keyring, ParsedSignature, PublicKeyAlgorithm, Signature,
+                SigningKey, crc24, decode_base64};
+    use std::io::Write;
+
+    const MESSAGE: &[u8] = include_bytes!("../../tests/data/message.txt");
+
+    fn parse_supported(data: &[u8]) -> Signature {
+        match Signature::parse(data).unwrap() {
+            ParsedSignature::Supported(signature) => signature,
+            ParsedSignature::Unsupported(_) => panic!("Unsupported signature"),
+        }
+    }
+
+    fn check_signature(key: &[u8], signature: &[u8], key_id: u64,
+                       algorithm: PublicKeyAlgorithm) {
+        let mut keyring = Keyring::new();
+        keyring.add_keys(key).unwrap();
+        assert_eq!(keyring.key_ids(), vec![key_id]);
+        let key = keyring.get(key_id).unwrap();
+        let signature = parse_supported(signature);
+        assert_eq!(signature.algorithm(), algorithm);
+        assert_eq!(signature.issuer(), Some(key_id));
+        let mut writer = signature.digest_writer();
+        writer.write_all(MESSAGE).unwrap();
+        assert!(signature.verify(key, writer));
+        let mut writer = signature.digest_writer();
+        writer.write_all(b"Some other message.\n").unwrap();
+        assert!(!signature.verify(key, writer));
+    }
+
+    #[test]
+    fn base64_decoding() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("Zg==").unwrap(), b"f");
+        assert_eq!(decode_base64("Zm8=").unwrap(), b"fo");
+        assert_eq!(decode_base64("Zm9v").unwrap(), b"foo");
+        assert_eq!(decode_base64("Zm9vYmFy").unwrap(), b"foobar");
+        assert!(decode_base64("Zm9v!").is_err());
+    }
+
+    #[test]
+    fn crc24_checksum() {
+        assert_eq!(crc24(b""), 0xb704ce);
+        assert_eq!(crc24(b"123456789"), 0x21cf02);
+    }
+
+    #[test]
+    fn verify_rsa_signature() {
+        check_signature(include_bytes!("../../tests/data/rsa.asc"),
+                        include_bytes!("../../tests/data/message.rsa.sig"),
+                        0xef3c0aaec98136d4,
+                        PublicKeyAlgorithm::Rsa);
+    }
+
+    #[test]
+    fn verify_dsa_signature() {
+        check_signature(include_bytes!("../../tests/data/dsa.asc"),
+                        include_bytes!("../../tests/data/message.dsa.sig"),
+                        0x96f54324d16923dd,
+                        PublicKeyAlgorithm::Dsa);
+    }
+
+    #[test]
+    fn verify_eddsa_signature() {
+        check_signature(include_bytes!("../../tests/data/ed25519.asc"),
+                        include_bytes!("../../tests/data/message.ed25519.sig"),
+                        0xdeb501a8275e7d4e,
+                        PublicKeyAlgorithm::EdDsa);
+    }
+
+    #[test]
+    fn binary_keys() {
+        let mut keyring = Keyring::new();
+        keyring.add_keys(&include_bytes!("../../tests/data/rsa.gpg")[..])
+            .unwrap();
+        assert_eq!(keyring.key_ids(), vec![0xef3c0aaec98136d4]);
+    }
+
+    #[test]
+    fn wrong_key() {
+        let mut keyring = Keyring::new();
+        keyring.add_keys(&include_bytes!("../../tests/data/dsa.asc")[..])
+            .unwrap();
+        let key = keyring.get(0x96f54324d16923dd).unwrap();
+        let signature =
+            parse_supported(include_bytes!("../../tests/data/message.rsa.sig"));
+        let mut writer = signature.digest_writer();
+        writer.write_all(MESSAGE).unwrap();
+        assert!(!signature.verify(key, writer));
+    }
+
+    fn check_signing(secret: &[u8], key_id: u64,
+                     algorithm: PublicKeyAlgorithm) {
+        let signing_key = SigningKey::read(secret).unwrap();
+        assert_eq!(signing_key.key_id(), key_id);
+        assert_eq!(signing_key.algorithm(), algorithm);
+        let mut writer = signing_key.digest_writer();
+        writer.write_all(MESSAGE).unwrap();
+        let packet = signing_key.sign(writer, 1500000000).unwrap();
+        assert!(packet.len() <= signing_key.max_signature_len());
+        let mut keyring = Keyring::new();
+        keyring.add_keys(secret).unwrap();
+        let key = keyring.get(key_id).unwrap();
+        let signature = parse_supported(&packet);
+        assert_eq!(signature.algorithm(), algorithm);
+        assert_eq!(signature.issuer(), Some(key_id));
+        let mut writer = signature.digest_writer();
+        writer.write_all(MESSAGE).unwrap();
+        assert!(signature.verify(key, writer));
+    }
+
+    #[test]
+    fn sign_with_rsa_key() {
+        check_signing(include_bytes!("../../tests/data/rsa-secret.asc"),
+                      0xef3c0aaec98136d4,
+                      PublicKeyAlgorithm::Rsa);
+    }
+
+    #[test]
+    fn sign_with_eddsa_key() {
+        check_signing(include_bytes!("../../tests/data/ed25519-secret.asc"),
+                      0xdeb501a8275e7d4e,
+                      PublicKeyAlgorithm::EdDsa);
+    }
+
+    #[test]
+    fn unsupported_and_malformed_signatures() {
+        let secret = include_bytes!("../../tests/data/rsa-secret.asc");
+        let signing_key = SigningKey::read(&secret[..]).unwrap();
+        let key_id = signing_key.key_id();
+        let mut writer = signing_key.digest_writer();
+        writer.write_all(MESSAGE).unwrap();
+        let packet = signing_key.sign(writer, 1500000000).unwrap();
+        // After the 3-byte packet header come the version, signature type,
+        // public-key algorithm, and hash algorithm.
+        let patched = |index: usize, value: u8| {
+            let mut packet = packet.clone();
+            packet[index] = value;
+            packet
+        };
+
+        // Unknown versions and algorithms make the signature unsupported,
+        // but the issuer is kept when it can be read.
+        for &(index, value, issuer) in &[(3, 6, None),
+                                         (5, 19, Some(key_id)),
+                                         (6, 99, Some(key_id))]
+        {
+            match Signature::parse(&patched(index, value)).unwrap() {
+                ParsedSignature::Unsupported(id) => assert_eq!(id, issuer),
+                ParsedSignature::Supported(_) => panic!("Supported"),
+            }
+        }
+
+        // Malformed packets are errors.
+        assert!(Signature::parse(&packet[..packet.len() - 10]).is_err());
+        assert!(Signature::parse(&packet[..3]).is_err());
+        assert!(Signature::parse(&patched(4, 0x01)).is_err());
+        assert!(Signature::parse(b"garbage").is_err());
+    }
+
+    #[test]
+    fn public_key_is_not_signing_key() {
+        assert!(SigningKey::read(&include_bytes!("../../tests/data/rsa.asc")
+                                     [..])
+            .is_err());
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,158 @@
+// ========================================================================= //
+
+/// The default interpreter for scriptlets.
+const DEFAULT_INTERPRETER: &str = "/bin/sh";
+
+// ========================================================================= //
+
+/// The point in a transaction at which a scriptlet is run.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ScriptletType {
+    /// Runs before the package is installed (`%pre`).
+    PreInstall,
+    /// Runs after the package is installed (`%post`).
+    PostInstall,
+    /// Runs before the package is uninstalled (`%preun`).
+    PreUninstall,
+    /// Runs after the package is uninstalled (`%postun`).
+    PostUninstall,
+    /// Runs at the start of a transaction that installs the package
+    /// (`%pretrans`).
+    PreTransaction,
+    /// Runs at the end of a transaction that installs the package
+    /// (`%posttrans`).
+    PostTransaction,
+    /// Runs at the start of a transaction that uninstalls the package
+    /// (`%preuntrans`).  Requires rpm 4.19 or later.
+    PreUninstallTransaction,
+    /// Runs at the end of a transaction that uninstalls the package
+    /// (`%postuntrans`).  Requires rpm 4.19 or later.
+    PostUninstallTransaction,
+}
+
+impl ScriptletType {
+    /// Returns a list of all scriptlet types.
+    pub fn all() -> &'static [ScriptletType] {
+        &[
+            ScriptletType::PreInstall,
+            ScriptletType::PostInstall,
+            ScriptletType::PreUninstall,
+            ScriptletType::PostUninstall,
+            ScriptletType::PreTransaction,
+            ScriptletType::PostTransaction,
+            ScriptletType::PreUninstallTransaction,
+            ScriptletType::PostUninstallTransaction,
+        ]
+    }
+
+    /// Returns the name of the spec file section for this type of scriptlet
+    /// (e.g. `"%post"`).
+    pub fn section_name(&self) -> &'static str {
+        match *self {
+            ScriptletType::PreInstall => "%pre",
+            ScriptletType::PostInstall => "%post",
+            ScriptletType::PreUninstall => "%preun",
+            ScriptletType::PostUninstall => "%postun",
+            ScriptletType::PreTransaction => "%pretrans",
+            ScriptletType::PostTransaction => "%posttrans",
+            ScriptletType::PreUninstallTransaction => "%preuntrans",
+            ScriptletType::PostUninstallTransaction => "%postuntrans",
+        }
+    }
+}
+
+// ========================================================================= //
+
+bitflags! {
+    /// Flags controlling how a scriptlet is processed before it is run (the
+    /// `RPMSCRIPT_FLAG_*` values).
+    pub struct ScriptletFlags: u32 {
+        /// Macros in the scriptlet body are expanded at install time.
+        const EXPAND = 1 << 0;
+        /// Header query format tags in the scriptlet body are expanded at
+        /// install time.
+        const QFORMAT = 1 << 1;
+        /// A failure of the scriptlet is treated as fatal (rpm 4.18 and
+        /// later).
+        const CRITICAL = 1 << 2;
+    }
+}
+
+// ========================================================================= //
+
+/// A script that is run when a package is installed or uninstalled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Scriptlet {
+    body: String,
+    interpreter: String,
+    flags: ScriptletFlags,
+}
+
+impl Scriptlet {
+    /// Constructs a new scriptlet with the given body, to be run with
+    /// `/bin/sh`.
+    pub fn new<S: Into<String>>(body: S) -> Scriptlet {
+        Scriptlet {
+            body: body.into(),
+            interpreter: DEFAULT_INTERPRETER.to_string(),
+            flags: ScriptletFlags::empty(),
+        }
+    }
+
+    /// Returns this scriptlet with the given interpreter (e.g.
+    /// `"/usr/bin/lua"`).  If the body is empty, the interpreter is simply
+    /// run with no script (e.g. `"/sbin/ldconfig"`).
+    pub fn with_interpreter<S: Into<String>>(mut self, interpreter: S)
+                                             -> Scriptlet {
+        self.interpreter = interpreter.into();
+        self
+    }
+
+    /// Returns this scriptlet with the given flags.
+    pub fn with_flags(mut self, flags: ScriptletFlags) -> Scriptlet {
+        self.flags = flags;
+        self
+    }
+
+    /// Returns the body of the script, which may be empty.
+    pub fn body(&self) -> &str { &self.body }
+
+    /// Returns the path to the interpreter that runs the script.
+    pub fn interpreter(&self) -> &str { &self.interpreter }
+
+    /// Returns the flags for this scriptlet.
+    pub fn flags(&self) -> ScriptletFlags { self.flags }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{Scriptlet, ScriptletFlags, ScriptletType};
+
+    #[test]
+    fn scriptlet_defaults() {
+        let scriptlet = Scriptlet::new("systemctl daemon-reload");
+        assert_eq!(scriptlet.body(), "systemctl daemon-reload");
+        assert_eq!(scriptlet.interpreter(), "/bin/sh");
+        assert_eq!(scriptlet.flags(), ScriptletFlags::empty());
+        let scriptlet = Scriptlet::new("")
+            .with_interpreter("/sbin/ldconfig")
+            .with_flags(ScriptletFlags::EXPAND);
+        assert_eq!(scriptlet.interpreter(), "/sbin/ldconfig");
+        assert_eq!(scriptlet.flags().bits(), 1);
+    }
+
+    #[test]
+    fn scriptlet_section_names() {
+        let names: Vec<&str> = ScriptletType::all()
+            .iter()
+            .map(ScriptletType::section_name)
+            .collect();
+        assert_eq!(names,
+                   vec!["%pre", "%post", "%preun", "%postun", "%pretrans",
+                        "%posttrans", "%preuntrans", "%postuntrans"]);
+    }
+}
+
+// ========================================================================= //
@@ -1,6 +1,8 @@
 use internal::convert::Sha1Writer;
+use internal::digest::DigestAlgorithm;
 use internal::index::{IndexTable, IndexType, IndexValue};
-use std::io::{self, Read, Seek, Write};
+use internal::pgp::PublicKeyAlgorithm;
+use std::io::{self, Cursor, Read, Seek, Write};
 
 // ========================================================================= //
 
@@ -9,45 +11,112 @@ const SECTION: &str = "Signature";
 
 // ========================================================================= //
 
-/// Required tag for the combined size of the Header and Archive sections.
+/// Tag for the combined size of the Header and Archive sections.  Either this
+/// or `TAG_LONGSIZE` is required.
 const TAG_SIZE: i32 = 1000;
+/// Tag for the combined size of the Header and Archive sections, as a 64-bit
+/// value.  Used instead of `TAG_SIZE` for packages of 4 GiB or more.
+const TAG_LONGSIZE: i32 = 270;
 /// Optional tag for the uncompressed size of the Archive section, including
 /// the cpio headers.
 const TAG_PAYLOAD_SIZE: i32 = 1007;
+/// Optional tag for the uncompressed size of the Archive section, as a 64-bit
+/// value.  Used instead of `TAG_PAYLOAD_SIZE` for archives of 4 GiB or more.
+const TAG_LONGARCHIVESIZE: i32 = 271;
 
 /// Optional tag for the SHA1 checksum of the Header section.
 const TAG_SHA1: i32 = 269;
+/// Optional tag for the SHA256 checksum of the Header section.
+const TAG_SHA256: i32 = 273;
 /// Required tag for the 128-bit MD5 checksum of the Header and Archive
 /// sections.
 const TAG_MD5: i32 = 1004;
 
+/// Optional tag for a DSA OpenPGP signature of the Header section.
+const TAG_DSA: i32 = 267;
+/// Optional tag for an RSA (or EdDSA) OpenPGP signature of the Header
+/// section.
+const TAG_RSA: i32 = 268;
+/// Optional tag for an RSA (or EdDSA) OpenPGP signature of the Header and
+/// Archive sections.
+const TAG_PGP: i32 = 1002;
+/// Optional tag for a DSA OpenPGP signature of the Header and Archive
+/// sections.
+const TAG_GPG: i32 = 1005;
+
+/// Optional tag for a block of zeros that reserves space in the Signature
+/// section, so that signatures can be changed without moving the Header.
+const TAG_RESERVEDSPACE: i32 = 1008;
+
+/// The minimum length of the RESERVEDSPACE entry in a placeholder section.
+const MIN_RESERVED_SPACE: usize = 8;
+
 // Known index entires for Signature section.  The bool indicates whether the
 // entry is required (true) or optional (false).
 #[cfg_attr(rustfmt, rustfmt_skip)]
 const ENTRIES: &[(bool, &str, i32, IndexType, Option<usize>)] = &[
-    (true,  "SIZE",         TAG_SIZE,         IndexType::Int32,  Some(1)),
-    (false, "PAYLOAD_SIZE", TAG_PAYLOAD_SIZE, IndexType::Int32,  Some(1)),
-    (false, "SHA1",         TAG_SHA1,         IndexType::String, None),
-    (true,  "MD5",          TAG_MD5,          IndexType::Binary, Some(16)),
-    // TODO: Add tags for DSA/RSA/PGP/GPG
+    (false, "SIZE",          TAG_SIZE,          IndexType::Int32,  Some(1)),
+    (false, "LONGSIGSIZE",   TAG_LONGSIZE,      IndexType::Int64,  Some(1)),
+    (false, "PAYLOAD_SIZE",  TAG_PAYLOAD_SIZE,  IndexType::Int32,  Some(1)),
+    (false, "LONGARCHIVESIZE", TAG_LONGARCHIVESIZE, IndexType::Int64,
+     Some(1)),
+    (false, "SHA1",          TAG_SHA1,          IndexType::String, None),
+    (false, "SHA256",        TAG_SHA256,        IndexType::String, None),
+    (true,  "MD5",           TAG_MD5,           IndexType::Binary, Some(16)),
+    (false, "DSAHEADER",     TAG_DSA,           IndexType::Binary, None),
+    (false, "RSAHEADER",     TAG_RSA,           IndexType::Binary, None),
+    (false, "PGP",           TAG_PGP,           IndexType::Binary, None),
+    (false, "GPG",           TAG_GPG,           IndexType::Binary, None),
+    (false, "RESERVEDSPACE", TAG_RESERVEDSPACE, IndexType::Binary, None),
+];
+
+// Signature section entries that hold OpenPGP signatures, along with the
+// region of the package that each one covers.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const OPENPGP_ENTRIES: &[(&str, i32, SignatureRegion)] = &[
+    ("DSAHEADER", TAG_DSA, SignatureRegion::Header),
+    ("RSAHEADER", TAG_RSA, SignatureRegion::Header),
+    ("PGP",       TAG_PGP, SignatureRegion::HeaderAndPayload),
+    ("GPG",       TAG_GPG, SignatureRegion::HeaderAndPayload),
 ];
 
 // ========================================================================= //
 
 /// The "Signature" section of an RPM package file.
+#[derive(Clone)]
 pub struct SignatureSection {
     table: IndexTable,
 }
 
 impl SignatureSection {
-    pub(crate) fn placeholder() -> SignatureSection {
+    /// Creates a placeholder section with space for the checksums, and (if
+    /// `signature_len` is given) for two OpenPGP signatures of up to that
+    /// many bytes each.  If `long_sizes` is true, the package and archive
+    /// sizes are stored as 64-bit values, which is required if either might
+    /// be 4 GiB or more.
+    pub(crate) fn placeholder(signature_len: Option<usize>, long_sizes: bool)
+                              -> SignatureSection {
         let mut table = IndexTable::new();
-        table.set(TAG_SIZE, IndexValue::Int32(vec![0]));
-        table.set(TAG_PAYLOAD_SIZE, IndexValue::Int32(vec![0]));
+        if long_sizes {
+            table.set(TAG_LONGSIZE, IndexValue::Int64(vec![0]));
+            table.set(TAG_LONGARCHIVESIZE, IndexValue::Int64(vec![0]));
+        } else {
+            table.set(TAG_SIZE, IndexValue::Int32(vec![0]));
+            table.set(TAG_PAYLOAD_SIZE, IndexValue::Int32(vec![0]));
+        }
         table.set(TAG_MD5, IndexValue::Binary(vec![0; 16]));
         let empty_sha1 = Sha1Writer::new().digest();
         debug_assert_eq!(empty_sha1.len(), 40);
         table.set(TAG_SHA1, IndexValue::String(empty_sha1));
+        let empty_sha256 = DigestAlgorithm::Sha256.writer().finish_hex();
+        debug_assert_eq!(empty_sha256.len(), 64);
+        table.set(TAG_SHA256, IndexValue::String(empty_sha256));
+        if let Some(len) = signature_len {
+            table.set(TAG_RSA, IndexValue::Binary(vec![0; len]));
+            table.set(TAG_PGP, IndexValue::Binary(vec![0; len]));
+            table.set(TAG_RESERVEDSPACE,
+                      IndexValue::Binary(vec![0; MIN_RESERVED_SPACE]));
+        }
         table.add_signatures_index();
         SignatureSection { table }
     }
@@ -58,6 +127,11 @@ impl SignatureSection {
         for &(required, name, tag, itype, count) in ENTRIES.iter() {
             table.expect_type(SECTION, required, name, tag, itype, count)?;
         }
+        if !table.has(TAG_SIZE) && !table.has(TAG_LONGSIZE) {
+            invalid_data!("Missing SIZE entry (tag {}) in {} section",
+                          TAG_SIZE,
+                          SECTION);
+        }
         Ok(SignatureSection { table: table })
     }
 
@@ -65,6 +139,53 @@ impl SignatureSection {
         self.table.write(writer, true)
     }
 
+    /// Returns the encoded bytes of this section (including padding).
+    pub(crate) fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        self.write(&mut cursor)?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Returns the number of bytes that this section takes up when written
+    /// (including padding).
+    pub(crate) fn written_size(&self) -> io::Result<u64> {
+        Ok(self.to_bytes()?.len() as u64)
+    }
+
+    /// Resizes the RESERVEDSPACE entry so that this section takes up exactly
+    /// `size` bytes when written.  Returns an error if that isn't possible.
+    pub(crate) fn fit_to_size(&mut self, size: u64) -> io::Result<()> {
+        let current_size = self.written_size()?;
+        let reserved = match self.table.get_binary(TAG_RESERVEDSPACE) {
+            Some(data) => data.len() as i64,
+            None => 0,
+        };
+        let reserved = reserved + (size as i64) - (current_size as i64);
+        if reserved > 0 {
+            self.table.set(TAG_RESERVEDSPACE,
+                           IndexValue::Binary(vec![0; reserved as usize]));
+        }
+        let new_size = self.written_size()?;
+        if new_size != size {
+            invalid_input!("{} section needs {} bytes, but only {} bytes \
+                            are available",
+                           SECTION,
+                           new_size,
+                           size);
+        }
+        Ok(())
+    }
+
+    /// If this section has a RESERVEDSPACE entry, resizes it so that the
+    /// section takes up exactly `size` bytes when written, or removes it if
+    /// that isn't possible.
+    pub(crate) fn refit_reserved_space(&mut self, size: u64) {
+        if self.table.has(TAG_RESERVEDSPACE) && self.fit_to_size(size).is_err()
+        {
+            self.table.remove(TAG_RESERVEDSPACE);
+        }
+    }
+
     /// Returns the raw underlying index table.
     pub fn table(&self) -> &IndexTable { &self.table }
 
@@ -79,6 +200,17 @@ impl SignatureSection {
         self.table.set(TAG_SHA1, IndexValue::String(digest));
     }
 
+    /// Returns the expected SHA256 checksum of the package's Header section,
+    /// if any.
+    pub fn header_sha256(&self) -> Option<&str> {
+        self.table.get_string(TAG_SHA256)
+    }
+
+    pub(crate) fn set_header_sha256(&mut self, digest: String) {
+        debug_assert_eq!(digest.len(), 64);
+        self.table.set(TAG_SHA256, IndexValue::String(digest));
+    }
+
     /// Returns the expected MD5 checksum of the package's Header and Archive
     /// sections.
     pub fn header_and_archive_md5(&self) -> &[u8; 16] {
@@ -95,30 +227,181 @@ impl SignatureSection {
     /// Returns the expected combined size of the package's Header and Archive
     /// sections.
     pub fn header_and_archive_size(&self) -> u64 {
-        self.table.get_nth_int32(TAG_SIZE, 0).unwrap() as u64
+        match self.table.get_nth_int64(TAG_LONGSIZE, 0) {
+            Some(size) => size,
+            None => self.table.get_nth_int32(TAG_SIZE, 0).unwrap() as u64,
+        }
     }
 
-    pub(crate) fn set_header_and_archive_size(&mut self, size: u64) {
-        self.table.set(TAG_SIZE, IndexValue::Int32(vec![size as u32]));
+    /// Sets the combined size of the Header and Archive sections.  Returns
+    /// an error if the size doesn't fit in the entry that this section has
+    /// room for, since the section can't grow once the package is written.
+    pub(crate) fn set_header_and_archive_size(&mut self, size: u64)
+                                              -> io::Result<()> {
+        self.set_size("SIZE", TAG_SIZE, TAG_LONGSIZE, size)
     }
 
     /// Returns the expected uncompressed size (if any) of the package's
     /// Archive section.
     pub fn uncompressed_archive_size(&self) -> Option<u64> {
-        self.table.get_nth_int32(TAG_PAYLOAD_SIZE, 0).map(|size| size as u64)
+        match self.table.get_nth_int64(TAG_LONGARCHIVESIZE, 0) {
+            Some(size) => Some(size),
+            None => {
+                self.table
+                    .get_nth_int32(TAG_PAYLOAD_SIZE, 0)
+                    .map(|size| size as u64)
+            }
+        }
+    }
+
+    /// Sets the uncompressed size of the Archive section.  Returns an error
+    /// if the size doesn't fit in the entry that this section has room for.
+    pub(crate) fn set_uncompressed_archive_size(&mut self, size: u64)
+                                                -> io::Result<()> {
+        self.set_size("PAYLOAD_SIZE",
+                      TAG_PAYLOAD_SIZE,
+                      TAG_LONGARCHIVESIZE,
+                      size)
+    }
+
+    fn set_size(&mut self, name: &str, tag: i32, long_tag: i32, size: u64)
+                -> io::Result<()> {
+        if self.table.has(long_tag) {
+            self.table.set(long_tag, IndexValue::Int64(vec![size]));
+        } else if size <= u32::MAX as u64 {
+            self.table.set(tag, IndexValue::Int32(vec![size as u32]));
+        } else {
+            invalid_input!("Size ({}) is too large for the {} entry (tag {}) \
+                            in {} section",
+                           size,
+                           name,
+                           tag,
+                           SECTION);
+        }
+        Ok(())
+    }
+
+    /// Returns true if this section contains any OpenPGP signatures.
+    pub fn has_openpgp_signatures(&self) -> bool {
+        OPENPGP_ENTRIES.iter().any(|&(_, tag, _)| self.table.has(tag))
+    }
+
+    /// Sets the OpenPGP signatures of the Header section and of the Header and
+    /// Archive sections, as made by an RSA or EdDSA key.
+    pub(crate) fn set_openpgp_signatures(&mut self, header: Vec<u8>,
+                                         header_and_payload: Vec<u8>) {
+        self.table.set(TAG_RSA, IndexValue::Binary(header));
+        self.table.set(TAG_PGP, IndexValue::Binary(header_and_payload));
     }
 
-    pub(crate) fn set_uncompressed_archive_size(&mut self, size: u64) {
-        self.table.set(TAG_PAYLOAD_SIZE, IndexValue::Int32(vec![size as u32]));
+    /// Removes all OpenPGP signatures from this section.
+    pub(crate) fn remove_openpgp_signatures(&mut self) {
+        for &(_, tag, _) in OPENPGP_ENTRIES.iter() {
+            self.table.remove(tag);
+        }
+    }
+
+    /// Returns the name, covered region, and raw packet data of each OpenPGP
+    /// signature in this section.
+    pub(crate) fn openpgp_signatures(&self)
+                                     -> Vec<(&'static str, SignatureRegion,
+                                             &[u8])> {
+        let mut signatures = Vec::new();
+        for &(name, tag, region) in OPENPGP_ENTRIES.iter() {
+            if let Some(data) = self.table.get_binary(tag) {
+                signatures.push((name, region, data));
+            }
+        }
+        signatures
     }
 }
 
 // ========================================================================= //
 
+/// A region of a package that can be covered by an OpenPGP signature.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SignatureRegion {
+    /// The signature covers only the Header section.
+    Header,
+    /// The signature covers both the Header and Archive sections.
+    HeaderAndPayload,
+}
+
+// ========================================================================= //
+
+/// The result of checking one OpenPGP signature in a package.
+#[derive(Clone, Debug)]
+pub struct SignatureVerification {
+    region: SignatureRegion,
+    key_id: Option<u64>,
+    algorithm: Option<PublicKeyAlgorithm>,
+    digest_algorithm: Option<DigestAlgorithm>,
+    verified: bool,
+}
+
+impl SignatureVerification {
+    pub(crate) fn new(region: SignatureRegion, key_id: Option<u64>,
+                      algorithm: PublicKeyAlgorithm,
+                      digest_algorithm: DigestAlgorithm, verified: bool)
+                      -> SignatureVerification {
+        SignatureVerification {
+            region,
+            key_id,
+            algorithm: Some(algorithm),
+            digest_algorithm: Some(digest_algorithm),
+            verified,
+        }
+    }
+
+    /// Creates the result for a signature that uses an unsupported OpenPGP
+    /// version or algorithm, and so can't be verified.
+    pub(crate) fn unsupported(region: SignatureRegion, key_id: Option<u64>)
+                              -> SignatureVerification {
+        SignatureVerification {
+            region,
+            key_id,
+            algorithm: None,
+            digest_algorithm: None,
+            verified: false,
+        }
+    }
+
+    /// Returns the region of the package covered by the signature.
+    pub fn region(&self) -> SignatureRegion { self.region }
+
+    /// Returns true if the signature uses a supported format and algorithms,
+    /// so that it could be verified given the right key.
+    pub fn is_supported(&self) -> bool { self.algorithm.is_some() }
+
+    /// Returns the 64-bit ID of the key that made the signature, or `None`
+    /// if the signature doesn't identify its key (or is in an unsupported
+    /// format).
+    pub fn key_id(&self) -> Option<u64> { self.key_id }
+
+    /// Returns the public-key algorithm used for the signature, or `None` if
+    /// the signature is unsupported.
+    pub fn algorithm(&self) -> Option<PublicKeyAlgorithm> { self.algorithm }
+
+    /// Returns the hash algorithm used for the signature, or `None` if the
+    /// signature is unsupported.
+    pub fn digest_algorithm(&self) -> Option<DigestAlgorithm> {
+        self.digest_algorithm
+    }
+
+    /// Returns true if the signature was verified against a key in the
+    /// keyring, or false if the signature is unsupported or the keyring
+    /// didn't contain the signing key.  (Signatures that fail verification
+    /// are reported as errors instead.)
+    pub fn is_verified(&self) -> bool { self.verified }
+}
+
+// ========================================================================= //
+
 #[cfg(test)]
 mod tests {
-    use super::ENTRIES;
+    use super::{ENTRIES, OPENPGP_ENTRIES, SignatureSection};
     use std::collections::HashSet;
+    use std::io::Cursor;
 
     #[test]
     fn tags_are_unique() {
@@ -128,6 +411,35 @@ mod tests {
             tags.insert(tag);
         }
     }
+
+    #[test]
+    fn openpgp_entries_are_known() {
+        for &(name, tag, _) in OPENPGP_ENTRIES.iter() {
+            assert!(ENTRIES
+                        .iter()
+                        .any(|&(_, entry_name, entry_tag, _, _)| {
+                                 entry_name == name && entry_tag == tag
+                             }));
+        }
+    }
+
+    #[test]
+    fn long_sizes() {
+        let mut signature = SignatureSection::placeholder(None, false);
+        signature.set_header_and_archive_size(1 << 20).unwrap();
+        assert!(signature.set_header_and_archive_size(5 << 30).is_err());
+        assert!(signature.set_uncompressed_archive_size(5 << 30).is_err());
+
+        let mut signature = SignatureSection::placeholder(None, true);
+        let size = signature.written_size().unwrap();
+        signature.set_header_and_archive_size(5 << 30).unwrap();
+        signature.set_uncompressed_archive_size(6 << 30).unwrap();
+        assert_eq!(signature.written_size().unwrap(), size);
+        let bytes = signature.to_bytes().unwrap();
+        let signature = SignatureSection::read(Cursor::new(bytes)).unwrap();
+        assert_eq!(signature.header_and_archive_size(), 5 << 30);
+        assert_eq!(signature.uncompressed_archive_size(), Some(6 << 30));
+    }
 }
 
 // ========================================================================= //
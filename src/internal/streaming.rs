@@ -0,0 +1,277 @@
+use internal::codec::PayloadCodecRegistry;
+use internal::convert::Sha1Writer;
+use internal::digest::{DigestAlgorithm, DigestWriter};
+use internal::header::HeaderSection;
+use internal::lead::LeadSection;
+use internal::package::{self, ArchiveSection};
+use internal::pgp::{self, Keyring, ParsedSignature};
+use internal::signature::{SignatureRegion, SignatureSection,
+                          SignatureVerification};
+use md5;
+use std::io::{self, Read, Write};
+
+// ========================================================================= //
+
+/// An RPM package file read in a single pass, from a reader that need not be
+/// seekable (such as a pipe or an HTTP response body).
+///
+/// The Lead, Signature, and Header sections are parsed up front.  After
+/// that, the Archive section can be read (at most once), and then `finish`
+/// reads through to the end of the stream and checks the package against
+/// its signature.  The digests needed for that are computed along the way,
+/// so nothing is read twice.
+pub struct StreamingPackage<R: Read> {
+    reader: DigestReader<R>,
+    lead: LeadSection,
+    signature: SignatureSection,
+    header: HeaderSection,
+    header_size: u64,
+    header_sha1: String,
+    header_sha256: String,
+    payload_codecs: PayloadCodecRegistry,
+    archive_started: bool,
+}
+
+impl<R: Read> StreamingPackage<R> {
+    /// Reads the Lead, Signature, and Header sections of a package, leaving
+    /// the reader positioned at the start of the Archive section.
+    pub fn read(mut reader: R) -> io::Result<StreamingPackage<R>> {
+        let lead = LeadSection::read(reader.by_ref())?;
+        let signature = SignatureSection::read(reader.by_ref())?;
+        let mut header_data = Vec::new();
+        let header = HeaderSection::read(TeeReader {
+                                             inner: reader.by_ref(),
+                                             copy: &mut header_data,
+                                         })?;
+        // Digests of the Header section alone can be finished right away;
+        // the others carry on through the Archive section.
+        let mut header_sha1 = Sha1Writer::new();
+        header_sha1.write_all(&header_data)?;
+        let mut header_sha256 = DigestAlgorithm::Sha256.writer();
+        header_sha256.write_all(&header_data)?;
+        let mut md5 = md5::Context::new();
+        md5.consume(&header_data);
+        let mut signatures = Vec::new();
+        for (name, region, data) in signature.openpgp_signatures() {
+            // Unsupported and malformed signatures are reported by `finish`,
+            // rather than preventing the package from being read.
+            let verifier = match package::parse_openpgp_signature(name, data) {
+                Ok(ParsedSignature::Supported(signature)) => {
+                    let mut writer = signature.digest_writer();
+                    writer.write_all(&header_data)?;
+                    PendingVerifier::Supported(signature, Box::new(writer))
+                }
+                Ok(ParsedSignature::Unsupported(key_id)) => {
+                    PendingVerifier::Unsupported(key_id)
+                }
+                Err(error) => PendingVerifier::Malformed(error),
+            };
+            signatures.push(PendingSignature {
+                                name,
+                                region,
+                                verifier,
+                            });
+        }
+        let payload_digest =
+            header.payload_digest_algorithm().map(|algorithm| {
+                                                      algorithm.writer()
+                                                  });
+        let reader = DigestReader {
+            inner: reader,
+            bytes_read: 0,
+            md5,
+            payload_digest,
+            signatures,
+        };
+        Ok(StreamingPackage {
+               reader,
+               lead,
+               signature,
+               header,
+               header_size: header_data.len() as u64,
+               header_sha1: header_sha1.digest(),
+               header_sha256: header_sha256.finish_hex(),
+               payload_codecs: PayloadCodecRegistry::new(),
+               archive_started: false,
+           })
+    }
+
+    /// Returns the lead section.
+    pub fn lead(&self) -> &LeadSection { &self.lead }
+
+    /// Returns the signature section.
+    pub fn signature(&self) -> &SignatureSection { &self.signature }
+
+    /// Returns the header section.
+    pub fn header(&self) -> &HeaderSection { &self.header }
+
+    /// Sets the codecs available for decompressing the Archive section.  By
+    /// default, all of the built-in codecs are available.
+    pub fn set_payload_codecs(&mut self, codecs: PayloadCodecRegistry) {
+        self.payload_codecs = codecs;
+    }
+
+    /// Reads files from the Archive section.  Since the package is only read
+    /// once, this returns an error if the archive has already been started.
+    pub fn read_archive(&mut self) -> io::Result<ArchiveSection> {
+        self.start_archive()?;
+        ArchiveSection::new(&self.payload_codecs,
+                            &self.header,
+                            Box::new(&mut self.reader))
+    }
+
+    /// Reads the rest of the package, and validates the package checksums
+    /// and signature, verifying any OpenPGP signatures made by keys in the
+    /// given keyring.  Returns an error if any of the validation checks
+    /// fail; otherwise, returns the results for each OpenPGP signature in the
+    /// package.  Signatures that use an unsupported format or algorithm are
+    /// reported as unsupported rather than as errors, but malformed
+    /// signatures are errors.
+    ///
+    /// Unlike `validate_with_keyring`, this doesn't check the individual
+    /// files in the archive (which may already have been read with
+    /// `read_archive`), although they are still covered by the payload
+    /// digest.
+    pub fn finish(mut self, keyring: &Keyring)
+                  -> io::Result<Vec<SignatureVerification>> {
+        io::copy(&mut self.reader, &mut io::sink())?;
+        let reader = self.reader;
+        package::check_header_and_archive_size(&self.signature,
+                                               self.header_size +
+                                                   reader.bytes_read)?;
+        package::check_header_and_archive_md5(&self.signature,
+                                              reader.md5.compute())?;
+        package::check_header_sha1(&self.signature, &self.header_sha1)?;
+        package::check_header_sha256(&self.signature, &self.header_sha256)?;
+        let payload_digest =
+            reader.payload_digest.map(DigestWriter::finish_hex);
+        package::check_payload_digest(&self.header, payload_digest)?;
+        let mut verifications = Vec::new();
+        for pending in reader.signatures {
+            let PendingSignature {
+                name,
+                region,
+                verifier,
+            } = pending;
+            let verification = match verifier {
+                PendingVerifier::Supported(signature, writer) => {
+                    package::verify_openpgp_signature(name,
+                                                      region,
+                                                      &signature,
+                                                      keyring,
+                                                      || Ok(*writer))?
+                }
+                PendingVerifier::Unsupported(key_id) => {
+                    SignatureVerification::unsupported(region, key_id)
+                }
+                PendingVerifier::Malformed(error) => return Err(error),
+            };
+            verifications.push(verification);
+        }
+        Ok(verifications)
+    }
+
+    /// Reads the rest of the package, and validates the package checksums
+    /// and signature, as well as the size and digest of each file in the
+    /// archive; returns an error if any of the validation checks fail.  The
+    /// archive must not have been started yet.  OpenPGP signatures can't be
+    /// verified without any keys; use `validate_with_keyring` to check them.
+    pub fn validate(self) -> io::Result<()> {
+        self.validate_with_keyring(&Keyring::new()).map(|_| ())
+    }
+
+    /// Like `validate`, but also verifies any OpenPGP signatures made by keys
+    /// in the given keyring, and returns the results for each OpenPGP
+    /// signature in the package.
+    pub fn validate_with_keyring(mut self, keyring: &Keyring)
+                                 -> io::Result<Vec<SignatureVerification>> {
+        self.start_archive()?;
+        {
+            let mut archive: ArchiveSection =
+                ArchiveSection::new(&self.payload_codecs,
+                                    &self.header,
+                                    Box::new(&mut self.reader))?;
+            package::check_archive_files(&self.header,
+                                         &self.signature,
+                                         &mut archive)?;
+        }
+        self.finish(keyring)
+    }
+
+    fn start_archive(&mut self) -> io::Result<()> {
+        if self.archive_started {
+            invalid_input!("The Archive section of a streaming package can \
+                            only be read once");
+        }
+        self.archive_started = true;
+        Ok(())
+    }
+}
+
+// ========================================================================= //
+
+/// An OpenPGP signature from the package signature, waiting to be checked
+/// once the whole package has been read.
+struct PendingSignature {
+    name: &'static str,
+    region: SignatureRegion,
+    verifier: PendingVerifier,
+}
+
+enum PendingVerifier {
+    /// A supported signature, along with a digest of as much of the region
+    /// it covers as has been read so far.
+    Supported(pgp::Signature, Box<DigestWriter>),
+    /// An unsupported signature, and the ID of its key, if known.
+    Unsupported(Option<u64>),
+    /// The error from parsing a malformed signature.
+    Malformed(io::Error),
+}
+
+/// Reads the Archive section (and anything after it), feeding the data into
+/// each digest that covers it.
+struct DigestReader<R: Read> {
+    inner: R,
+    bytes_read: u64,
+    md5: md5::Context,
+    payload_digest: Option<DigestWriter>,
+    signatures: Vec<PendingSignature>,
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        let data = &buf[..bytes_read];
+        self.bytes_read += bytes_read as u64;
+        self.md5.consume(data);
+        if let Some(ref mut writer) = self.payload_digest {
+            writer.write_all(data)?;
+        }
+        for pending in self.signatures.iter_mut() {
+            if pending.region == SignatureRegion::HeaderAndPayload {
+                if let PendingVerifier::Supported(_, ref mut writer) =
+                    pending.verifier
+                {
+                    writer.write_all(data)?;
+                }
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+/// Passes through data from a reader, keeping a copy of everything read.
+struct TeeReader<'a, R: Read> {
+    inner: R,
+    copy: &'a mut Vec<u8>,
+}
+
+impl<'a, R: Read> Read for TeeReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.copy.extend_from_slice(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,191 @@
+use std::io::{self, Read, Write};
+
+// ========================================================================= //
+
+/// The magic number at the start of each entry in a stripped cpio archive.
+pub(crate) const MAGIC_NUMBER: &[u8; 6] = b"07070X";
+
+/// The length of a stripped entry header: the magic number, followed by the
+/// file's index in the package header as eight hex digits.
+const HEADER_LEN: u64 = 14;
+
+// ========================================================================= //
+
+/// Returns the number of padding bytes needed after an entry with `data_len`
+/// bytes of data, so that the next entry starts on a four-byte boundary.
+fn padding_len(data_len: u64) -> usize {
+    ((4 - (HEADER_LEN + data_len) % 4) % 4) as usize
+}
+
+/// Reads the file index of a stripped entry header, after its magic number
+/// has already been read.
+pub(crate) fn read_file_index<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut digits = [0u8; 8];
+    reader.read_exact(&mut digits)?;
+    let index = match ::std::str::from_utf8(&digits) {
+        Ok(string) => usize::from_str_radix(string, 16).ok(),
+        Err(_) => None,
+    };
+    match index {
+        Some(index) => Ok(index),
+        None => {
+            invalid_data!("Invalid file index ({:?}) in stripped cpio entry",
+                          String::from_utf8_lossy(&digits));
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// Reads the data of one entry in a stripped cpio archive.  Stripped entries
+/// carry no metadata of their own (other than the index of the file in the
+/// package header), so the caller must supply the data size.
+pub(crate) struct StrippedReader<R: Read> {
+    inner: R,
+    file_size: u64,
+    bytes_read: u64,
+}
+
+impl<R: Read> StrippedReader<R> {
+    /// Creates a reader for an entry's data, given a reader positioned just
+    /// after the entry header.
+    pub(crate) fn new(inner: R, file_size: u64) -> StrippedReader<R> {
+        StrippedReader {
+            inner,
+            file_size,
+            bytes_read: 0,
+        }
+    }
+
+    /// Skips any unread data and the padding after it, and returns the
+    /// underlying reader, positioned at the start of the next entry.
+    pub(crate) fn finish(mut self) -> io::Result<R> {
+        let remaining = self.file_size - self.bytes_read;
+        io::copy(&mut self.inner.by_ref().take(remaining), &mut io::sink())?;
+        let mut padding = [0u8; 3];
+        let padding_len = padding_len(self.file_size);
+        self.inner.read_exact(&mut padding[..padding_len])?;
+        Ok(self.inner)
+    }
+}
+
+impl<R: Read> Read for StrippedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.file_size - self.bytes_read;
+        let len = (buf.len() as u64).min(remaining) as usize;
+        let bytes_read = self.inner.read(&mut buf[..len])?;
+        self.bytes_read += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+// ========================================================================= //
+
+/// Writes one entry into a stripped cpio archive.
+pub(crate) struct StrippedWriter<W: Write> {
+    inner: W,
+    file_size: u64,
+    bytes_written: u64,
+}
+
+impl<W: Write> StrippedWriter<W> {
+    /// Writes an entry header for the file with the given index in the
+    /// package header, and returns a writer for `file_size` bytes of data.
+    pub(crate) fn new(mut inner: W, file_index: usize, file_size: u64)
+                      -> io::Result<StrippedWriter<W>> {
+        if file_index > u32::MAX as usize {
+            invalid_input!("File index ({}) is too large for a stripped cpio \
+                            entry",
+                           file_index);
+        }
+        inner.write_all(MAGIC_NUMBER)?;
+        inner.write_all(format!("{:08x}", file_index).as_bytes())?;
+        Ok(StrippedWriter {
+               inner,
+               file_size,
+               bytes_written: 0,
+           })
+    }
+
+    /// Writes the padding after the entry data, and returns the underlying
+    /// writer.  Returns an error if not all of the data was written.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if self.bytes_written < self.file_size {
+            invalid_input!("Stripped cpio entry has {} bytes of data, but \
+                            only {} were written",
+                           self.file_size,
+                           self.bytes_written);
+        }
+        let padding = [0u8; 3];
+        self.inner.write_all(&padding[..padding_len(self.file_size)])?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for StrippedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let remaining = self.file_size - self.bytes_written;
+        if buf.len() as u64 > remaining {
+            invalid_input!("Tried to write {} bytes to stripped cpio entry, \
+                            but only {} bytes remain",
+                           buf.len(),
+                           remaining);
+        }
+        let bytes_written = self.inner.write(buf)?;
+        self.bytes_written += bytes_written as u64;
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{MAGIC_NUMBER, StrippedReader, StrippedWriter,
+                read_file_index};
+    use std::io::{Cursor, Read, Write};
+
+    #[test]
+    fn write_entries() {
+        let mut writer = StrippedWriter::new(Vec::new(), 0, 5).unwrap();
+        writer.write_all(b"Hello").unwrap();
+        let output = writer.finish().unwrap();
+        let writer = StrippedWriter::new(output, 0x1f, 0).unwrap();
+        let output = writer.finish().unwrap();
+        assert_eq!(output.as_slice(),
+                   &b"07070X00000000Hello\x0007070X0000001f\0\0"[..]);
+    }
+
+    #[test]
+    fn read_entries() {
+        let mut input =
+            Cursor::new(b"07070X00000002Hello\x0007070X0000001f\0\0".to_vec());
+        let mut magic = [0u8; 6];
+        input.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, MAGIC_NUMBER);
+        assert_eq!(read_file_index(&mut input).unwrap(), 2);
+        let mut reader = StrippedReader::new(&mut input, 5);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data.as_slice(), b"Hello");
+        reader.finish().unwrap();
+        input.read_exact(&mut magic).unwrap();
+        assert_eq!(&magic, MAGIC_NUMBER);
+        assert_eq!(read_file_index(&mut input).unwrap(), 0x1f);
+        StrippedReader::new(&mut input, 0).finish().unwrap();
+        assert_eq!(input.position(), 36);
+    }
+
+    #[test]
+    fn incomplete_entry() {
+        let mut writer = StrippedWriter::new(Vec::new(), 0, 5).unwrap();
+        writer.write_all(b"Hi").unwrap();
+        assert!(writer.finish().is_err());
+        let mut writer = StrippedWriter::new(Vec::new(), 0, 1).unwrap();
+        assert!(writer.write_all(b"Hi").is_err());
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,177 @@
+use internal::dependency::{Dependency, DependencyFlags};
+use internal::scriptlet::Scriptlet;
+
+// ========================================================================= //
+
+/// The default priority for file triggers, as used by rpmbuild.
+const DEFAULT_FILE_TRIGGER_PRIORITY: u32 = 1_000_000;
+
+// ========================================================================= //
+
+/// The event that causes a trigger to run.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TriggerType {
+    /// Runs before the triggering package is installed (`%triggerprein`).
+    /// Not supported for file triggers.
+    PreInstall,
+    /// Runs after the triggering package is installed (`%triggerin`).
+    Install,
+    /// Runs before the triggering package is uninstalled (`%triggerun`).
+    Uninstall,
+    /// Runs after the triggering package is uninstalled
+    /// (`%triggerpostun`).
+    PostUninstall,
+}
+
+impl TriggerType {
+    /// Returns the dependency flag that marks a condition as belonging to
+    /// this type of trigger.
+    pub(crate) fn dependency_flag(&self) -> DependencyFlags {
+        match *self {
+            TriggerType::PreInstall => DependencyFlags::TRIGGERPREIN,
+            TriggerType::Install => DependencyFlags::TRIGGERIN,
+            TriggerType::Uninstall => DependencyFlags::TRIGGERUN,
+            TriggerType::PostUninstall => DependencyFlags::TRIGGERPOSTUN,
+        }
+    }
+
+    /// Returns the trigger type indicated by the given dependency flags, if
+    /// any.
+    pub(crate) fn from_dependency_flags(flags: DependencyFlags)
+                                        -> Option<TriggerType> {
+        if flags.contains(DependencyFlags::TRIGGERPREIN) {
+            Some(TriggerType::PreInstall)
+        } else if flags.contains(DependencyFlags::TRIGGERIN) {
+            Some(TriggerType::Install)
+        } else if flags.contains(DependencyFlags::TRIGGERUN) {
+            Some(TriggerType::Uninstall)
+        } else if flags.contains(DependencyFlags::TRIGGERPOSTUN) {
+            Some(TriggerType::PostUninstall)
+        } else {
+            None
+        }
+    }
+
+    /// Returns all dependency flags that mark trigger types.
+    pub(crate) fn all_dependency_flags() -> DependencyFlags {
+        DependencyFlags::TRIGGERPREIN | DependencyFlags::TRIGGERIN |
+            DependencyFlags::TRIGGERUN | DependencyFlags::TRIGGERPOSTUN
+    }
+}
+
+// ========================================================================= //
+
+/// A scriptlet that runs when other packages matching one of its conditions
+/// are installed or uninstalled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Trigger {
+    trigger_type: TriggerType,
+    scriptlet: Scriptlet,
+    conditions: Vec<Dependency>,
+}
+
+impl Trigger {
+    /// Constructs a new trigger with no conditions.  At least one condition
+    /// must be added before the trigger is added to a package.
+    pub fn new(trigger_type: TriggerType, scriptlet: Scriptlet) -> Trigger {
+        Trigger {
+            trigger_type,
+            scriptlet,
+            conditions: Vec::new(),
+        }
+    }
+
+    /// Returns this trigger with an additional condition (e.g.
+    /// `Dependency::any("httpd")`).
+    pub fn with_condition(mut self, condition: Dependency) -> Trigger {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Returns the event that causes this trigger to run.
+    pub fn trigger_type(&self) -> TriggerType { self.trigger_type }
+
+    /// Returns the scriptlet that is run.
+    pub fn scriptlet(&self) -> &Scriptlet { &self.scriptlet }
+
+    /// Returns the packages that set off this trigger.
+    pub fn conditions(&self) -> &[Dependency] { &self.conditions }
+}
+
+// ========================================================================= //
+
+/// A scriptlet that runs when files under one of its path prefixes are
+/// installed or uninstalled by any package.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileTrigger {
+    trigger_type: TriggerType,
+    scriptlet: Scriptlet,
+    prefixes: Vec<String>,
+    priority: u32,
+}
+
+impl FileTrigger {
+    /// Constructs a new file trigger with no path prefixes and the default
+    /// priority.  At least one prefix must be added before the trigger is
+    /// added to a package.
+    pub fn new(trigger_type: TriggerType, scriptlet: Scriptlet)
+               -> FileTrigger {
+        FileTrigger {
+            trigger_type,
+            scriptlet,
+            prefixes: Vec::new(),
+            priority: DEFAULT_FILE_TRIGGER_PRIORITY,
+        }
+    }
+
+    /// Returns this trigger with an additional path prefix (e.g.
+    /// `"/usr/lib/myapp/plugins/"`).
+    pub fn with_prefix<S: Into<String>>(mut self, prefix: S) -> FileTrigger {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Returns this trigger with the given priority.  Triggers with higher
+    /// priorities run first.
+    pub fn with_priority(mut self, priority: u32) -> FileTrigger {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns the event that causes this trigger to run.
+    pub fn trigger_type(&self) -> TriggerType { self.trigger_type }
+
+    /// Returns the scriptlet that is run.
+    pub fn scriptlet(&self) -> &Scriptlet { &self.scriptlet }
+
+    /// Returns the path prefixes that set off this trigger.
+    pub fn prefixes(&self) -> &[String] { &self.prefixes }
+
+    /// Returns the priority of this trigger.
+    pub fn priority(&self) -> u32 { self.priority }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::TriggerType;
+
+    #[test]
+    fn trigger_type_flags_round_trip() {
+        let types = &[
+            TriggerType::PreInstall,
+            TriggerType::Install,
+            TriggerType::Uninstall,
+            TriggerType::PostUninstall,
+        ];
+        for &trigger_type in types {
+            let flags = trigger_type.dependency_flag();
+            assert!(TriggerType::all_dependency_flags().contains(flags));
+            assert_eq!(TriggerType::from_dependency_flags(flags),
+                       Some(trigger_type));
+        }
+    }
+}
+
+// ========================================================================= //
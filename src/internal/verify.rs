@@ -0,0 +1,429 @@
+#[cfg(unix)]
+use internal::convert;
+#[cfg(unix)]
+use internal::digest::DigestAlgorithm;
+#[cfg(unix)]
+use internal::header::FileInfo;
+use internal::header::FileFlags;
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(unix)]
+use std::fs::{self, File};
+#[cfg(unix)]
+use std::io::{self, BufRead, BufReader};
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+#[cfg(unix)]
+use std::path::Path;
+
+// ========================================================================= //
+
+/// File type bits mask for a Unix mode.
+#[cfg(unix)]
+const S_IFMT: u32 = 0o170000;
+/// File type bits for a character device.
+#[cfg(unix)]
+const S_IFCHR: u32 = 0o020000;
+/// File type bits for a block device.
+#[cfg(unix)]
+const S_IFBLK: u32 = 0o060000;
+
+/// The characters used for each verification test in `rpm -V` output, in
+/// order.
+const RESULT_CHARS: &[(VerifyFlags, char)] = &[
+    (VerifyFlags::FILESIZE, 'S'),
+    (VerifyFlags::MODE, 'M'),
+    (VerifyFlags::FILEDIGEST, '5'),
+    (VerifyFlags::RDEV, 'D'),
+    (VerifyFlags::LINKTO, 'L'),
+    (VerifyFlags::USER, 'U'),
+    (VerifyFlags::GROUP, 'G'),
+    (VerifyFlags::MTIME, 'T'),
+    (VerifyFlags::CAPS, 'P'),
+];
+
+/// The characters used for file attributes in `rpm -V` output, in order of
+/// precedence (only the first matching attribute is shown).
+const ATTRIBUTE_CHARS: &[(FileFlags, char)] = &[
+    (FileFlags::DOC, 'd'),
+    (FileFlags::CONFIG, 'c'),
+    (FileFlags::SPECFILE, 's'),
+    (FileFlags::MISSINGOK, 'm'),
+    (FileFlags::NOREPLACE, 'n'),
+    (FileFlags::GHOST, 'g'),
+    (FileFlags::LICENSE, 'l'),
+    (FileFlags::README, 'r'),
+    (FileFlags::ARTIFACT, 'a'),
+];
+
+// ========================================================================= //
+
+bitflags! {
+    /// Flags specifying which attributes of an installed file are checked
+    /// when it is verified (the `RPMVERIFY_*` values).
+    pub struct VerifyFlags: u32 {
+        /// Check the file digest.
+        const FILEDIGEST = 1 << 0;
+        /// Check the file size.
+        const FILESIZE = 1 << 1;
+        /// Check the symlink target.
+        const LINKTO = 1 << 2;
+        /// Check the owner user.
+        const USER = 1 << 3;
+        /// Check the owner group.
+        const GROUP = 1 << 4;
+        /// Check the last-modified time.
+        const MTIME = 1 << 5;
+        /// Check the mode (file type and permissions).
+        const MODE = 1 << 6;
+        /// Check the device numbers.
+        const RDEV = 1 << 7;
+        /// Check the file capabilities.
+        const CAPS = 1 << 8;
+    }
+}
+
+// ========================================================================= //
+
+/// The result of verifying one installed file against the package metadata,
+/// as with `rpm -V`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileVerification {
+    path: String,
+    file_flags: FileFlags,
+    missing: bool,
+    failed: VerifyFlags,
+    unknown: VerifyFlags,
+}
+
+impl FileVerification {
+    /// Compares the installed copy of a file under `root` with its package
+    /// metadata.
+    #[cfg(unix)]
+    pub(crate) fn verify(file_info: &FileInfo,
+                         algorithm: Option<DigestAlgorithm>,
+                         owners: &OwnerNames, root: &Path)
+                         -> FileVerification {
+        let mut verification = FileVerification {
+            path: file_info.name().to_string(),
+            file_flags: file_info.flags(),
+            missing: false,
+            failed: VerifyFlags::empty(),
+            unknown: VerifyFlags::empty(),
+        };
+        let path = root.join(file_info.name().trim_start_matches('/'));
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                verification.missing = true;
+                return verification;
+            }
+        };
+
+        // Like rpm, only check the attributes that make sense for the type
+        // of file on disk.
+        let content =
+            VerifyFlags::FILEDIGEST | VerifyFlags::FILESIZE |
+                VerifyFlags::MTIME;
+        let file_type = metadata.file_type();
+        let mut flags = file_info.verify_flags();
+        if file_type.is_dir() || file_type.is_fifo() ||
+            file_type.is_char_device() ||
+            file_type.is_block_device()
+        {
+            flags -= content | VerifyFlags::LINKTO | VerifyFlags::CAPS;
+        } else if file_type.is_symlink() {
+            flags -= content | VerifyFlags::MODE | VerifyFlags::CAPS;
+        } else {
+            flags -= VerifyFlags::LINKTO;
+        }
+        // The contents of ghost files are not managed by the package.
+        if file_info.is_ghost() {
+            flags -= content | VerifyFlags::LINKTO;
+        }
+        // We don't record file capabilities, so there's nothing to check.
+        flags -= VerifyFlags::CAPS;
+
+        let mut failed = VerifyFlags::empty();
+        let mut unknown = VerifyFlags::empty();
+        if flags.contains(VerifyFlags::FILESIZE) &&
+            metadata.len() != file_info.size()
+        {
+            failed |= VerifyFlags::FILESIZE;
+        }
+        if flags.contains(VerifyFlags::MODE) {
+            let mut expected_mode = u32::from(file_info.mode());
+            let mut actual_mode = metadata.mode();
+            // The type of a ghost file is meaningless, but its permissions
+            // are not.
+            if file_info.is_ghost() {
+                expected_mode &= !S_IFMT;
+                actual_mode &= !S_IFMT;
+            }
+            if expected_mode != actual_mode {
+                failed |= VerifyFlags::MODE;
+            }
+        }
+        if flags.contains(VerifyFlags::FILEDIGEST) &&
+            !file_info.digest().is_empty()
+        {
+            match algorithm.map(|algorithm| digest_file(&path, algorithm)) {
+                Some(Ok(digest)) => {
+                    if digest != file_info.digest().to_lowercase() {
+                        failed |= VerifyFlags::FILEDIGEST;
+                    }
+                }
+                _ => unknown |= VerifyFlags::FILEDIGEST,
+            }
+        }
+        if flags.contains(VerifyFlags::RDEV) {
+            let expected_type = u32::from(file_info.mode()) & S_IFMT;
+            let actual_type = metadata.mode() & S_IFMT;
+            if (expected_type == S_IFCHR) != (actual_type == S_IFCHR) ||
+                (expected_type == S_IFBLK) != (actual_type == S_IFBLK)
+            {
+                failed |= VerifyFlags::RDEV;
+            } else if let Some((major, minor)) = file_info.device_numbers() {
+                let expected_rdev = (u64::from(major) << 8) |
+                    u64::from(minor);
+                if metadata.rdev() & 0xffff != expected_rdev {
+                    failed |= VerifyFlags::RDEV;
+                }
+            }
+        }
+        if flags.contains(VerifyFlags::LINKTO) {
+            match fs::read_link(&path) {
+                Ok(target) => {
+                    let expected_target = file_info.symlink_target();
+                    if Some(target.as_path()) != expected_target.map(Path::new)
+                    {
+                        failed |= VerifyFlags::LINKTO;
+                    }
+                }
+                Err(_) => unknown |= VerifyFlags::LINKTO,
+            }
+        }
+        if flags.contains(VerifyFlags::USER) &&
+            owners.user_name(metadata.uid()) != Some(file_info.user_name())
+        {
+            failed |= VerifyFlags::USER;
+        }
+        if flags.contains(VerifyFlags::GROUP) &&
+            owners.group_name(metadata.gid()) !=
+                Some(file_info.group_name())
+        {
+            failed |= VerifyFlags::GROUP;
+        }
+        if flags.contains(VerifyFlags::MTIME) {
+            let expected_mtime =
+                convert::system_time_to_u32(file_info.modified_time());
+            if metadata.mtime() != i64::from(expected_mtime) {
+                failed |= VerifyFlags::MTIME;
+            }
+        }
+        verification.failed = failed;
+        verification.unknown = unknown;
+        verification
+    }
+
+    /// Returns the install path of the file.
+    pub fn path(&self) -> &str { &self.path }
+
+    /// Returns the flags for the file in the package metadata.
+    pub fn file_flags(&self) -> FileFlags { self.file_flags }
+
+    /// Returns true if the file does not exist on disk.
+    pub fn is_missing(&self) -> bool { self.missing }
+
+    /// Returns the attributes that don't match the package metadata.
+    pub fn failed(&self) -> VerifyFlags { self.failed }
+
+    /// Returns the attributes that could not be checked (e.g. because the
+    /// file could not be read).
+    pub fn unknown(&self) -> VerifyFlags { self.unknown }
+
+    /// Returns true if the file passed verification.  As with `rpm -V`, a
+    /// missing file is only considered a problem if it isn't a `%ghost` or
+    /// `%config(missingok)` file.
+    pub fn is_ok(&self) -> bool {
+        if self.missing {
+            self.file_flags
+                .intersects(FileFlags::GHOST | FileFlags::MISSINGOK)
+        } else {
+            self.failed.is_empty() && self.unknown.is_empty()
+        }
+    }
+}
+
+/// Formats the result as a line of `rpm -V` output (e.g.
+/// `S.5....T.  c /etc/foo.conf`).
+impl fmt::Display for FileVerification {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let attribute = ATTRIBUTE_CHARS
+            .iter()
+            .find(|&&(flag, _)| self.file_flags.contains(flag))
+            .map(|&(_, ch)| ch)
+            .unwrap_or(' ');
+        if self.missing {
+            return write!(formatter, "missing   {} {}", attribute, self.path);
+        }
+        for &(flag, ch) in RESULT_CHARS.iter() {
+            let ch = if self.unknown.contains(flag) {
+                '?'
+            } else if self.failed.contains(flag) {
+                ch
+            } else {
+                '.'
+            };
+            write!(formatter, "{}", ch)?;
+        }
+        write!(formatter, "  {} {}", attribute, self.path)
+    }
+}
+
+#[cfg(unix)]
+fn digest_file(path: &Path, algorithm: DigestAlgorithm)
+               -> io::Result<String> {
+    let mut context = algorithm.writer();
+    io::copy(&mut File::open(path)?, &mut context)?;
+    Ok(context.finish_hex())
+}
+
+// ========================================================================= //
+
+/// User and group names for numeric IDs, as read from the `/etc/passwd` and
+/// `/etc/group` files under a filesystem root.
+#[cfg(unix)]
+pub(crate) struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+#[cfg(unix)]
+impl OwnerNames {
+    pub(crate) fn read(root: &Path) -> io::Result<OwnerNames> {
+        Ok(OwnerNames {
+               users: read_id_names(&root.join("etc/passwd"))?,
+               groups: read_id_names(&root.join("etc/group"))?,
+           })
+    }
+
+    fn user_name(&self, uid: u32) -> Option<&str> {
+        lookup_id_name(&self.users, uid)
+    }
+
+    fn group_name(&self, gid: u32) -> Option<&str> {
+        lookup_id_name(&self.groups, gid)
+    }
+
+    pub(crate) fn user_id(&self, name: &str) -> Option<u32> {
+        lookup_name_id(&self.users, name)
+    }
+
+    pub(crate) fn group_id(&self, name: &str) -> Option<u32> {
+        lookup_name_id(&self.groups, name)
+    }
+}
+
+#[cfg(unix)]
+fn lookup_id_name(names: &HashMap<u32, String>, id: u32) -> Option<&str> {
+    match names.get(&id) {
+        Some(name) => Some(name),
+        None if id == 0 => Some("root"),
+        None => None,
+    }
+}
+
+#[cfg(unix)]
+fn lookup_name_id(names: &HashMap<u32, String>, name: &str) -> Option<u32> {
+    match names.iter().find(|&(_, id_name)| id_name == name) {
+        Some((&id, _)) => Some(id),
+        None if name == "root" => Some(0),
+        None => None,
+    }
+}
+
+/// Reads a name-to-ID file in the format of `/etc/passwd` or `/etc/group`.
+/// If the file doesn't exist, returns an empty map.
+#[cfg(unix)]
+fn read_id_names(path: &Path) -> io::Result<HashMap<u32, String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+            return Ok(HashMap::new());
+        }
+        Err(error) => return Err(error),
+    };
+    let mut names = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let mut fields = line.split(':');
+        let name = fields.next().unwrap_or("");
+        if let Some(id) = fields.nth(1).and_then(|id| id.parse().ok()) {
+            names.entry(id).or_insert_with(|| name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{FileVerification, VerifyFlags};
+    use internal::header::FileFlags;
+
+    fn verification(file_flags: FileFlags, missing: bool,
+                    failed: VerifyFlags, unknown: VerifyFlags)
+                    -> FileVerification {
+        FileVerification {
+            path: "/etc/foo.conf".to_string(),
+            file_flags,
+            missing,
+            failed,
+            unknown,
+        }
+    }
+
+    #[test]
+    fn format_verification() {
+        let result = verification(FileFlags::CONFIG,
+                                  false,
+                                  VerifyFlags::FILESIZE |
+                                      VerifyFlags::FILEDIGEST |
+                                      VerifyFlags::MTIME,
+                                  VerifyFlags::empty());
+        assert!(!result.is_ok());
+        assert_eq!(result.to_string(), "S.5....T.  c /etc/foo.conf");
+        let result = verification(FileFlags::empty(),
+                                  false,
+                                  VerifyFlags::USER,
+                                  VerifyFlags::FILEDIGEST);
+        assert_eq!(result.to_string(), "..?..U...    /etc/foo.conf");
+        let result = verification(FileFlags::empty(),
+                                  false,
+                                  VerifyFlags::empty(),
+                                  VerifyFlags::empty());
+        assert!(result.is_ok());
+        assert_eq!(result.to_string(), ".........    /etc/foo.conf");
+    }
+
+    #[test]
+    fn format_missing() {
+        let result = verification(FileFlags::CONFIG | FileFlags::NOREPLACE,
+                                  true,
+                                  VerifyFlags::empty(),
+                                  VerifyFlags::empty());
+        assert!(!result.is_ok());
+        assert_eq!(result.to_string(), "missing   c /etc/foo.conf");
+        let result = verification(FileFlags::GHOST,
+                                  true,
+                                  VerifyFlags::empty(),
+                                  VerifyFlags::empty());
+        assert!(result.is_ok());
+        assert_eq!(result.to_string(), "missing   g /etc/foo.conf");
+    }
+}
+
+// ========================================================================= //
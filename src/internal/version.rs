@@ -0,0 +1,486 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+
+// ========================================================================= //
+
+/// Compares two version (or release) strings using the same rules as rpm's
+/// `rpmvercmp` function.
+pub(crate) fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let mut one = a.as_bytes();
+    let mut two = b.as_bytes();
+    loop {
+        one = skip_separators(one);
+        two = skip_separators(two);
+        if one.is_empty() && two.is_empty() {
+            break;
+        }
+        // A tilde sorts before everything else, even the end of the string.
+        if one.first() == Some(&b'~') || two.first() == Some(&b'~') {
+            if one.first() != Some(&b'~') {
+                return Ordering::Greater;
+            }
+            if two.first() != Some(&b'~') {
+                return Ordering::Less;
+            }
+            one = &one[1..];
+            two = &two[1..];
+            continue;
+        }
+        // A caret sorts after the end of the string, but before everything
+        // else.
+        if one.first() == Some(&b'^') || two.first() == Some(&b'^') {
+            if one.is_empty() {
+                return Ordering::Less;
+            }
+            if two.is_empty() {
+                return Ordering::Greater;
+            }
+            if one.first() != Some(&b'^') {
+                return Ordering::Greater;
+            }
+            if two.first() != Some(&b'^') {
+                return Ordering::Less;
+            }
+            one = &one[1..];
+            two = &two[1..];
+            continue;
+        }
+        if one.is_empty() || two.is_empty() {
+            break;
+        }
+        let is_num = one[0].is_ascii_digit();
+        let (segment1, rest1) = split_segment(one, is_num);
+        let (segment2, rest2) = split_segment(two, is_num);
+        if segment2.is_empty() {
+            // Numeric segments are always newer than alphabetic segments.
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+        let ordering = if is_num {
+            let segment1 = strip_leading_zeros(segment1);
+            let segment2 = strip_leading_zeros(segment2);
+            segment1
+                .len()
+                .cmp(&segment2.len())
+                .then_with(|| segment1.cmp(segment2))
+        } else {
+            segment1.cmp(segment2)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        one = rest1;
+        two = rest2;
+    }
+    // Whichever version still has characters left over wins.
+    match (one.is_empty(), two.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (false, _) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+    }
+}
+
+fn skip_separators(string: &[u8]) -> &[u8] {
+    let start = string
+        .iter()
+        .position(|&ch| ch.is_ascii_alphanumeric() || ch == b'~' || ch == b'^')
+        .unwrap_or(string.len());
+    &string[start..]
+}
+
+fn split_segment(string: &[u8], is_num: bool) -> (&[u8], &[u8]) {
+    let end = string
+        .iter()
+        .position(|&ch| if is_num {
+                      !ch.is_ascii_digit()
+                  } else {
+                      !ch.is_ascii_alphabetic()
+                  })
+        .unwrap_or(string.len());
+    string.split_at(end)
+}
+
+fn strip_leading_zeros(string: &[u8]) -> &[u8] {
+    let start = string
+        .iter()
+        .position(|&ch| ch != b'0')
+        .unwrap_or(string.len());
+    &string[start..]
+}
+
+// ========================================================================= //
+
+/// Splits an EVR string of the form `[epoch:]version[-release]` into its
+/// parts.  A missing epoch is returned as `None`, and a missing release as an
+/// empty string.
+pub(crate) fn split_evr(evr: &str) -> (Option<&str>, &str, &str) {
+    let (epoch, rest) = match evr.find(':') {
+        Some(index) if evr[..index].bytes().all(|ch| ch.is_ascii_digit()) => {
+            (Some(&evr[..index]), &evr[(index + 1)..])
+        }
+        _ => (None, evr),
+    };
+    let (version, release) = match rest.rfind('-') {
+        Some(index) => (&rest[..index], &rest[(index + 1)..]),
+        None => (rest, ""),
+    };
+    (epoch, version, release)
+}
+
+/// Compares two EVR strings the way rpm does when matching dependencies: a
+/// missing epoch is treated as zero, and the releases are only compared if
+/// both strings have one.
+pub(crate) fn compare_dependency_evrs(a: &str, b: &str) -> Ordering {
+    let (epoch1, version1, release1) = split_evr(a);
+    let (epoch2, version2, release2) = split_evr(b);
+    let epoch1 = epoch1.unwrap_or("0");
+    let epoch2 = epoch2.unwrap_or("0");
+    let ordering = rpmvercmp(epoch1, epoch2);
+    if ordering != Ordering::Equal {
+        return ordering;
+    }
+    let ordering = rpmvercmp(version1, version2);
+    if ordering != Ordering::Equal || release1.is_empty() ||
+        release2.is_empty()
+    {
+        return ordering;
+    }
+    rpmvercmp(release1, release2)
+}
+
+// ========================================================================= //
+
+/// A package version, consisting of an optional epoch, a version string, and
+/// a release string.  Versions are ordered using the same rules as rpm.
+#[derive(Clone, Debug)]
+pub struct Evr {
+    epoch: Option<u32>,
+    version: String,
+    release: String,
+}
+
+impl Evr {
+    /// Constructs a new `Evr` from its parts.
+    pub fn new<S1, S2>(epoch: Option<u32>, version: S1, release: S2) -> Evr
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Evr {
+            epoch,
+            version: version.into(),
+            release: release.into(),
+        }
+    }
+
+    /// Parses a string of the form `[epoch:]version[-release]`.
+    pub fn parse(string: &str) -> io::Result<Evr> {
+        let (epoch, version, release) = split_evr(string);
+        let epoch = match epoch {
+            Some(epoch) => {
+                match epoch.parse::<u32>() {
+                    Ok(epoch) => Some(epoch),
+                    Err(_) => invalid_data!("Invalid epoch in {:?}", string),
+                }
+            }
+            None => None,
+        };
+        if version.is_empty() {
+            invalid_data!("Missing version in {:?}", string);
+        }
+        Ok(Evr::new(epoch, version, release))
+    }
+
+    /// Returns the epoch, if any.  A missing epoch compares the same as an
+    /// epoch of zero.
+    pub fn epoch(&self) -> Option<u32> { self.epoch }
+
+    /// Returns the version string (e.g. `"1.2.3"`).
+    pub fn version(&self) -> &str { &self.version }
+
+    /// Returns the release string (e.g. `"1.fc30"`), which may be empty.
+    pub fn release(&self) -> &str { &self.release }
+}
+
+impl fmt::Display for Evr {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(epoch) = self.epoch {
+            write!(formatter, "{}:", epoch)?;
+        }
+        formatter.write_str(&self.version)?;
+        if !self.release.is_empty() {
+            write!(formatter, "-{}", self.release)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Evr {
+    fn cmp(&self, other: &Evr) -> Ordering {
+        self.epoch
+            .unwrap_or(0)
+            .cmp(&other.epoch.unwrap_or(0))
+            .then_with(|| rpmvercmp(&self.version, &other.version))
+            .then_with(|| rpmvercmp(&self.release, &other.release))
+    }
+}
+
+impl PartialOrd for Evr {
+    fn partial_cmp(&self, other: &Evr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Evr {
+    fn eq(&self, other: &Evr) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Evr {}
+
+// ========================================================================= //
+
+/// A fully-qualified package identifier, consisting of a name, an `Evr`, and
+/// an architecture (e.g. `"bash-0:5.0.7-1.fc30.x86_64"`).
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Nevra {
+    name: String,
+    evr: Evr,
+    arch: String,
+}
+
+impl Nevra {
+    /// Constructs a new `Nevra` from its parts.
+    pub fn new<S1, S2>(name: S1, evr: Evr, arch: S2) -> Nevra
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Nevra {
+            name: name.into(),
+            evr,
+            arch: arch.into(),
+        }
+    }
+
+    /// Parses a string of the form `name-[epoch:]version-release.arch`.
+    pub fn parse(string: &str) -> io::Result<Nevra> {
+        let (rest, arch) = match string.rfind('.') {
+            Some(index) => (&string[..index], &string[(index + 1)..]),
+            None => invalid_data!("Missing architecture in {:?}", string),
+        };
+        let (rest, release) = match rest.rfind('-') {
+            Some(index) => (&rest[..index], &rest[(index + 1)..]),
+            None => invalid_data!("Missing release in {:?}", string),
+        };
+        let (name, version) = match rest.rfind('-') {
+            Some(index) => (&rest[..index], &rest[(index + 1)..]),
+            None => invalid_data!("Missing version in {:?}", string),
+        };
+        if name.is_empty() || arch.is_empty() || release.is_empty() {
+            invalid_data!("Invalid NEVRA string {:?}", string);
+        }
+        let evr = Evr::parse(&format!("{}-{}", version, release))?;
+        Ok(Nevra::new(name, evr, arch))
+    }
+
+    /// Returns the package name.
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Returns the package's epoch, version, and release.
+    pub fn evr(&self) -> &Evr { &self.evr }
+
+    /// Returns the package architecture (e.g. `"x86_64"` or `"noarch"`).
+    pub fn arch(&self) -> &str { &self.arch }
+}
+
+impl fmt::Display for Nevra {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}-{}.{}", self.name, self.evr, self.arch)
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{Evr, Nevra, compare_dependency_evrs, rpmvercmp, split_evr};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn rpmvercmp_test_vectors() {
+        // These test cases are taken from rpm's own test suite.
+        let cases: &[(&str, &str, Ordering)] = &[
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "2.0", Ordering::Less),
+            ("2.0", "1.0", Ordering::Greater),
+            ("2.0.1", "2.0.1", Ordering::Equal),
+            ("2.0", "2.0.1", Ordering::Less),
+            ("2.0.1", "2.0", Ordering::Greater),
+            ("2.0.1a", "2.0.1a", Ordering::Equal),
+            ("2.0.1a", "2.0.1", Ordering::Greater),
+            ("2.0.1", "2.0.1a", Ordering::Less),
+            ("5.5p1", "5.5p1", Ordering::Equal),
+            ("5.5p1", "5.5p2", Ordering::Less),
+            ("5.5p2", "5.5p1", Ordering::Greater),
+            ("5.5p10", "5.5p10", Ordering::Equal),
+            ("5.5p1", "5.5p10", Ordering::Less),
+            ("5.5p10", "5.5p1", Ordering::Greater),
+            ("10xyz", "10.1xyz", Ordering::Less),
+            ("10.1xyz", "10xyz", Ordering::Greater),
+            ("xyz10", "xyz10", Ordering::Equal),
+            ("xyz10", "xyz10.1", Ordering::Less),
+            ("xyz10.1", "xyz10", Ordering::Greater),
+            ("xyz.4", "xyz.4", Ordering::Equal),
+            ("xyz.4", "8", Ordering::Less),
+            ("8", "xyz.4", Ordering::Greater),
+            ("xyz.4", "2", Ordering::Less),
+            ("2", "xyz.4", Ordering::Greater),
+            ("5.5p2", "5.6p1", Ordering::Less),
+            ("5.6p1", "5.5p2", Ordering::Greater),
+            ("5.6p1", "6.5p1", Ordering::Less),
+            ("6.5p1", "5.6p1", Ordering::Greater),
+            ("6.0.rc1", "6.0", Ordering::Greater),
+            ("6.0", "6.0.rc1", Ordering::Less),
+            ("10b2", "10a1", Ordering::Greater),
+            ("10a2", "10b2", Ordering::Less),
+            ("1.0aa", "1.0aa", Ordering::Equal),
+            ("1.0a", "1.0aa", Ordering::Less),
+            ("1.0aa", "1.0a", Ordering::Greater),
+            ("10.0001", "10.0001", Ordering::Equal),
+            ("10.0001", "10.1", Ordering::Equal),
+            ("10.1", "10.0001", Ordering::Equal),
+            ("10.0001", "10.0039", Ordering::Less),
+            ("10.0039", "10.0001", Ordering::Greater),
+            ("4.999.9", "5.0", Ordering::Less),
+            ("5.0", "4.999.9", Ordering::Greater),
+            ("20101121", "20101121", Ordering::Equal),
+            ("20101121", "20101122", Ordering::Less),
+            ("20101122", "20101121", Ordering::Greater),
+            ("2_0", "2_0", Ordering::Equal),
+            ("2.0", "2_0", Ordering::Equal),
+            ("2_0", "2.0", Ordering::Equal),
+            ("a", "a", Ordering::Equal),
+            ("a+", "a+", Ordering::Equal),
+            ("a+", "a_", Ordering::Equal),
+            ("a_", "a+", Ordering::Equal),
+            ("+a", "+a", Ordering::Equal),
+            ("+a", "_a", Ordering::Equal),
+            ("_a", "+a", Ordering::Equal),
+            ("+_", "+_", Ordering::Equal),
+            ("_+", "+_", Ordering::Equal),
+            ("_+", "_+", Ordering::Equal),
+            ("+", "_", Ordering::Equal),
+            ("_", "+", Ordering::Equal),
+            ("1.0~rc1", "1.0~rc1", Ordering::Equal),
+            ("1.0~rc1", "1.0", Ordering::Less),
+            ("1.0", "1.0~rc1", Ordering::Greater),
+            ("1.0~rc1", "1.0~rc2", Ordering::Less),
+            ("1.0~rc2", "1.0~rc1", Ordering::Greater),
+            ("1.0~rc1~git123", "1.0~rc1~git123", Ordering::Equal),
+            ("1.0~rc1~git123", "1.0~rc1", Ordering::Less),
+            ("1.0~rc1", "1.0~rc1~git123", Ordering::Greater),
+            ("1.0^", "1.0^", Ordering::Equal),
+            ("1.0^", "1.0", Ordering::Greater),
+            ("1.0", "1.0^", Ordering::Less),
+            ("1.0^git1", "1.0^git1", Ordering::Equal),
+            ("1.0^git1", "1.0", Ordering::Greater),
+            ("1.0", "1.0^git1", Ordering::Less),
+            ("1.0^git1", "1.0^git2", Ordering::Less),
+            ("1.0^git2", "1.0^git1", Ordering::Greater),
+            ("1.0^git1", "1.01", Ordering::Less),
+            ("1.01", "1.0^git1", Ordering::Greater),
+            ("1.0^20160101", "1.0^20160101", Ordering::Equal),
+            ("1.0^20160101", "1.0.1", Ordering::Less),
+            ("1.0.1", "1.0^20160101", Ordering::Greater),
+            ("1.0^20160101^git1", "1.0^20160101^git1", Ordering::Equal),
+            ("1.0^20160102", "1.0^20160101^git1", Ordering::Greater),
+            ("1.0^20160101^git1", "1.0^20160102", Ordering::Less),
+            ("1.0~rc1^git1", "1.0~rc1^git1", Ordering::Equal),
+            ("1.0~rc1^git1", "1.0~rc1", Ordering::Greater),
+            ("1.0~rc1", "1.0~rc1^git1", Ordering::Less),
+            ("1.0^git1~pre", "1.0^git1~pre", Ordering::Equal),
+            ("1.0^git1", "1.0^git1~pre", Ordering::Greater),
+            ("1.0^git1~pre", "1.0^git1", Ordering::Less),
+        ];
+        for &(a, b, expected) in cases.iter() {
+            assert_eq!(rpmvercmp(a, b), expected, "{:?} vs. {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn split_evr_strings() {
+        assert_eq!(split_evr("1.2"), (None, "1.2", ""));
+        assert_eq!(split_evr("1.2-3"), (None, "1.2", "3"));
+        assert_eq!(split_evr("4:1.2-3.fc30"), (Some("4"), "1.2", "3.fc30"));
+        assert_eq!(split_evr("1:2"), (Some("1"), "2", ""));
+    }
+
+    #[test]
+    fn compare_evrs_for_dependencies() {
+        assert_eq!(compare_dependency_evrs("1.2", "1.2-3"), Ordering::Equal);
+        assert_eq!(compare_dependency_evrs("1.2-4", "1.2-3"),
+                   Ordering::Greater);
+        assert_eq!(compare_dependency_evrs("0:1.2", "1.2"), Ordering::Equal);
+        assert_eq!(compare_dependency_evrs("1:1.0", "2.0"), Ordering::Greater);
+    }
+
+    #[test]
+    fn evr_ordering() {
+        let parse = |string: &str| Evr::parse(string).unwrap();
+        assert!(parse("1.0-1") < parse("1.0-2"));
+        assert!(parse("1.0~rc1-1") < parse("1.0-1"));
+        assert!(parse("1.0^git1-1") > parse("1.0-1"));
+        assert!(parse("1:1.0-1") > parse("2.0-1"));
+        assert_eq!(parse("0:1.0-1"), parse("1.0-1"));
+        assert_eq!(parse("1.01-1"), parse("1.1-1"));
+        let mut evrs = [parse("2.0-1"), parse("1.0-1"), parse("1:0.1-1")];
+        evrs.sort();
+        let strings: Vec<String> =
+            evrs.iter().map(|evr| evr.to_string()).collect();
+        assert_eq!(strings, vec!["1.0-1", "2.0-1", "1:0.1-1"]);
+    }
+
+    #[test]
+    fn evr_parse_and_display() {
+        let evr = Evr::parse("3:1.2.3-4.fc30").unwrap();
+        assert_eq!(evr.epoch(), Some(3));
+        assert_eq!(evr.version(), "1.2.3");
+        assert_eq!(evr.release(), "4.fc30");
+        assert_eq!(evr.to_string(), "3:1.2.3-4.fc30");
+        assert_eq!(Evr::parse("1.0").unwrap().to_string(), "1.0");
+        assert!(Evr::parse("").is_err());
+        assert!(Evr::parse("99999999999:1.0").is_err());
+    }
+
+    #[test]
+    fn nevra_parse_and_display() {
+        let nevra = Nevra::parse("python3-foo-bar-1:2.3~rc1-4.fc30.noarch")
+            .unwrap();
+        assert_eq!(nevra.name(), "python3-foo-bar");
+        assert_eq!(nevra.evr().epoch(), Some(1));
+        assert_eq!(nevra.evr().version(), "2.3~rc1");
+        assert_eq!(nevra.evr().release(), "4.fc30");
+        assert_eq!(nevra.arch(), "noarch");
+        assert_eq!(nevra.to_string(),
+                   "python3-foo-bar-1:2.3~rc1-4.fc30.noarch");
+
+        let nevra = Nevra::parse("bash-5.0.7-1.fc30.x86_64").unwrap();
+        assert_eq!(nevra.name(), "bash");
+        assert_eq!(nevra.evr().epoch(), None);
+        assert_eq!(nevra.evr().version(), "5.0.7");
+        assert_eq!(nevra.arch(), "x86_64");
+
+        assert!(Nevra::parse("bash").is_err());
+        assert!(Nevra::parse("bash.x86_64").is_err());
+        assert!(Nevra::parse("bash-1.0.x86_64").is_err());
+    }
+}
+
+// ========================================================================= //
@@ -3,21 +3,45 @@
 
 #![warn(missing_docs)]
 
+#[macro_use]
+extern crate bitflags;
 extern crate byteorder;
 extern crate bzip2;
 extern crate cpio;
+extern crate dsa;
+extern crate ed25519_dalek;
 extern crate flate2;
+extern crate lz4_flex;
 extern crate md5;
+extern crate rsa;
 extern crate sha1;
+extern crate sha2;
 extern crate xz2;
+extern crate zstd;
 
 mod internal;
 
 pub use internal::builder::{ArchiveBuilder, FileWriter, PackageBuilder};
-pub use internal::header::{FileInfo, FileInfoIter, HeaderSection};
+pub use internal::changelog::ChangeLogEntry;
+pub use internal::codec::{PayloadCodec, PayloadCodecRegistry, PayloadEncoder,
+                          PayloadFlags};
+pub use internal::dependency::{Dependency, DependencyFlags,
+                               RichDependency};
+pub use internal::digest::DigestAlgorithm;
+#[cfg(unix)]
+pub use internal::extract::ExtractOptions;
+pub use internal::header::{ChangeLogIter, DependencyIter, FileFlags,
+                           FileInfo, FileInfoIter, HeaderSection};
 pub use internal::index::{IndexTable, IndexValue};
 pub use internal::lead::{LeadSection, PackageType};
 pub use internal::package::{ArchiveSection, FileReader, Package};
-pub use internal::signature::SignatureSection;
+pub use internal::pgp::{Keyring, PublicKeyAlgorithm, SigningKey};
+pub use internal::scriptlet::{Scriptlet, ScriptletFlags, ScriptletType};
+pub use internal::signature::{SignatureRegion, SignatureSection,
+                              SignatureVerification};
+pub use internal::streaming::StreamingPackage;
+pub use internal::trigger::{FileTrigger, Trigger, TriggerType};
+pub use internal::verify::{FileVerification, VerifyFlags};
+pub use internal::version::{Evr, Nevra};
 
 // ========================================================================= //
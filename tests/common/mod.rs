@@ -0,0 +1,81 @@
+// Each test crate uses only some of these helpers.
+#![allow(dead_code)]
+
+use rpmpkg::{FileInfo, PackageBuilder, PackageType, SigningKey};
+use std::io::{Cursor, Write};
+
+// ========================================================================= //
+
+pub const RSA_SECRET_KEY: &[u8] = include_bytes!("../data/rsa-secret.asc");
+pub const RSA_KEY_ID: u64 = 0xef3c0aaec98136d4;
+pub const EDDSA_SECRET_KEY: &[u8] =
+    include_bytes!("../data/ed25519-secret.asc");
+pub const EDDSA_KEY_ID: u64 = 0xdeb501a8275e7d4e;
+
+/// The RSAHEADER tag in the Signature section.
+pub const SIGNATURE_TAG_RSA: u32 = 268;
+
+// ========================================================================= //
+
+/// Builds a small two-file package, using the given payload compressor and
+/// (optionally) signing key.
+pub fn build_package(compressor: &str, signing_key: Option<SigningKey>)
+                     -> Vec<u8> {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.set_version_string("0.1.2");
+    builder.set_release_string("1");
+    builder.set_payload_compression(compressor, 6);
+    builder.add_file(FileInfo::new("/usr/lib/hi.txt", 44));
+    builder.add_file(FileInfo::new("/usr/lib/bye.txt", 45));
+    if let Some(key) = signing_key {
+        builder.set_signing_key(key);
+    }
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut writer) = builder.next_file().unwrap() {
+        let contents = format!("Hello, {:?}!\nNice to meet you.\n",
+                               writer.file_path());
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    builder.finish().unwrap().into_inner()
+}
+
+/// Changes the public-key algorithm of each RSA signature packet (version 4,
+/// binary document, RSA, SHA-256) in a package's Signature section to ECDSA,
+/// which isn't supported.  Returns the number of packets changed.
+pub fn make_signatures_unsupported(package_data: &mut [u8]) -> usize {
+    // The Signature section comes between the 96-byte Lead and the Header,
+    // both sections starting with the same magic number.
+    let magic = [0x8e, 0xad, 0xe8, 0x01];
+    let header_start = (97..package_data.len())
+        .find(|&i| package_data[i..].starts_with(&magic))
+        .unwrap();
+    let mut count = 0;
+    for i in 96..header_start {
+        if package_data[i..].starts_with(&[4, 0, 1, 8]) {
+            package_data[i + 2] = 19;
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Shortens the given entry in a package's Signature section index by a few
+/// bytes, so that the signature packet it holds is cut off.
+pub fn truncate_signature(package_data: &mut [u8], tag: u32) {
+    // The index entries follow the 96-byte Lead and a 16-byte index header.
+    let read_u32 = |data: &[u8], offset: usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&data[offset..offset + 4]);
+        u32::from_be_bytes(bytes)
+    };
+    let num_entries = read_u32(package_data, 104) as usize;
+    let entry = (0..num_entries)
+        .map(|index| 112 + 16 * index)
+        .find(|&entry| read_u32(package_data, entry) == tag)
+        .unwrap();
+    let count = read_u32(package_data, entry + 12) - 10;
+    package_data[entry + 12..entry + 16].copy_from_slice(&count.to_be_bytes());
+}
+
+// ========================================================================= //
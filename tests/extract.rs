@@ -0,0 +1,199 @@
+#![cfg(unix)]
+
+extern crate rpmpkg;
+
+use rpmpkg::{ExtractOptions, FileFlags, FileInfo, Package, PackageBuilder,
+             PackageType};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+// ========================================================================= //
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("rpmpkg-extract-{}-{}", name, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    dir
+}
+
+fn build_package(files: Vec<(FileInfo, &[u8])>)
+                 -> Package<Cursor<Vec<u8>>> {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    let mut contents = HashMap::new();
+    for (file_info, data) in files {
+        contents.insert(file_info.name().to_string(), data);
+        builder.add_file(file_info);
+    }
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut writer) = builder.next_file().unwrap() {
+        let data = contents[writer.file_path()];
+        writer.write_all(data).unwrap();
+    }
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+    Package::read(package_file).unwrap()
+}
+
+fn hello_package() -> Package<Cursor<Vec<u8>>> {
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    build_package(vec![
+        (FileInfo::directory("/etc/hello").with_modified_time(mtime), b""),
+        (FileInfo::new("/etc/hello/hello.conf", 17)
+             .with_mode(0o600)
+             .with_flags(FileFlags::CONFIG)
+             .with_modified_time(mtime),
+         b"greeting = hello\n"),
+        (FileInfo::new("/usr/bin/hello", 21)
+             .with_mode(0o755)
+             .with_modified_time(mtime),
+         b"#!/bin/sh\necho hello\n"),
+        (FileInfo::symlink("/usr/bin/hi", "hello"), b""),
+        (FileInfo::new("/usr/share/doc/hello/README", 6)
+             .with_flags(FileFlags::DOC),
+         b"Hello\n"),
+        (FileInfo::new("/var/log/hello.log", 0).with_flags(FileFlags::GHOST),
+         b""),
+    ])
+}
+
+// ========================================================================= //
+
+#[test]
+fn extract_all_files() {
+    let dir = temp_dir("all");
+    let mut package = hello_package();
+    package.extract_to(&dir, &ExtractOptions::new()).unwrap();
+
+    assert_eq!(fs::read(dir.join("etc/hello/hello.conf")).unwrap(),
+               b"greeting = hello\n");
+    assert_eq!(fs::read(dir.join("usr/bin/hello")).unwrap(),
+               b"#!/bin/sh\necho hello\n");
+    assert_eq!(fs::read_link(dir.join("usr/bin/hi")).unwrap(),
+               PathBuf::from("hello"));
+    assert_eq!(fs::read(dir.join("usr/share/doc/hello/README")).unwrap(),
+               b"Hello\n");
+    assert!(!dir.join("var/log/hello.log").exists());
+
+    let metadata = fs::metadata(dir.join("etc/hello/hello.conf")).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o7777, 0o600);
+    assert_eq!(metadata.modified().unwrap(),
+               UNIX_EPOCH + Duration::from_secs(1_500_000_000));
+    let metadata = fs::metadata(dir.join("usr/bin/hello")).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o7777, 0o755);
+    let metadata = fs::metadata(dir.join("etc/hello")).unwrap();
+    assert!(metadata.is_dir());
+    assert_eq!(metadata.modified().unwrap(),
+               UNIX_EPOCH + Duration::from_secs(1_500_000_000));
+
+    // Extracting again over the top of the existing files should work.
+    package.extract_to(&dir, &ExtractOptions::new()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extract_with_options() {
+    let dir = temp_dir("options");
+    let mut package = hello_package();
+    let options = ExtractOptions::new().with_docs(false).with_ghosts(true);
+    package.extract_to(&dir, &options).unwrap();
+    assert!(dir.join("usr/bin/hello").exists());
+    assert!(!dir.join("usr/share/doc/hello/README").exists());
+    assert_eq!(fs::read(dir.join("var/log/hello.log")).unwrap(), b"");
+    fs::remove_dir_all(&dir).unwrap();
+
+    let options = ExtractOptions::new()
+        .with_pattern("/usr/bin/*")
+        .with_pattern("/etc/**.conf");
+    package.extract_to(&dir, &options).unwrap();
+    assert!(dir.join("usr/bin/hello").exists());
+    assert!(fs::symlink_metadata(dir.join("usr/bin/hi")).is_ok());
+    assert!(dir.join("etc/hello/hello.conf").exists());
+    assert!(!dir.join("usr/share").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extract_hard_links() {
+    let dir = temp_dir("hardlinks");
+    let mut package = build_package(vec![
+        (FileInfo::new("/usr/bin/hello", 6).with_inode(7), b""),
+        (FileInfo::new("/usr/bin/howdy", 6).with_inode(7), b"Hello\n"),
+        (FileInfo::new("/usr/lib/empty1", 0).with_inode(8), b""),
+        (FileInfo::new("/usr/lib/empty2", 0).with_inode(8), b""),
+    ]);
+    package.extract_to(&dir, &ExtractOptions::new()).unwrap();
+    assert_eq!(fs::read(dir.join("usr/bin/hello")).unwrap(), b"Hello\n");
+    assert_eq!(fs::read(dir.join("usr/bin/howdy")).unwrap(), b"Hello\n");
+    let hello = fs::metadata(dir.join("usr/bin/hello")).unwrap();
+    let howdy = fs::metadata(dir.join("usr/bin/howdy")).unwrap();
+    assert_eq!(hello.ino(), howdy.ino());
+    assert_eq!(hello.nlink(), 2);
+    let empty1 = fs::metadata(dir.join("usr/lib/empty1")).unwrap();
+    let empty2 = fs::metadata(dir.join("usr/lib/empty2")).unwrap();
+    assert_eq!(empty1.ino(), empty2.ino());
+    assert_eq!(empty1.len(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+
+    // If only some links are selected, they still get the data.
+    let options = ExtractOptions::new().with_pattern("/usr/bin/hello");
+    package.extract_to(&dir, &options).unwrap();
+    assert_eq!(fs::read(dir.join("usr/bin/hello")).unwrap(), b"Hello\n");
+    assert!(!dir.join("usr/bin/howdy").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extract_stripped_payload() {
+    let dir = temp_dir("stripped");
+    let mut package = build_package(vec![
+        (FileInfo::new("/var/lib/big.img", 5 << 30)
+             .with_flags(FileFlags::GHOST),
+         b""),
+        (FileInfo::new("/usr/bin/hello", 6).with_inode(7), b""),
+        (FileInfo::symlink("/usr/bin/hi", "hello"), b""),
+        (FileInfo::new("/usr/bin/howdy", 6).with_inode(7), b"Hello\n"),
+    ]);
+    assert!(package.header().has_stripped_payload());
+    package.extract_to(&dir, &ExtractOptions::new()).unwrap();
+    assert_eq!(fs::read(dir.join("usr/bin/hello")).unwrap(), b"Hello\n");
+    assert_eq!(fs::read(dir.join("usr/bin/howdy")).unwrap(), b"Hello\n");
+    assert_eq!(fs::read_link(dir.join("usr/bin/hi")).unwrap(),
+               PathBuf::from("hello"));
+    assert_eq!(fs::metadata(dir.join("usr/bin/hello")).unwrap().nlink(), 2);
+    assert!(!dir.join("var/lib/big.img").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reject_path_traversal() {
+    let dir = temp_dir("traversal");
+    let mut package =
+        build_package(vec![(FileInfo::new("/usr/../../evil", 4), b"evil")]);
+    assert!(package.extract_to(dir.join("root"), &ExtractOptions::new())
+                   .is_err());
+    assert!(!dir.join("evil").exists());
+    assert!(!dir.join("root").exists());
+}
+
+#[test]
+fn reject_writing_through_symlink() {
+    let dir = temp_dir("symlink");
+    let outside = dir.join("outside");
+    fs::create_dir_all(&outside).unwrap();
+    let mut package = build_package(vec![
+        (FileInfo::symlink("/usr/lib", outside.to_str().unwrap()), b""),
+        (FileInfo::new("/usr/lib/evil", 4), b"evil"),
+    ]);
+    assert!(package.extract_to(dir.join("root"), &ExtractOptions::new())
+                   .is_err());
+    assert!(!outside.join("evil").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// ========================================================================= //
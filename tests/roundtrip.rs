@@ -1,11 +1,17 @@
 extern crate rpmpkg;
 
-use rpmpkg::{FileInfo, Package, PackageBuilder, PackageType};
-use std::io::{Cursor, Read, Write};
+use rpmpkg::{ChangeLogEntry, Dependency, DependencyFlags, DependencyIter,
+             DigestAlgorithm, Evr, FileFlags, FileInfo, FileTrigger, Nevra,
+             Package, PackageBuilder, PackageType, PayloadCodec,
+             PayloadCodecRegistry, PayloadEncoder, PayloadFlags, Scriptlet,
+             ScriptletFlags, ScriptletType, Trigger, TriggerType};
+use std::io::{self, Cursor, Read, Write};
+use std::time::{Duration, UNIX_EPOCH};
 
 // ========================================================================= //
 
 #[test]
+#[allow(deprecated)]
 fn bzip2_compression() {
     let mut builder = PackageBuilder::new(PackageType::Binary);
     builder.set_package_name("hello");
@@ -60,6 +66,7 @@ fn bzip2_compression() {
 }
 
 #[test]
+#[allow(deprecated)]
 fn xz_compression() {
     let mut builder = PackageBuilder::new(PackageType::Binary);
     builder.set_payload_compression("xz", 2);
@@ -102,6 +109,281 @@ fn xz_compression() {
 }
 
 #[test]
+#[allow(deprecated)]
+fn zstd_compression() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_compression("zstd", 19);
+    builder.add_file(FileInfo::new("/usr/lib/hi.txt", 44));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    builder
+        .next_file()
+        .unwrap()
+        .unwrap()
+        .write_all(b"Hello, \"/usr/lib/hi.txt\"!\nNice to meet you.\n")
+        .unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    assert_eq!(package.header().payload_compressor(), "zstd");
+    assert_eq!(package.header().payload_compression_level(), "19");
+    assert!(package
+                .header()
+                .requires()
+                .any(|dep| dep.name() == "rpmlib(PayloadIsZstd)"));
+    let mut archive = package.read_archive().unwrap();
+    {
+        let mut file = archive.next_file().unwrap().unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents.as_str(),
+                   "Hello, \"/usr/lib/hi.txt\"!\n\
+                    Nice to meet you.\n");
+    }
+    assert!(archive.next_file().unwrap().is_none());
+}
+
+#[test]
+#[allow(deprecated)]
+fn zstd_long_window() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_compression("zstd", 3);
+    builder.set_payload_zstd_long_window(28);
+    builder.add_file(FileInfo::new("/usr/lib/zeros", 100_000));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    builder
+        .next_file()
+        .unwrap()
+        .unwrap()
+        .write_all(&[0; 100_000])
+        .unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    assert_eq!(package.header().payload_compression_level(), "3L28");
+
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_compression("zstd", 3);
+    builder.set_payload_zstd_long_window(40);
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn bzip2_compression_levels() {
+    let data: Vec<u8> = (0..400_000u32)
+        .map(|n| (n.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect();
+    let mut sizes = Vec::new();
+    for &level in &[1, 9] {
+        let mut builder = PackageBuilder::new(PackageType::Binary);
+        builder.set_payload_compression("bzip2", level);
+        builder.add_file(FileInfo::new("/usr/lib/data", data.len() as u64));
+        let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+        builder.next_file().unwrap().unwrap().write_all(&data).unwrap();
+        let package_data = builder.finish().unwrap().into_inner();
+        sizes.push(package_data.len());
+        let mut package = Package::read(Cursor::new(package_data)).unwrap();
+        package.validate().unwrap();
+        assert_eq!(package.header().payload_flags().unwrap().level(),
+                   Some(level));
+    }
+    // Level 1 uses 100k blocks, and level 9 uses 900k blocks, so level 1
+    // has to compress the data in several pieces.
+    assert!(sizes[0] > sizes[1]);
+}
+
+#[test]
+fn full_payload_flags() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_compression("xz", 6);
+    builder.set_payload_flags(PayloadFlags::new(2)
+                                  .with_threads(4)
+                                  .with_io("xzdio"));
+    builder.add_file(FileInfo::new("/usr/lib/hi.txt", 6));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    builder.next_file().unwrap().unwrap().write_all(b"Hello\n").unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let flags = package.header().payload_flags().unwrap();
+    assert_eq!(flags.to_string(), "w2T4.xzdio");
+    assert_eq!(flags.level(), Some(2));
+    assert_eq!(flags.threads(), Some(4));
+    assert_eq!(flags.io(), Some("xzdio"));
+
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_compression("gzip", 6);
+    builder.set_payload_flags(PayloadFlags::new(10));
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn multithreaded_compression() {
+    let data: Vec<u8> = (0..600_000u32)
+        .map(|n| (n.wrapping_mul(2_654_435_761) >> 28) as u8)
+        .collect();
+    for &(compressor, level) in &[("gzip", 9), ("xz", 2), ("zstd", 3)] {
+        let mut payloads = Vec::new();
+        for &threads in &[1, 4] {
+            let mut builder = PackageBuilder::new(PackageType::Binary);
+            builder.set_payload_compression(compressor, level);
+            builder.set_payload_threads(threads);
+            builder.add_file(FileInfo::new("/usr/lib/data", 600_000));
+            let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+            builder.next_file().unwrap().unwrap().write_all(&data).unwrap();
+            let package_file =
+                Cursor::new(builder.finish().unwrap().into_inner());
+
+            let mut package = Package::read(package_file).unwrap();
+            package.validate().unwrap();
+            let flags = package.header().payload_flags().unwrap();
+            assert_eq!(flags.to_string(), format!("{}T{}", level, threads));
+            let payload_digest =
+                package.header().payload_digest().unwrap().to_string();
+            payloads.push(payload_digest);
+            let mut archive = package.read_archive().unwrap();
+            let mut contents = Vec::new();
+            archive
+                .next_file()
+                .unwrap()
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert!(contents == data);
+        }
+        assert_eq!(payloads[0], payloads[1]);
+    }
+}
+
+#[test]
+fn lzma_lz4_and_identity_compression() {
+    for &(compressor, feature) in &[("lzma", Some("rpmlib(PayloadIsLzma)")),
+                                    ("lz4", None),
+                                    ("identity", None)]
+    {
+        let mut builder = PackageBuilder::new(PackageType::Binary);
+        builder.set_payload_compression(compressor, 6);
+        builder.add_file(FileInfo::new("/usr/lib/hi.txt", 44));
+        let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+        builder
+            .next_file()
+            .unwrap()
+            .unwrap()
+            .write_all(b"Hello, \"/usr/lib/hi.txt\"!\nNice to meet you.\n")
+            .unwrap();
+        let package_file =
+            Cursor::new(builder.finish().unwrap().into_inner());
+
+        let mut package = Package::read(package_file).unwrap();
+        package.validate().unwrap();
+        assert_eq!(package.header().payload_compressor(), compressor);
+        let payload_features: Vec<String> = package
+            .header()
+            .requires()
+            .map(|dep| dep.name().to_string())
+            .filter(|name| name.starts_with("rpmlib(PayloadIs"))
+            .collect();
+        assert_eq!(payload_features,
+                   feature.iter().map(|f| f.to_string()).collect::<Vec<_>>());
+        let mut archive = package.read_archive().unwrap();
+        {
+            let mut file = archive.next_file().unwrap().unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents.as_str(),
+                       "Hello, \"/usr/lib/hi.txt\"!\n\
+                        Nice to meet you.\n");
+        }
+        assert!(archive.next_file().unwrap().is_none());
+    }
+}
+
+/// A toy codec that "compresses" by inverting every bit.
+struct InvertCodec;
+
+struct InvertReader<'r>(Box<dyn Read + 'r>);
+
+impl<'r> Read for InvertReader<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.0.read(buf)?;
+        for byte in buf[..bytes_read].iter_mut() {
+            *byte = !*byte;
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl PayloadEncoder for InvertCodec {
+    fn encode(&mut self, data: &[u8], output: &mut dyn Write)
+              -> io::Result<()> {
+        let inverted: Vec<u8> = data.iter().map(|&byte| !byte).collect();
+        output.write_all(&inverted)
+    }
+
+    fn finish(self: Box<Self>, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl PayloadCodec for InvertCodec {
+    fn name(&self) -> &str { "invert" }
+
+    fn decoder<'r>(&self, reader: Box<dyn Read + 'r>)
+                   -> io::Result<Box<dyn Read + 'r>> {
+        Ok(Box::new(InvertReader(reader)))
+    }
+
+    fn encoder(&self, _flags: &PayloadFlags)
+               -> io::Result<Box<dyn PayloadEncoder>> {
+        Ok(Box::new(InvertCodec))
+    }
+}
+
+#[test]
+fn custom_payload_codec() {
+    let mut codecs = PayloadCodecRegistry::new();
+    codecs.register(InvertCodec);
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_codecs(codecs.clone());
+    builder.set_payload_compression("invert", 1);
+    builder.add_file(FileInfo::new("/usr/lib/hi.txt", 6));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    builder.next_file().unwrap().unwrap().write_all(b"Hello\n").unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    assert_eq!(package.header().payload_compressor(), "invert");
+    // The default registry doesn't know about the custom codec.
+    assert!(package.validate().is_err());
+    assert!(package.read_archive().is_err());
+    package.set_payload_codecs(codecs);
+    package.validate().unwrap();
+    let mut archive = package.read_archive().unwrap();
+    let mut contents = String::new();
+    archive
+        .next_file()
+        .unwrap()
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents.as_str(), "Hello\n");
+}
+
+#[test]
+fn unsupported_payload_compressor() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_compression("lzip", 6);
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_payload_codecs(PayloadCodecRegistry::empty());
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+#[allow(deprecated)]
 fn gzip_compression() {
     let mut builder = PackageBuilder::new(PackageType::Binary);
     builder.set_payload_compression("gzip", 9);
@@ -143,4 +425,586 @@ fn gzip_compression() {
     assert!(archive.next_file().unwrap().is_none());
 }
 
+#[test]
+fn header_and_payload_digests() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.add_file(FileInfo::new("/usr/lib/hi.txt", 44));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut writer) = builder.next_file().unwrap() {
+        let contents = format!("Hello, {:?}!\nNice to meet you.\n",
+                               writer.file_path());
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    let package_data = builder.finish().unwrap().into_inner();
+
+    let mut package = Package::read(Cursor::new(package_data.clone()))
+        .unwrap();
+    package.validate().unwrap();
+    assert_eq!(package.header().payload_digest_algorithm(),
+               Some(DigestAlgorithm::Sha256));
+    assert_eq!(package.header().payload_digest().unwrap().len(), 64);
+    assert_eq!(package.signature().header_sha256().unwrap().len(), 64);
+
+    let mut corrupted = package_data;
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    let mut package = Package::read(Cursor::new(corrupted)).unwrap();
+    assert!(package.validate().is_err());
+}
+
+#[test]
+fn file_digest_algorithm() {
+    let builder = PackageBuilder::new(PackageType::Binary);
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+    let package = Package::read(package_file).unwrap();
+    assert_eq!(package.header().file_digest_algorithm(),
+               Some(DigestAlgorithm::Sha256));
+    assert!(package
+                .header()
+                .requires()
+                .any(|dep| dep.name() == "rpmlib(FileDigests)"));
+
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_file_digest_algorithm(DigestAlgorithm::Md5);
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+    let package = Package::read(package_file).unwrap();
+    assert_eq!(package.header().file_digest_algorithm(),
+               Some(DigestAlgorithm::Md5));
+    assert!(!package
+                 .header()
+                 .requires()
+                 .any(|dep| dep.name() == "rpmlib(FileDigests)"));
+}
+
+#[test]
+fn file_digests() {
+    let algorithms = [
+        (DigestAlgorithm::Sha256,
+         "90db3c2ea89843dc21ab076c0f879885\
+          6f0449d2f364aee38a09b798ee29445e"),
+        (DigestAlgorithm::Md5, "7e2252666b78adb9b19956c293f68b3e"),
+    ];
+    for &(algorithm, expected_digest) in algorithms.iter() {
+        let mut builder = PackageBuilder::new(PackageType::Binary);
+        builder.set_file_digest_algorithm(algorithm);
+        builder.add_file(FileInfo::new("/usr/lib/hi.txt", 44));
+        let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut writer) = builder.next_file().unwrap() {
+            let contents = format!("Hello, {:?}!\nNice to meet you.\n",
+                                   writer.file_path());
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        let package_data = builder.finish().unwrap().into_inner();
+
+        let mut package = Package::read(Cursor::new(package_data)).unwrap();
+        package.validate().unwrap();
+        let files: Vec<FileInfo> = package.header().files().collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].digest(), expected_digest);
+    }
+}
+
+#[test]
+fn dependencies() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.set_version_string("1.2.3");
+    builder.set_release_string("4");
+    builder.set_payload_compression("xz", 6);
+    builder.add_provides(Dependency::equal("hello-world", "1.2.3"));
+    builder.add_requires(Dependency::greater_or_equal("libfoo", "2.0"));
+    builder.add_requires(Dependency::any("/bin/sh")
+                             .with_flags(DependencyFlags::SCRIPT_POST));
+    builder.add_conflicts(Dependency::less("goodbye", "3"));
+    builder.add_obsoletes(Dependency::any("hello-old"));
+    builder.add_file(FileInfo::new("/usr/lib/hi.txt", 0));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    while builder.next_file().unwrap().is_some() {}
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let header = package.header();
+    let provides: Vec<String> =
+        header.provides().map(|dep| dep.to_string()).collect();
+    assert_eq!(provides,
+               vec!["hello-world = 1.2.3".to_string(),
+                    "hello = 1.2.3-4".to_string()]);
+    let requires: Vec<Dependency> = header.requires().collect();
+    assert_eq!(requires.len(), 5);
+    assert_eq!(requires[0], Dependency::greater_or_equal("libfoo", "2.0"));
+    assert_eq!(requires[1].name(), "/bin/sh");
+    assert_eq!(requires[1].flags(), DependencyFlags::SCRIPT_POST);
+    assert_eq!(requires[2].to_string(),
+               "rpmlib(CompressedFileNames) <= 3.0.4-1");
+    assert!(requires[2].flags().contains(DependencyFlags::RPMLIB));
+    assert_eq!(requires[3].name(), "rpmlib(FileDigests)");
+    assert_eq!(requires[4].name(), "rpmlib(PayloadIsXz)");
+    let conflicts: Vec<String> =
+        header.conflicts().map(|dep| dep.to_string()).collect();
+    assert_eq!(conflicts, vec!["goodbye < 3".to_string()]);
+    let obsoletes: Vec<String> =
+        header.obsoletes().map(|dep| dep.to_string()).collect();
+    assert_eq!(obsoletes, vec!["hello-old".to_string()]);
+    let files: Vec<FileInfo> = header.files().collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name(), "/usr/lib/hi.txt");
+}
+
+#[test]
+fn weak_dependencies() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_recommends(Dependency::any("hello-docs"));
+    builder.add_suggests(Dependency::greater_or_equal("hello-extras", "2"));
+    builder.add_supplements(Dependency::any("hello-desktop"));
+    builder.add_enhances(Dependency::equal("goodbye", "1.0"));
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let header = package.header();
+    let strings = |iter: DependencyIter| -> Vec<String> {
+        iter.map(|dep| dep.to_string()).collect()
+    };
+    assert_eq!(strings(header.recommends()), vec!["hello-docs".to_string()]);
+    assert_eq!(strings(header.suggests()),
+               vec!["hello-extras >= 2".to_string()]);
+    assert_eq!(strings(header.supplements()),
+               vec!["hello-desktop".to_string()]);
+    assert_eq!(strings(header.enhances()), vec!["goodbye = 1.0".to_string()]);
+    assert_eq!(header.conflicts().count(), 0);
+}
+
+#[test]
+fn epoch_and_nevra() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.set_epoch(2);
+    builder.set_version_string("1.0~rc1");
+    builder.set_release_string("3");
+    builder.set_architecture("noarch");
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let header = package.header();
+    assert_eq!(header.epoch(), Some(2));
+    assert_eq!(header.evr(), Evr::new(Some(2), "1.0~rc1", "3"));
+    assert!(header.evr() < Evr::parse("2:1.0-1").unwrap());
+    assert!(header.evr() > Evr::parse("1:9.9-1").unwrap());
+    assert_eq!(header.nevra().to_string(), "hello-2:1.0~rc1-3.noarch");
+    assert_eq!(header.nevra(),
+               Nevra::parse("hello-2:1.0~rc1-3.noarch").unwrap());
+    assert!(header
+                .provides()
+                .any(|dep| dep == Dependency::equal("hello", "2:1.0~rc1-3")));
+}
+
+#[test]
+fn scriptlets() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.set_scriptlet(ScriptletType::PostInstall,
+                          Scriptlet::new("systemctl daemon-reload\n"));
+    let ldconfig = Scriptlet::new("").with_interpreter("/sbin/ldconfig");
+    builder.set_scriptlet(ScriptletType::PostUninstall, ldconfig);
+    builder.set_scriptlet(ScriptletType::PostUninstallTransaction,
+                          Scriptlet::new("print(\"bye\")")
+                              .with_interpreter("<lua>")
+                              .with_flags(ScriptletFlags::EXPAND |
+                                              ScriptletFlags::CRITICAL));
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let header = package.header();
+    assert_eq!(header.scriptlet(ScriptletType::PreInstall), None);
+    assert_eq!(header.scriptlet(ScriptletType::PostInstall),
+               Some(Scriptlet::new("systemctl daemon-reload\n")));
+    let postun = header.scriptlet(ScriptletType::PostUninstall).unwrap();
+    assert_eq!(postun.body(), "");
+    assert_eq!(postun.interpreter(), "/sbin/ldconfig");
+    let postuntrans = header
+        .scriptlet(ScriptletType::PostUninstallTransaction)
+        .unwrap();
+    assert_eq!(postuntrans.body(), "print(\"bye\")");
+    assert_eq!(postuntrans.interpreter(), "<lua>");
+    assert_eq!(postuntrans.flags(),
+               ScriptletFlags::EXPAND | ScriptletFlags::CRITICAL);
+}
+
+#[test]
+fn triggers() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello-plugin");
+    let reload = Scriptlet::new("systemctl try-restart hello\n");
+    builder.add_trigger(Trigger::new(TriggerType::Install, reload.clone())
+                            .with_condition(Dependency::any("hello"))
+                            .with_condition(Dependency::greater_or_equal(
+                                "hello-server", "2.0")));
+    builder.add_trigger(Trigger::new(TriggerType::PostUninstall,
+                                     Scriptlet::new("echo bye\n"))
+                            .with_condition(Dependency::any("hello")));
+    let cache = Scriptlet::new("/usr/bin/hello-cache --update\n");
+    builder.add_file_trigger(FileTrigger::new(TriggerType::Install,
+                                              cache.clone())
+                                 .with_prefix("/usr/lib/hello/plugins/"));
+    builder.add_transaction_file_trigger(
+        FileTrigger::new(TriggerType::Uninstall, cache.clone())
+            .with_prefix("/usr/lib/hello/plugins/")
+            .with_prefix("/usr/share/hello/")
+            .with_priority(500));
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let header = package.header();
+    let triggers = header.triggers();
+    assert_eq!(triggers.len(), 2);
+    assert_eq!(triggers[0].trigger_type(), TriggerType::Install);
+    assert_eq!(triggers[0].scriptlet(), &reload);
+    assert_eq!(triggers[0].conditions(),
+               &[Dependency::any("hello"),
+                 Dependency::greater_or_equal("hello-server", "2.0")]);
+    assert_eq!(triggers[1].trigger_type(), TriggerType::PostUninstall);
+    assert_eq!(triggers[1].scriptlet().body(), "echo bye\n");
+    assert_eq!(triggers[1].conditions(), &[Dependency::any("hello")]);
+
+    let file_triggers = header.file_triggers();
+    assert_eq!(file_triggers.len(), 1);
+    assert_eq!(file_triggers[0].trigger_type(), TriggerType::Install);
+    assert_eq!(file_triggers[0].scriptlet(), &cache);
+    assert_eq!(file_triggers[0].prefixes(),
+               &["/usr/lib/hello/plugins/".to_string()]);
+    assert_eq!(file_triggers[0].priority(), 1000000);
+
+    let trans_triggers = header.transaction_file_triggers();
+    assert_eq!(trans_triggers.len(), 1);
+    assert_eq!(trans_triggers[0].trigger_type(), TriggerType::Uninstall);
+    assert_eq!(trans_triggers[0].prefixes(),
+               &["/usr/lib/hello/plugins/".to_string(),
+                 "/usr/share/hello/".to_string()]);
+    assert_eq!(trans_triggers[0].priority(), 500);
+    assert!(header
+                .requires()
+                .any(|dep| dep.name() == "rpmlib(FileTriggers)"));
+}
+
+#[test]
+fn trigger_without_conditions() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.add_trigger(Trigger::new(TriggerType::Install,
+                                     Scriptlet::new("echo hi\n")));
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn preinstall_file_trigger() {
+    let scriptlet = Scriptlet::new("echo hi\n");
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.add_file_trigger(FileTrigger::new(TriggerType::PreInstall,
+                                              scriptlet.clone())
+                                 .with_prefix("/usr/lib/hello/"));
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.add_transaction_file_trigger(
+        FileTrigger::new(TriggerType::PreInstall, scriptlet)
+            .with_prefix("/usr/lib/hello/"));
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+}
+
+#[test]
+fn changelog() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    let day = |days: u64| UNIX_EPOCH + Duration::from_secs(days * 86400);
+    builder.add_changelog_entry(day(100), "Jane Doe - 1.0-1", "- Middle");
+    builder.add_changelog_entry(day(200), "Jane Doe - 2.0-1", "- Newest");
+    builder.add_changelog_entry(day(50), "John Doe - 0.1-1", "- Oldest");
+    let spec = "* Fri Jan 02 1970 John Doe - 0.0-1\n- Ancient\n";
+    for entry in ChangeLogEntry::parse_spec_changelog(spec).unwrap() {
+        builder.add_changelog_entry(entry.timestamp(),
+                                    entry.author(),
+                                    entry.description());
+    }
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let entries: Vec<ChangeLogEntry> = package.header().changelog().collect();
+    let descriptions: Vec<&str> =
+        entries.iter().map(ChangeLogEntry::description).collect();
+    assert_eq!(descriptions,
+               vec!["- Newest", "- Middle", "- Oldest", "- Ancient"]);
+    assert_eq!(entries[0].timestamp(), day(200));
+    assert_eq!(entries[0].author(), "Jane Doe - 2.0-1");
+    assert_eq!(entries[3].timestamp(),
+               day(1) + Duration::from_secs(12 * 3600));
+}
+
+#[test]
+fn empty_changelog() {
+    let builder = PackageBuilder::new(PackageType::Binary);
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+    let package = Package::read(package_file).unwrap();
+    assert_eq!(package.header().changelog().count(), 0);
+}
+
+#[test]
+fn special_files() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_file(FileInfo::directory("/etc/hello")
+                         .with_user("nginx")
+                         .with_group("nginx"));
+    builder.add_file(FileInfo::new("/etc/hello/hello.conf", 6)
+                         .with_mode(0o600)
+                         .with_user("nginx")
+                         .with_group("nginx")
+                         .with_flags(FileFlags::CONFIG |
+                                         FileFlags::NOREPLACE)
+                         .with_modified_time(UNIX_EPOCH +
+                                                 Duration::from_secs(1000)));
+    builder.add_file(FileInfo::symlink("/usr/bin/hi", "/usr/bin/hello"));
+    builder.add_file(FileInfo::char_device("/dev/hello", 10, 200));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    {
+        let mut writer = builder.next_file().unwrap().unwrap();
+        assert_eq!(writer.file_path(), "/etc/hello/hello.conf");
+        writer.write_all(b"x = 1\n").unwrap();
+    }
+    assert!(builder.next_file().unwrap().is_none());
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let files: Vec<FileInfo> = package.header().files().collect();
+    assert_eq!(files.len(), 4);
+    assert!(files[0].is_directory());
+    assert_eq!(files[0].mode(), 0o040755);
+    assert_eq!(files[0].user_name(), "nginx");
+    assert_eq!(files[0].group_name(), "nginx");
+    assert_eq!(files[0].digest(), "");
+    assert!(files[1].is_regular_file());
+    assert_eq!(files[1].mode(), 0o100600);
+    assert_eq!(files[1].flags(), FileFlags::CONFIG | FileFlags::NOREPLACE);
+    assert_eq!(files[1].modified_time(),
+               UNIX_EPOCH + Duration::from_secs(1000));
+    assert!(files[2].is_symlink());
+    assert_eq!(files[2].symlink_target(), Some("/usr/bin/hello"));
+    assert_eq!(files[2].size(), 14);
+    assert_eq!(files[3].device_numbers(), Some((10, 200)));
+    assert_eq!(files[3].mode(), 0o020600);
+
+    let mut archive = package.read_archive().unwrap();
+    let mut names = Vec::new();
+    while let Some(mut file) = archive.next_file().unwrap() {
+        names.push(file.file_path().to_string());
+        if file.file_path() == "/usr/bin/hi" {
+            let mut target = String::new();
+            file.read_to_string(&mut target).unwrap();
+            assert_eq!(target, "/usr/bin/hello");
+        }
+    }
+    assert_eq!(names,
+               vec!["/etc/hello", "/etc/hello/hello.conf", "/usr/bin/hi",
+                    "/dev/hello"]);
+}
+
+#[test]
+fn ghost_files() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_file(FileInfo::new("/var/log/hello.log", 100)
+                         .with_flags(FileFlags::GHOST));
+    builder.add_file(FileInfo::new("/usr/share/doc/hello/README", 6)
+                         .with_flags(FileFlags::DOC | FileFlags::README));
+    builder.add_file(FileInfo::new("/var/run/hello.pid", 0)
+                         .with_flags(FileFlags::GHOST));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    {
+        let mut writer = builder.next_file().unwrap().unwrap();
+        assert_eq!(writer.file_path(), "/usr/share/doc/hello/README");
+        writer.write_all(b"Hello\n").unwrap();
+    }
+    assert!(builder.next_file().unwrap().is_none());
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    assert_eq!(package.header().total_install_size(), 6);
+    let files: Vec<FileInfo> = package.header().files().collect();
+    assert_eq!(files.len(), 3);
+    assert!(files[0].is_ghost());
+    assert_eq!(files[0].size(), 100);
+    assert_eq!(files[0].digest(), "");
+    assert!(!files[1].is_ghost());
+    assert_eq!(files[1].flags(), FileFlags::DOC | FileFlags::README);
+    assert!(files[2].is_ghost());
+
+    let mut archive = package.read_archive().unwrap();
+    {
+        let file = archive.next_file().unwrap().unwrap();
+        assert_eq!(file.file_path(), "/usr/share/doc/hello/README");
+    }
+    assert!(archive.next_file().unwrap().is_none());
+}
+
+#[test]
+fn large_files() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_file(FileInfo::new("/var/lib/hello/big.img", 5 << 30)
+                         .with_flags(FileFlags::GHOST));
+    builder.add_file(FileInfo::new("/usr/bin/hello", 6));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    builder.next_file().unwrap().unwrap().write_all(b"Hello\n").unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    assert!(package
+                .header()
+                .requires()
+                .any(|dep| dep.name() == "rpmlib(LargeFiles)"));
+    assert_eq!(package.header().total_install_size(), 6);
+    let sizes: Vec<u64> = package
+        .header()
+        .files()
+        .map(|file_info| file_info.size())
+        .collect();
+    assert_eq!(sizes, vec![5 << 30, 6]);
+}
+
+#[test]
+fn stripped_payload() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    // A large ghost file is enough to require 64-bit file sizes, and so the
+    // stripped cpio format, without having to write that much data.
+    builder.add_file(FileInfo::new("/var/lib/hello/big.img", 5 << 30)
+                         .with_flags(FileFlags::GHOST));
+    builder.add_file(FileInfo::directory("/usr/lib/hello"));
+    builder.add_file(FileInfo::new("/usr/bin/hello", 7).with_inode(1));
+    builder.add_file(FileInfo::symlink("/usr/bin/hi", "hello"));
+    builder.add_file(FileInfo::new("/usr/bin/howdy", 7).with_inode(1));
+    builder.add_file(FileInfo::new("/usr/lib/hello/data", 3));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    for &(path, data) in &[("/usr/bin/howdy", "Howdy!\n"),
+                           ("/usr/lib/hello/data", "abc")]
+    {
+        let mut writer = builder.next_file().unwrap().unwrap();
+        assert_eq!(writer.file_path(), path);
+        writer.write_all(data.as_bytes()).unwrap();
+    }
+    assert!(builder.next_file().unwrap().is_none());
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    assert!(package.header().has_stripped_payload());
+    package.validate().unwrap();
+    let mut entries = Vec::new();
+    let mut archive = package.read_archive().unwrap();
+    while let Some(mut file) = archive.next_file().unwrap() {
+        let mut data = String::new();
+        file.read_to_string(&mut data).unwrap();
+        entries.push((file.file_path().to_string(), data));
+    }
+    let entries: Vec<(&str, &str)> = entries
+        .iter()
+        .map(|&(ref path, ref data)| (path.as_str(), data.as_str()))
+        .collect();
+    assert_eq!(entries,
+               vec![("/usr/lib/hello", ""),
+                    ("/usr/bin/hello", ""),
+                    ("/usr/bin/hi", "hello"),
+                    ("/usr/bin/howdy", "Howdy!\n"),
+                    ("/usr/lib/hello/data", "abc")]);
+}
+
+#[test]
+fn hard_links() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_file(FileInfo::new("/usr/bin/hello", 6).with_inode(1234));
+    builder.add_file(FileInfo::new("/usr/bin/hi", 3));
+    builder.add_file(FileInfo::new("/usr/bin/howdy", 6).with_inode(1234));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    {
+        let mut writer = builder.next_file().unwrap().unwrap();
+        assert_eq!(writer.file_path(), "/usr/bin/hi");
+        writer.write_all(b"Hi\n").unwrap();
+    }
+    {
+        let mut writer = builder.next_file().unwrap().unwrap();
+        assert_eq!(writer.file_path(), "/usr/bin/howdy");
+        writer.write_all(b"Hello\n").unwrap();
+    }
+    assert!(builder.next_file().unwrap().is_none());
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    assert_eq!(package.header().hard_link_sets(), vec![vec![0, 2]]);
+    assert_eq!(package.header().total_install_size(), 9);
+    let files: Vec<FileInfo> = package.header().files().collect();
+    assert_eq!(files[0].inode(), files[2].inode());
+    assert_ne!(files[0].inode(), files[1].inode());
+    assert_ne!(files[1].inode(), 0);
+    assert_eq!(files[0].size(), 6);
+    assert!(!files[0].digest().is_empty());
+    assert_eq!(files[0].digest(), files[2].digest());
+    assert_ne!(files[0].digest(), files[1].digest());
+
+    let mut archive = package.read_archive().unwrap();
+    let mut sizes = Vec::new();
+    while let Some(file) = archive.next_file().unwrap() {
+        sizes.push((file.file_path().to_string(), file.file_size()));
+    }
+    assert_eq!(sizes,
+               vec![("/usr/bin/hello".to_string(), 0),
+                    ("/usr/bin/hi".to_string(), 3),
+                    ("/usr/bin/howdy".to_string(), 6)]);
+}
+
+#[test]
+fn rich_dependencies() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_recommends(Dependency::any("(hello-docs if man-db)"));
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+
+    let mut package = Package::read(package_file).unwrap();
+    package.validate().unwrap();
+    let header = package.header();
+    let recommend = header.recommends().next().unwrap();
+    assert!(recommend.is_rich());
+    let rich = recommend.to_rich().unwrap();
+    assert_eq!(rich.to_string(), "(hello-docs if man-db)");
+    assert!(rich.is_satisfied_by(&[]));
+    assert!(!rich.is_satisfied_by(&[Dependency::any("man-db")]));
+    assert!(header.requires().any(|dep| {
+        dep.name() == "rpmlib(RichDependencies)" &&
+            dep.flags().contains(DependencyFlags::RPMLIB)
+    }));
+}
+
+#[test]
+fn invalid_rich_dependency() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.add_requires(Dependency::any("(foo and bar or baz)"));
+    assert!(builder.build(Cursor::new(Vec::new())).is_err());
+}
+
 // ========================================================================= //
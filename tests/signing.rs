@@ -0,0 +1,172 @@
+extern crate rpmpkg;
+
+mod common;
+
+use common::{EDDSA_KEY_ID, EDDSA_SECRET_KEY, RSA_KEY_ID, RSA_SECRET_KEY,
+             SIGNATURE_TAG_RSA, build_package, make_signatures_unsupported,
+             truncate_signature};
+use rpmpkg::{Keyring, Package, PublicKeyAlgorithm, SignatureRegion,
+             SigningKey};
+use std::io::Cursor;
+
+// ========================================================================= //
+
+fn check_signed_package(secret_key: &[u8], key_id: u64,
+                        algorithm: PublicKeyAlgorithm) {
+    let signing_key = SigningKey::read(secret_key).unwrap();
+    assert_eq!(signing_key.key_id(), key_id);
+    let package_data = build_package("gzip", Some(signing_key));
+
+    let mut package = Package::read(Cursor::new(package_data.clone()))
+        .unwrap();
+    assert!(package.signature().has_openpgp_signatures());
+    let mut keyring = Keyring::new();
+    keyring.add_keys(secret_key).unwrap();
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert_eq!(verifications[0].region(), SignatureRegion::Header);
+    assert_eq!(verifications[1].region(),
+               SignatureRegion::HeaderAndPayload);
+    for verification in verifications.iter() {
+        assert_eq!(verification.key_id(), Some(key_id));
+        assert_eq!(verification.algorithm(), Some(algorithm));
+        assert!(verification.is_verified());
+    }
+
+    // Without the key, the package is still valid, but the signatures can't
+    // be verified.
+    let verifications =
+        package.validate_with_keyring(&Keyring::new()).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert!(verifications.iter().all(|v| !v.is_verified()));
+
+    // Corrupting the end of the payload should invalidate the signature.
+    let mut corrupted = package_data;
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    let mut package = Package::read(Cursor::new(corrupted)).unwrap();
+    assert!(package.validate_with_keyring(&keyring).is_err());
+}
+
+#[test]
+fn rsa_signed_package() {
+    check_signed_package(RSA_SECRET_KEY, RSA_KEY_ID, PublicKeyAlgorithm::Rsa);
+}
+
+#[test]
+fn eddsa_signed_package() {
+    check_signed_package(EDDSA_SECRET_KEY,
+                         EDDSA_KEY_ID,
+                         PublicKeyAlgorithm::EdDsa);
+}
+
+#[test]
+fn unsupported_signature_algorithm() {
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let mut package_data = build_package("gzip", Some(signing_key));
+    assert_eq!(make_signatures_unsupported(&mut package_data), 2);
+
+    // The package should still validate, with both signatures reported as
+    // unsupported.
+    let mut package = Package::read(Cursor::new(package_data)).unwrap();
+    package.validate().unwrap();
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    for verification in verifications.iter() {
+        assert!(!verification.is_supported());
+        assert!(!verification.is_verified());
+        assert_eq!(verification.key_id(), Some(RSA_KEY_ID));
+        assert_eq!(verification.algorithm(), None);
+    }
+}
+
+#[test]
+fn malformed_signature() {
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let mut package_data = build_package("gzip", Some(signing_key));
+    truncate_signature(&mut package_data, SIGNATURE_TAG_RSA);
+
+    // A malformed signature is an error, unlike an unsupported one.
+    let mut package = Package::read(Cursor::new(package_data)).unwrap();
+    assert!(package.validate().is_err());
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+    assert!(package.validate_with_keyring(&keyring).is_err());
+}
+
+#[test]
+fn unsigned_package() {
+    let package_data = build_package("gzip", None);
+    let mut package = Package::read(Cursor::new(package_data)).unwrap();
+    assert!(!package.signature().has_openpgp_signatures());
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert!(verifications.is_empty());
+}
+
+#[test]
+fn add_signature_to_unsigned_package() {
+    let package_data = build_package("gzip", None);
+    let mut package = Package::read(Cursor::new(package_data)).unwrap();
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let mut output = Vec::new();
+    package.write_with_signature_added(&signing_key, &mut output).unwrap();
+
+    let mut package = Package::read(Cursor::new(output)).unwrap();
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert!(verifications.iter().all(|v| v.is_verified()));
+    assert!(verifications.iter().all(|v| v.key_id() == Some(RSA_KEY_ID)));
+    assert_eq!(package.header().package_name(), "hello");
+}
+
+#[test]
+fn resign_package() {
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let package_data = build_package("gzip", Some(signing_key));
+    let mut package = Package::read(Cursor::new(package_data.clone()))
+        .unwrap();
+    let signing_key = SigningKey::read(EDDSA_SECRET_KEY).unwrap();
+    let mut output = Vec::new();
+    package.write_resigned(&signing_key, &mut output).unwrap();
+    // The reserved space in the Signature section should absorb the change
+    // in signature size, so the Header stays put.
+    assert_eq!(output.len(), package_data.len());
+
+    let mut package = Package::read(Cursor::new(output)).unwrap();
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+    keyring.add_keys(EDDSA_SECRET_KEY).unwrap();
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    for verification in verifications.iter() {
+        assert_eq!(verification.key_id(), Some(EDDSA_KEY_ID));
+        assert_eq!(verification.algorithm(),
+                   Some(PublicKeyAlgorithm::EdDsa));
+        assert!(verification.is_verified());
+    }
+}
+
+#[test]
+fn strip_signatures_from_package() {
+    let signing_key = SigningKey::read(EDDSA_SECRET_KEY).unwrap();
+    let package_data = build_package("gzip", Some(signing_key));
+    let mut package = Package::read(Cursor::new(package_data.clone()))
+        .unwrap();
+    let mut output = Vec::new();
+    package.write_without_signatures(&mut output).unwrap();
+    assert_eq!(output.len(), package_data.len());
+
+    let mut package = Package::read(Cursor::new(output)).unwrap();
+    assert!(!package.signature().has_openpgp_signatures());
+    let verifications =
+        package.validate_with_keyring(&Keyring::new()).unwrap();
+    assert!(verifications.is_empty());
+}
+
+// ========================================================================= //
@@ -0,0 +1,159 @@
+extern crate rpmpkg;
+
+mod common;
+
+use common::{RSA_KEY_ID, RSA_SECRET_KEY, SIGNATURE_TAG_RSA, build_package,
+             make_signatures_unsupported, truncate_signature};
+use rpmpkg::{Keyring, SignatureRegion, SigningKey, StreamingPackage};
+use std::io::{self, Read};
+
+// ========================================================================= //
+
+/// A reader that doesn't implement `Seek`, and that returns at most a few
+/// bytes at a time, like a slow pipe.
+struct PipeReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Read for PipeReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(7);
+        self.data.read(&mut buf[..len])
+    }
+}
+
+fn pipe(data: &[u8]) -> PipeReader { PipeReader { data } }
+
+// ========================================================================= //
+
+#[test]
+fn read_streaming_package() {
+    for &compressor in &["gzip", "xz", "zstd"] {
+        let package_data = build_package(compressor, None);
+        let mut package = StreamingPackage::read(pipe(&package_data))
+            .unwrap();
+        assert_eq!(package.header().package_name(), "hello");
+        {
+            let mut archive = package.read_archive().unwrap();
+            {
+                let mut file = archive.next_file().unwrap().unwrap();
+                assert_eq!(file.file_path(), "/usr/lib/hi.txt");
+                let mut contents = String::new();
+                file.read_to_string(&mut contents).unwrap();
+                assert_eq!(contents.as_str(),
+                           "Hello, \"/usr/lib/hi.txt\"!\n\
+                            Nice to meet you.\n");
+            }
+            // Leave the second file unread; `finish` should still consume
+            // and check the rest of the payload.
+        }
+        assert!(package.read_archive().is_err());
+        assert!(package.finish(&Keyring::new()).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn validate_streaming_package() {
+    let package_data = build_package("gzip", None);
+    let package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    package.validate().unwrap();
+
+    // A package whose archive has already been read can no longer be fully
+    // validated.
+    let mut package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    package.read_archive().unwrap();
+    assert!(package.validate().is_err());
+
+    // Corrupting the payload should be caught.
+    let mut corrupted = package_data.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    let package = StreamingPackage::read(pipe(&corrupted)).unwrap();
+    assert!(package.validate().is_err());
+    let package = StreamingPackage::read(pipe(&corrupted)).unwrap();
+    assert!(package.finish(&Keyring::new()).is_err());
+
+    // So should a truncated package.
+    let truncated = &package_data[..package_data.len() - 10];
+    let package = StreamingPackage::read(pipe(truncated)).unwrap();
+    assert!(package.finish(&Keyring::new()).is_err());
+}
+
+#[test]
+fn verify_streaming_signed_package() {
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let package_data = build_package("gzip", Some(signing_key));
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+
+    let package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    assert!(package.signature().has_openpgp_signatures());
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert_eq!(verifications[0].region(), SignatureRegion::Header);
+    assert_eq!(verifications[1].region(),
+               SignatureRegion::HeaderAndPayload);
+    for verification in verifications.iter() {
+        assert_eq!(verification.key_id(), Some(RSA_KEY_ID));
+        assert!(verification.is_verified());
+    }
+
+    // Without the key, the signatures can't be verified.
+    let package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    let verifications = package.finish(&Keyring::new()).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert!(verifications.iter().all(|v| !v.is_verified()));
+
+    // Corrupting the end of the payload should invalidate the signature.
+    let mut corrupted = package_data;
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    let package = StreamingPackage::read(pipe(&corrupted)).unwrap();
+    assert!(package.finish(&keyring).is_err());
+}
+
+#[test]
+fn stream_package_with_unsupported_signature() {
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let mut package_data = build_package("gzip", Some(signing_key));
+    assert_eq!(make_signatures_unsupported(&mut package_data), 2);
+    let mut keyring = Keyring::new();
+    keyring.add_keys(RSA_SECRET_KEY).unwrap();
+
+    // The package should still be readable, with both signatures reported
+    // as unsupported once the package is finished.
+    let mut package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    assert_eq!(package.header().package_name(), "hello");
+    {
+        let mut archive = package.read_archive().unwrap();
+        let file = archive.next_file().unwrap().unwrap();
+        assert_eq!(file.file_path(), "/usr/lib/hi.txt");
+    }
+    let verifications = package.finish(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert!(verifications.iter().all(|v| !v.is_supported()));
+    assert!(verifications.iter().all(|v| !v.is_verified()));
+
+    let package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    let verifications = package.validate_with_keyring(&keyring).unwrap();
+    assert_eq!(verifications.len(), 2);
+    assert!(verifications.iter().all(|v| !v.is_supported()));
+    assert!(verifications.iter().all(|v| v.key_id() == Some(RSA_KEY_ID)));
+}
+
+#[test]
+fn stream_package_with_malformed_signature() {
+    let signing_key = SigningKey::read(RSA_SECRET_KEY).unwrap();
+    let mut package_data = build_package("gzip", Some(signing_key));
+    truncate_signature(&mut package_data, SIGNATURE_TAG_RSA);
+
+    // The package can still be read, but finishing or validating it fails.
+    let mut package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    assert_eq!(package.header().package_name(), "hello");
+    package.read_archive().unwrap();
+    assert!(package.finish(&Keyring::new()).is_err());
+    let package = StreamingPackage::read(pipe(&package_data)).unwrap();
+    assert!(package.validate().is_err());
+}
+
+// ========================================================================= //
@@ -0,0 +1,137 @@
+#![cfg(unix)]
+
+extern crate rpmpkg;
+
+use rpmpkg::{FileFlags, FileInfo, Package, PackageBuilder, PackageType,
+             VerifyFlags};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::path::{Path, PathBuf};
+
+// ========================================================================= //
+
+fn make_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir()
+        .join(format!("rpmpkg-verify-{}-{}", name, std::process::id()));
+    if root.exists() {
+        fs::remove_dir_all(&root).unwrap();
+    }
+    fs::create_dir_all(root.join("etc/hello")).unwrap();
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    // Make sure that the owner of the files we create has a name under the
+    // root, so that user and group checks pass.
+    let metadata = fs::metadata(&root).unwrap();
+    fs::write(root.join("etc/passwd"),
+              format!("root:x:{}:{}::/root:/bin/sh\n",
+                      metadata.uid(),
+                      metadata.gid()))
+        .unwrap();
+    fs::write(root.join("etc/group"),
+              format!("root:x:{}:\n", metadata.gid()))
+        .unwrap();
+    root
+}
+
+fn build_package(root: &Path, contents: &[(&str, &[u8])])
+                 -> Package<Cursor<Vec<u8>>> {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    for &(path, _) in contents {
+        let metadata = fs::symlink_metadata(root.join(&path[1..])).unwrap();
+        let mut file_info = FileInfo::from_metadata(path, &metadata).unwrap();
+        if path.starts_with("/etc/") {
+            file_info = file_info.with_flags(FileFlags::CONFIG);
+        }
+        builder.add_file(file_info);
+    }
+    builder.add_file(FileInfo::symlink("/usr/bin/hi", "hello"));
+    builder.add_file(FileInfo::new("/var/log/hello.log", 0)
+                         .with_flags(FileFlags::GHOST));
+    let mut builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut index = 0;
+    while let Some(mut writer) = builder.next_file().unwrap() {
+        writer.write_all(contents[index].1).unwrap();
+        index += 1;
+    }
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+    Package::read(package_file).unwrap()
+}
+
+// ========================================================================= //
+
+#[test]
+fn verify_unmodified_files() {
+    let root = make_root("unmodified");
+    let contents: &[(&str, &[u8])] = &[
+        ("/etc/hello/hello.conf", b"greeting = hello\n"),
+        ("/usr/bin/hello", b"#!/bin/sh\necho hello\n"),
+    ];
+    for &(path, data) in contents {
+        fs::write(root.join(&path[1..]), data).unwrap();
+    }
+    symlink("hello", root.join("usr/bin/hi")).unwrap();
+    let package = build_package(&root, contents);
+
+    let results = package.header().verify_files(&root).unwrap();
+    assert_eq!(results.len(), 4);
+    for result in results.iter() {
+        assert!(result.is_ok(), "{}", result);
+    }
+    assert!(!results[2].is_missing());
+    assert_eq!(results[2].to_string(), ".........    /usr/bin/hi");
+    assert!(results[3].is_missing());
+    assert_eq!(results[3].to_string(), "missing   g /var/log/hello.log");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn verify_modified_files() {
+    let root = make_root("modified");
+    let contents: &[(&str, &[u8])] = &[
+        ("/etc/hello/hello.conf", b"greeting = hello\n"),
+        ("/usr/bin/hello", b"#!/bin/sh\necho hello\n"),
+    ];
+    for &(path, data) in contents {
+        fs::write(root.join(&path[1..]), data).unwrap();
+    }
+    symlink("goodbye", root.join("usr/bin/hi")).unwrap();
+    let package = build_package(&root, contents);
+    fs::write(root.join("etc/hello/hello.conf"), b"greeting = howdy\n")
+        .unwrap();
+    fs::remove_file(root.join("usr/bin/hello")).unwrap();
+
+    let results = package.header().verify_files(&root).unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(!results[0].is_ok());
+    assert!(results[0].failed().contains(VerifyFlags::FILEDIGEST));
+    assert!(!results[0].failed().contains(VerifyFlags::FILESIZE));
+    assert!(results[0].to_string().starts_with("..5"));
+    assert!(results[0].to_string().ends_with("  c /etc/hello/hello.conf"));
+    assert!(!results[1].is_ok());
+    assert_eq!(results[1].to_string(), "missing     /usr/bin/hello");
+    assert!(!results[2].is_ok());
+    assert_eq!(results[2].failed(), VerifyFlags::LINKTO);
+    assert_eq!(results[2].to_string(), "....L....    /usr/bin/hi");
+    assert!(results[3].is_ok());
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn verify_flags_round_trip() {
+    let mut builder = PackageBuilder::new(PackageType::Binary);
+    builder.set_package_name("hello");
+    builder.add_file(FileInfo::new("/etc/hello.conf", 0)
+                         .with_verify_flags(VerifyFlags::all() -
+                                                VerifyFlags::MTIME));
+    builder.add_file(FileInfo::new("/usr/bin/hello", 0));
+    let builder = builder.build(Cursor::new(Vec::new())).unwrap();
+    let package_file = Cursor::new(builder.finish().unwrap().into_inner());
+    let package = Package::read(package_file).unwrap();
+    let files: Vec<FileInfo> = package.header().files().collect();
+    assert_eq!(files[0].verify_flags(),
+               VerifyFlags::all() - VerifyFlags::MTIME);
+    assert_eq!(files[1].verify_flags(), VerifyFlags::all());
+}
+
+// ========================================================================= //